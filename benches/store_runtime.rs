@@ -0,0 +1,141 @@
+use std::cell::Cell;
+use std::hint::black_box;
+use std::rc::Rc;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fluxum::store::{
+    DerivedStore, EmittingStore, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl, mk_eq_fn,
+};
+
+fn emitting(rt: &mut StoreRuntimeImpl) -> StoreKey {
+    rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))))
+}
+
+// Derived store that copies the value of `dep` (+1), the usual shape of generated code
+fn derived(rt: &mut StoreRuntimeImpl, deps: &[StoreKey]) -> StoreKey {
+    let key: Rc<Cell<Option<StoreKey>>> = Rc::new(Cell::new(None));
+    let key_for_cb = key.clone();
+
+    let cb: StoreCallback = Rc::new(move |store, _sub, rt: &mut StoreEffects| {
+        let value = *rt.get_value(store).downcast_ref::<i32>().unwrap();
+        if let Some(k) = key_for_cb.get() {
+            rt.set_value(k, Box::new(value + 1));
+        }
+    });
+
+    let store = DerivedStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()), cb, deps.iter().copied(), rt);
+    let k = rt.alloc_store(Box::new(store));
+    key.set(Some(k));
+    k
+}
+
+fn write_single_subscriber(c: &mut Criterion) {
+    let mut rt = StoreRuntimeImpl::new();
+    let key = emitting(&mut rt);
+    let cb: StoreCallback = Rc::new(|_, _, _: &mut StoreEffects| {});
+    rt.subscribe(key, cb);
+
+    let mut value = 0i32;
+    c.bench_function("write_single_subscriber", |b| {
+        b.iter(|| {
+            value += 1;
+            rt.set_value(key, Box::new(black_box(value)));
+            rt.drain_notifications();
+        })
+    });
+}
+
+fn fan_out(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fan_out");
+
+    for count in [100, 10_000] {
+        let mut rt = StoreRuntimeImpl::new();
+        let source = emitting(&mut rt);
+        for _ in 0..count {
+            derived(&mut rt, &[source]);
+        }
+
+        let mut value = 0i32;
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                value += 1;
+                rt.set_value(source, Box::new(value));
+                rt.drain_notifications();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn derived_chain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("derived_chain");
+
+    for depth in [10, 500] {
+        let mut rt = StoreRuntimeImpl::new();
+        let source = emitting(&mut rt);
+        let mut last = source;
+        for _ in 0..depth {
+            last = derived(&mut rt, &[last]);
+        }
+
+        let mut value = 0i32;
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, _| {
+            b.iter(|| {
+                value += 1;
+                rt.set_value(source, Box::new(value));
+                rt.drain_notifications();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// Layers of diamonds: top → (left, right) → bottom, the bottom is the top of the next layer
+fn diamonds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("diamonds");
+
+    for layers in [10, 200] {
+        let mut rt = StoreRuntimeImpl::new();
+        let source = emitting(&mut rt);
+        let mut top = source;
+        for _ in 0..layers {
+            let left = derived(&mut rt, &[top]);
+            let right = derived(&mut rt, &[top]);
+            top = derived(&mut rt, &[left, right]);
+        }
+
+        let mut value = 0i32;
+        group.bench_with_input(BenchmarkId::from_parameter(layers), &layers, |b, _| {
+            b.iter(|| {
+                value += 1;
+                rt.set_value(source, Box::new(value));
+                rt.drain_notifications();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn subscribe_churn(c: &mut Criterion) {
+    let mut rt = StoreRuntimeImpl::new();
+    let key = emitting(&mut rt);
+    let cb: StoreCallback = Rc::new(|_, _, _: &mut StoreEffects| {});
+    let mut subs = Vec::with_capacity(1000);
+
+    c.bench_function("subscribe_churn_1000", |b| {
+        b.iter(|| {
+            for _ in 0..1000 {
+                subs.push(rt.subscribe(key, cb.clone()));
+            }
+            for sub in subs.drain(..) {
+                rt.unsubscribe(sub);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, write_single_subscriber, fan_out, derived_chain, diamonds, subscribe_churn);
+criterion_main!(benches);
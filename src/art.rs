@@ -0,0 +1,236 @@
+use std::rc::Rc;
+
+use thunderdome::{Arena, Index};
+
+pub mod instruction;
+pub mod layout;
+pub mod render_data;
+pub mod text;
+
+use instruction::{Instruction, supplied_data};
+use render_data::*;
+
+pub type NodeKey = Index;
+
+/// What the adapter creates for the node, and for containers the layout algorithm.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    Box,
+    Column,
+    Row,
+    FlowBox,
+    Grid,
+    Text,
+    Image,
+    Icon,
+}
+
+pub struct Node {
+    pub kind: NodeKind,
+    pub parent: Option<NodeKey>,
+    pub children: Vec<NodeKey>,
+    pub text: Option<Rc<str>>,     // the content of a text node
+    pub data: RenderData,
+}
+
+/// A change of the actual UI. Adapters apply the patches of a frame in order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Patch {
+    /// A new node with the default render data, at `index` among the children of `parent`.
+    Create { node: NodeKey, kind: NodeKind, parent: Option<NodeKey>, index: usize },
+    /// Remove the node with its subtree.
+    Remove { node: NodeKey },
+    Move { node: NodeKey, parent: Option<NodeKey>, index: usize },
+    /// `mask` has the DIRTY_* bits of the paint fields that changed.
+    SetPaint { node: NodeKey, mask: u64, paint: Box<Paint> },
+    SetBounds { node: NodeKey, bounds: Rect },
+}
+
+/// The render data the adapter paints.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Paint {
+    pub border: Surrounding,
+    pub border_color: Color,
+    pub background: Background,
+    pub corner_radius: Surrounding,
+    pub shadow: Option<Shadow>,
+    pub color: Option<Color>,
+    pub font: Font,
+    pub line_height: Option<Dip>,
+    pub no_select: bool,
+    pub underline: bool,
+    pub small_caps: bool,
+    pub letter_spacing: f32,
+    pub pointer_events: bool,
+    pub text: Option<Rc<str>>,
+}
+
+/// The Abstract Render Tree: the nodes of the scene with their render data, and the patches
+/// generated since the adapter took them last.
+#[derive(Default)]
+pub struct Art {
+    nodes: Arena<Node>,
+    roots: Vec<NodeKey>,
+    patches: Vec<Patch>,
+    relayout: Vec<NodeKey>,        // nodes whose layout changed since the last layout, maybe removed
+}
+
+impl Art {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a node as the last child of `parent`, a root when `None`.
+    pub fn create(&mut self, kind: NodeKind, parent: Option<NodeKey>) -> NodeKey {
+        let index = self.siblings(parent).len();
+        self.insert(kind, parent, index)
+    }
+
+    pub fn insert(&mut self, kind: NodeKind, parent: Option<NodeKey>, index: usize) -> NodeKey {
+        let data = RenderData { dirty_mask: LAYOUT_MASK | PAINT_MASK, ..RenderData::default() };
+        let node = self.nodes.insert(Node { kind, parent, children: Vec::new(), text: None, data });
+        self.siblings_mut(parent).insert(index, node);
+        self.patches.push(Patch::Create { node, kind, parent, index });
+        self.relayout.push(node);
+        node
+    }
+
+    /// Remove the node and its subtree.
+    pub fn remove(&mut self, node: NodeKey) {
+        let parent = self.node(node).parent;
+        self.siblings_mut(parent).retain(|&child| child != node);
+
+        let mut stack = vec![node];
+        while let Some(key) = stack.pop() {
+            let removed = self.nodes.remove(key).unwrap();
+            stack.extend(removed.children);
+            self.relayout.push(key);
+        }
+        self.relayout.extend(parent);
+        self.patches.push(Patch::Remove { node });
+    }
+
+    /// Move the node to `index` among the children of `parent`, the index is taken after
+    /// the node is removed from its current position.
+    pub fn move_to(&mut self, node: NodeKey, parent: Option<NodeKey>, index: usize) {
+        let current = self.node(node).parent;
+        self.siblings_mut(current).retain(|&child| child != node);
+        self.siblings_mut(parent).insert(index, node);
+        self.node_mut(node).parent = parent;
+        self.patches.push(Patch::Move { node, parent, index });
+        self.relayout.extend(current);
+        self.relayout.push(node);
+    }
+
+    /// Update the supplied render data of the node from its instructions. Only the fields
+    /// that changed are updated, their bits are set in the dirty mask and returned.
+    pub fn apply<'a>(&mut self, node: NodeKey, instructions: impl IntoIterator<Item = &'a Instruction>) -> u64 {
+        let next = supplied_data(instructions);
+        let data = &mut self.node_mut(node).data;
+        let mask = data.supplied.update(next);
+        data.dirty_mask |= mask;
+        if mask & LAYOUT_MASK != 0 {
+            self.relayout.push(node);
+        }
+        self.paint(node, mask);
+        mask
+    }
+
+    pub fn set_text(&mut self, node: NodeKey, text: &str) {
+        let target = self.node_mut(node);
+        if target.text.as_deref() == Some(text) {
+            return;
+        }
+        target.text = Some(Rc::from(text));
+        target.data.dirty_mask |= DIRTY_TEXT;
+        self.relayout.push(node);
+        self.paint(node, DIRTY_TEXT);
+    }
+
+    /// Set the bounds computed by the layout, a patch is generated only when they change.
+    pub fn set_bounds(&mut self, node: NodeKey, bounds: Rect) {
+        let derived = &mut self.node_mut(node).data.derived;
+        if derived.bounds != bounds {
+            derived.bounds = bounds;
+            self.patches.push(Patch::SetBounds { node, bounds });
+        }
+    }
+
+    /// The node has been rendered, its fields are not dirty anymore.
+    pub fn clear_dirty(&mut self, node: NodeKey) {
+        self.node_mut(node).data.dirty_mask = 0;
+    }
+
+    /// The patches generated since the last call, in order.
+    pub fn take_patches(&mut self) -> Vec<Patch> {
+        std::mem::take(&mut self.patches)
+    }
+
+    pub fn node(&self, node: NodeKey) -> &Node {
+        self.nodes.get(node).unwrap_or_else(|| panic!("ART: missing node {node:?}"))
+    }
+
+    pub fn get(&self, node: NodeKey) -> Option<&Node> {
+        self.nodes.get(node)
+    }
+
+    pub fn contains(&self, node: NodeKey) -> bool {
+        self.nodes.contains(node)
+    }
+
+    pub fn roots(&self) -> &[NodeKey] {
+        &self.roots
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn node_mut(&mut self, node: NodeKey) -> &mut Node {
+        self.nodes.get_mut(node).unwrap_or_else(|| panic!("ART: missing node {node:?}"))
+    }
+
+    fn siblings(&self, parent: Option<NodeKey>) -> &Vec<NodeKey> {
+        match parent {
+            Some(parent) => &self.node(parent).children,
+            None => &self.roots,
+        }
+    }
+
+    fn siblings_mut(&mut self, parent: Option<NodeKey>) -> &mut Vec<NodeKey> {
+        match parent {
+            Some(parent) => &mut self.node_mut(parent).children,
+            None => &mut self.roots,
+        }
+    }
+
+    // A paint patch when any of the changed fields is painted.
+    fn paint(&mut self, node: NodeKey, mask: u64) {
+        if mask & PAINT_MASK == 0 {
+            return;
+        }
+        let target = self.node(node);
+        let data = &target.data.supplied;
+        let paint = Paint {
+            border: data.border,
+            border_color: data.border_color,
+            background: data.background,
+            corner_radius: data.corner_radius,
+            shadow: data.shadow,
+            color: data.color,
+            font: data.font.clone(),
+            line_height: data.line_height,
+            no_select: data.no_select,
+            underline: data.underline,
+            small_caps: data.small_caps,
+            letter_spacing: data.letter_spacing,
+            pointer_events: data.pointer_events,
+            text: target.text.clone(),
+        };
+        self.patches.push(Patch::SetPaint { node, mask: mask & PAINT_MASK, paint: Box::new(paint) });
+    }
+}
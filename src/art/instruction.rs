@@ -0,0 +1,130 @@
+// instruction.rs
+use std::rc::Rc;
+
+use crate::art::render_data::*;
+
+/// A standard instruction with its values, the runtime form of `compiler::instruction::Instruction`.
+/// Shorthands are expanded by the compiler, `None` values are not specified and keep the value
+/// set by the instructions before.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Instruction {
+    Padding(Sides),
+    Border { sides: Sides, color: Option<Color> },
+    Margin(Sides),
+    Width(Dimension),
+    Height(Dimension),
+    Position(Point),
+    FillStrategy(FillStrategy),
+    Gap { width: Option<Dip>, height: Option<Dip> },
+    AlignSelf(Alignment),
+    AlignItems(Alignment),
+    AlignRelative(RelativeAlignment),
+    Spacing(Spacing),
+    Scroll(ScrollDirection),
+    Color(Color),
+    Background(Background),
+    CornerRadius(Sides),
+    Shadow(Shadow),
+    Font { name: Option<Rc<str>>, size: Option<f32>, weight: Option<u32>, color: Option<Color> },
+    LineHeight(Dip),
+    NoSelect,
+    TextWrap(bool),
+    Underline,
+    SmallCaps,
+    LetterSpacing(f32),
+    PointerEvents(bool),
+    GridTemplate { columns: Option<Rc<[Track]>>, rows: Option<Rc<[Track]>> },
+    GridCell(GridCell),
+}
+
+/// DIP values of the four sides, `None` => not specified.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Sides {
+    pub top: Option<Dip>,
+    pub right: Option<Dip>,
+    pub bottom: Option<Dip>,
+    pub left: Option<Dip>,
+}
+
+impl Sides {
+    pub const fn all(value: Dip) -> Self {
+        Self { top: Some(value), right: Some(value), bottom: Some(value), left: Some(value) }
+    }
+
+    fn apply(&self, to: &mut Surrounding) {
+        set(&mut to.top, self.top);
+        set(&mut to.right, self.right);
+        set(&mut to.bottom, self.bottom);
+        set(&mut to.left, self.left);
+    }
+}
+
+/// The supplied render data of a node with the given instructions, applied in order over
+/// the defaults: a later instruction overrides what an earlier one specified.
+pub fn supplied_data<'a>(instructions: impl IntoIterator<Item = &'a Instruction>) -> SuppliedData {
+    let mut data = SuppliedData::default();
+    for instruction in instructions {
+        apply(instruction, &mut data);
+    }
+    data
+}
+
+pub fn apply(instruction: &Instruction, data: &mut SuppliedData) {
+    match instruction {
+        Instruction::Padding(sides) => sides.apply(&mut data.padding),
+        Instruction::Border { sides, color } => {
+            sides.apply(&mut data.border);
+            set(&mut data.border_color, *color);
+        }
+        Instruction::Margin(sides) => sides.apply(&mut data.margin),
+        Instruction::Width(dimension) => data.width = *dimension,
+        Instruction::Height(dimension) => data.height = *dimension,
+        Instruction::Position(point) => data.position = Some(*point),
+        Instruction::FillStrategy(strategy) => data.fill_strategy = *strategy,
+        Instruction::Gap { width, height } => {
+            set(&mut data.gap.width, *width);
+            set(&mut data.gap.height, *height);
+        }
+        Instruction::AlignSelf(alignment) => align(&mut data.align_self, alignment),
+        Instruction::AlignItems(alignment) => align(&mut data.align_items, alignment),
+        Instruction::AlignRelative(alignment) => {
+            data.align_relative.horizontal = alignment.horizontal.or(data.align_relative.horizontal);
+            data.align_relative.vertical = alignment.vertical.or(data.align_relative.vertical);
+        }
+        Instruction::Spacing(spacing) => data.spacing = Some(*spacing),
+        Instruction::Scroll(direction) => data.scroll = Some(*direction),
+        Instruction::Color(color) => data.color = Some(*color),
+        Instruction::Background(background) => data.background = *background,
+        Instruction::CornerRadius(sides) => sides.apply(&mut data.corner_radius),
+        Instruction::Shadow(shadow) => data.shadow = Some(*shadow),
+        Instruction::Font { name, size, weight, color } => {
+            set(&mut data.font.name, name.clone());
+            set(&mut data.font.size, *size);
+            set(&mut data.font.weight, *weight);
+            set(&mut data.font.color, *color);
+        }
+        Instruction::LineHeight(height) => data.line_height = Some(*height),
+        Instruction::NoSelect => data.no_select = true,
+        Instruction::TextWrap(wrap) => data.text_wrap = *wrap,
+        Instruction::Underline => data.underline = true,
+        Instruction::SmallCaps => data.small_caps = true,
+        Instruction::LetterSpacing(spacing) => data.letter_spacing = *spacing,
+        Instruction::PointerEvents(enabled) => data.pointer_events = *enabled,
+        Instruction::GridTemplate { columns, rows } => {
+            set(&mut data.grid_template.columns, columns.clone());
+            set(&mut data.grid_template.rows, rows.clone());
+        }
+        Instruction::GridCell(cell) => data.grid_cell = Some(*cell),
+    }
+}
+
+fn align(to: &mut Alignment, alignment: &Alignment) {
+    to.horizontal = alignment.horizontal.or(to.horizontal);
+    to.vertical = alignment.vertical.or(to.vertical);
+}
+
+fn set<T>(to: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *to = value;
+    }
+}
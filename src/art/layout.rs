@@ -0,0 +1,598 @@
+// layout.rs
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::art::render_data::*;
+use crate::art::text::{MonospaceMeasurer, TextLayout, TextMeasurer, TextStyle};
+use crate::art::{Art, NodeKey, NodeKind};
+
+mod flow;
+mod grid;
+
+/// A size in DIP, `Dip::INFINITY` => unlimited.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Size {
+    pub width: Dip,
+    pub height: Dip,
+}
+
+impl Size {
+    pub const UNLIMITED: Size = Size { width: Dip::INFINITY, height: Dip::INFINITY };
+
+    pub fn new(width: Dip, height: Dip) -> Self {
+        Self { width, height }
+    }
+
+    fn along(axis: Axis, main: Dip, cross: Dip) -> Self {
+        match axis {
+            Axis::Width => Self { width: main, height: cross },
+            Axis::Height => Self { width: cross, height: main },
+        }
+    }
+
+    fn main(&self, axis: Axis) -> Dip {
+        match axis {
+            Axis::Width => self.width,
+            Axis::Height => self.height,
+        }
+    }
+
+    fn cross(&self, axis: Axis) -> Dip {
+        match axis {
+            Axis::Width => self.height,
+            Axis::Height => self.width,
+        }
+    }
+}
+
+/// The space proposed to a node, the key of the measurement cache.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Constraint {
+    proposed: Size,
+    stretch: Option<Axis>,
+}
+
+// A node laid out with a constraint: its size, its baseline from its top, and where its
+// children are with the constraint each of them was laid out with.
+struct Measurement {
+    constraint: Constraint,
+    size: Size,
+    baseline: Option<Dip>,
+    children: Vec<(Rect, Constraint)>,
+    text: Option<Rc<TextLayout>>,
+}
+
+// Measurements kept per node, the least recently used goes first. The measurements of the
+// parent laid out with an evicted one go too, so the children of a cached measurement are
+// always cached.
+const CACHED_MEASUREMENTS: usize = 4;
+
+/// Measures and lays out the nodes of the ART in a single pass, see `doc/40_render/layout.md`.
+///
+/// Sizes are outer sizes: margin, border and padding are counted into the size of the node.
+///
+/// Layout is incremental: the measurements of a node are cached by constraint until its
+/// layout or the layout of a node in its subtree changes. A change invalidates the
+/// measurements up to the nearest ancestor whose size and baseline do not change, that one
+/// is laid out again in the space it had.
+pub struct LayoutEngine {
+    measurer: Box<dyn TextMeasurer>,
+    cache: HashMap<NodeKey, Vec<Measurement>>,
+    committed: HashMap<NodeKey, Constraint>,   // the constraint of the bounds set on the ART
+    measured: usize,
+    font_scale: f32,                           // DIP per SP
+}
+
+impl Default for LayoutEngine {
+    fn default() -> Self {
+        Self::with_measurer(MonospaceMeasurer::default())
+    }
+}
+
+impl LayoutEngine {
+    /// An engine measuring text with `MonospaceMeasurer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_measurer(measurer: impl TextMeasurer + 'static) -> Self {
+        Self { measurer: Box::new(measurer), cache: HashMap::new(), committed: HashMap::new(), measured: 0, font_scale: 1.0 }
+    }
+
+    /// Set the DIP per SP of font sizes, the text scale of the user (1 by default). A new
+    /// scale drops every measurement, the next layout is a full one.
+    pub fn set_font_scale(&mut self, scale: f32) {
+        if scale != self.font_scale {
+            self.font_scale = scale;
+            self.cache.clear();
+            self.committed.clear();
+        }
+    }
+
+    /// Lay out the ART in the viewport. The roots are placed at the top-left corner of the
+    /// viewport, the changed bounds become `SetBounds` patches.
+    pub fn layout(&mut self, art: &mut Art, viewport: Size) {
+        let changed = std::mem::take(&mut art.relayout);
+        for key in &changed {
+            if !art.contains(*key) {
+                self.cache.remove(key);
+                self.committed.remove(key);
+            }
+        }
+
+        let mut pass = Pass {
+            art,
+            measurer: &*self.measurer,
+            font_scale: self.font_scale,
+            cache: &mut self.cache,
+            committed: &self.committed,
+            last: HashMap::new(),
+            fresh: HashSet::new(),
+        };
+
+        // the boundaries first: one whose baseline changed invalidates its ancestors up to
+        // the next boundary
+        let mut pending = BTreeMap::new();
+        for key in changed.into_iter().filter(|&key| art.contains(key)) {
+            pass.cache.remove(&key);
+            pass.invalidate(key, &mut pending);
+        }
+        let mut boundaries = Vec::new();
+        while let Some((boundary, baseline)) = pending.pop_first() {
+            let constraint = pass.committed[&boundary];
+            pass.node(boundary, constraint.proposed, constraint.stretch);
+            if pass.baseline(boundary) != baseline {
+                pass.invalidate(boundary, &mut pending);
+            }
+            boundaries.push(boundary);
+        }
+        boundaries.sort();
+        boundaries.dedup();
+
+        let roots: Vec<NodeKey> = art.roots().to_vec();
+        let mut sizes = Vec::with_capacity(roots.len());
+        for &root in &roots {
+            sizes.push(pass.node(root, viewport, None));
+        }
+        let mut fresh = pass.fresh;
+        self.measured = fresh.len();
+
+        let constraint = Constraint { proposed: viewport, stretch: None };
+        for (root, size) in roots.into_iter().zip(sizes) {
+            art.set_bounds(root, Rect { x: 0.0, y: 0.0, width: size.width, height: size.height });
+            self.commit(art, &mut fresh, root, constraint);
+        }
+        for boundary in boundaries {
+            let constraint = self.committed[&boundary];
+            self.commit(art, &mut fresh, boundary, constraint);
+        }
+    }
+
+    /// The number of nodes the last layout measured, cached measurements excluded.
+    pub fn measured(&self) -> usize {
+        self.measured
+    }
+
+    // Set the bounds of the children of a node laid out with the constraint, down to the
+    // subtrees laid out as before.
+    fn commit(&mut self, art: &mut Art, fresh: &mut HashSet<NodeKey>, node: NodeKey, constraint: Constraint) {
+        if !fresh.remove(&node) && self.committed.get(&node) == Some(&constraint) {
+            return;
+        }
+        self.committed.insert(node, constraint);
+
+        // the children of cached measurements are cached, measured again if that ever fails
+        if !self.cache.get(&node).is_some_and(|cached| cached.iter().any(|measurement| measurement.constraint == constraint)) {
+            let mut pass = Pass {
+                art,
+                measurer: &*self.measurer,
+                font_scale: self.font_scale,
+                cache: &mut self.cache,
+                committed: &self.committed,
+                last: HashMap::new(),
+                fresh: HashSet::new(),
+            };
+            pass.node(node, constraint.proposed, constraint.stretch);
+            fresh.extend(pass.fresh);
+            fresh.remove(&node);
+        }
+
+        let Some(measurement) = self.cache[&node].iter().find(|measurement| measurement.constraint == constraint) else { return };
+        if art.node(node).kind == NodeKind::Text {
+            art.node_mut(node).data.derived.text = measurement.text.clone();
+        }
+        let children: Vec<(NodeKey, Rect, Constraint)> = art.node(node).children.iter().zip(&measurement.children)
+            .map(|(&child, &(rect, constraint))| (child, rect, constraint))
+            .collect();
+
+        for (child, rect, constraint) in children {
+            art.set_bounds(child, rect);
+            self.commit(art, fresh, child, constraint);
+        }
+    }
+}
+
+struct Pass<'a> {
+    art: &'a Art,
+    measurer: &'a dyn TextMeasurer,
+    font_scale: f32,
+    cache: &'a mut HashMap<NodeKey, Vec<Measurement>>,
+    committed: &'a HashMap<NodeKey, Constraint>,
+    last: HashMap<NodeKey, Constraint>,    // the constraint of the last layout of each node
+    fresh: HashSet<NodeKey>,               // nodes measured by this pass
+}
+
+impl Pass<'_> {
+    // Drop the measurements of the ancestors of a changed node up to the nearest boundary,
+    // which is added to `pending` with its baseline before the change. The ancestors of the
+    // boundary keep the measurements laid out with the space it had.
+    fn invalidate(&mut self, key: NodeKey, pending: &mut BTreeMap<NodeKey, Option<Dip>>) {
+        let mut current = self.art.node(key).parent;
+        while let Some(node) = current {
+            if self.boundary(node) {
+                let committed = self.committed[&node];
+                let measurements = self.cache.remove(&node).unwrap_or_default();
+                let baseline = measurements.iter().find(|measurement| measurement.constraint == committed).and_then(|measurement| measurement.baseline);
+                pending.entry(node).or_insert(baseline);
+                for measurement in measurements.into_iter().filter(|measurement| measurement.constraint != committed) {
+                    self.evict(node, measurement.constraint);
+                }
+                return;
+            }
+            self.cache.remove(&node);
+            current = self.art.node(node).parent;
+        }
+    }
+
+    // The size of the node cannot change when its subtree changes: it is fixed or taken
+    // from the space it had on both axes. Grids and `resize_to_max` measure their children
+    // in other spaces first, their children are not boundaries.
+    fn boundary(&self, node: NodeKey) -> bool {
+        let Some(constraint) = self.committed.get(&node) else { return false };
+        let node = self.art.node(node);
+        if let Some(parent) = node.parent.map(|parent| self.art.node(parent)) {
+            let resized = matches!(parent.kind, NodeKind::Column | NodeKind::Row) && parent.data.supplied.fill_strategy == FillStrategy::ResizeToMax;
+            if resized || parent.kind == NodeKind::Grid {
+                return false;
+            }
+        }
+
+        let data = &node.data.supplied;
+        let fixed = |dimension: &Dimension, proposed: Dip| match dimension.value {
+            DimValue::Fixed(_) => true,
+            DimValue::Expand | DimValue::Container => proposed.is_finite(),
+            DimValue::Content => false,
+        };
+        fixed(&data.width, constraint.proposed.width) && fixed(&data.height, constraint.proposed.height)
+    }
+
+    // Drop a measurement of the node and the measurements of its parent laid out with it.
+    fn evict(&mut self, key: NodeKey, constraint: Constraint) {
+        if let Some(cached) = self.cache.get_mut(&key) {
+            cached.retain(|measurement| measurement.constraint != constraint);
+        }
+        let Some(parent) = self.art.node(key).parent else { return };
+        let Some(index) = self.art.node(parent).children.iter().position(|&child| child == key) else { return };
+        let stale: Vec<Constraint> = self.cache.get(&parent).into_iter().flatten()
+            .filter(|measurement| measurement.children.get(index).is_some_and(|&(_, child)| child == constraint))
+            .map(|measurement| measurement.constraint)
+            .collect();
+        for constraint in stale {
+            self.evict(parent, constraint);
+        }
+    }
+
+    // The outer size of the node in the proposed space, its children are placed in it.
+    // A stretched axis uses all the proposed space whatever the dimension of the node is.
+    fn node(&mut self, key: NodeKey, proposed: Size, stretch: Option<Axis>) -> Size {
+        let constraint = Constraint { proposed, stretch };
+        self.last.insert(key, constraint);
+
+        let cached = self.cache.entry(key).or_default();
+        if let Some(i) = cached.iter().position(|measurement| measurement.constraint == constraint) {
+            let measurement = cached.remove(i);
+            let size = measurement.size;
+            cached.push(measurement);
+            return size;
+        }
+
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+        let surrounding = data.surrounding();
+
+        let mut inner = Size {
+            width: inner_space(&data.width, proposed.width, surrounding.horizontal()),
+            height: inner_space(&data.height, proposed.height, surrounding.vertical()),
+        };
+        match data.scroll {
+            Some(ScrollDirection::Horizontal) => inner.width = Dip::INFINITY,
+            Some(ScrollDirection::Vertical) => inner.height = Dip::INFINITY,
+            Some(ScrollDirection::Both) => inner = Size::UNLIMITED,
+            None => (),
+        }
+
+        let (content, mut rects) = match node.kind {
+            NodeKind::Column => self.directional(key, Axis::Height, inner),
+            NodeKind::Row => self.directional(key, Axis::Width, inner),
+            NodeKind::Box => self.boxed(key, inner),
+            NodeKind::FlowBox => self.flow(key, inner),
+            NodeKind::Grid => self.grid(key, inner),
+            NodeKind::Text | NodeKind::Image | NodeKind::Icon => (Size::default(), Vec::new()),
+        };
+        let text = (node.kind == NodeKind::Text).then(|| Rc::new(self.text(key, inner)));
+        let content = text.as_ref().map_or(content, |text| Size::new(text.width, text.height));
+
+        let size = Size {
+            width: resolve(&data.width, proposed.width, content.width + surrounding.horizontal(), stretch == Some(Axis::Width)),
+            height: resolve(&data.height, proposed.height, content.height + surrounding.vertical(), stretch == Some(Axis::Height)),
+        };
+
+        // column, row and box place their children in the final content box
+        let inner = Size {
+            width: if inner.width.is_finite() { size.width - surrounding.horizontal() } else { content.width },
+            height: if inner.height.is_finite() { size.height - surrounding.vertical() } else { content.height },
+        };
+        match node.kind {
+            NodeKind::Column => self.place_directional(key, Axis::Height, &mut rects, content, inner),
+            NodeKind::Row => self.place_directional(key, Axis::Width, &mut rects, content, inner),
+            NodeKind::Box => self.place_boxed(key, &mut rects, inner),
+            _ => (),
+        }
+
+        let children: Vec<(Rect, Constraint)> = node.children.iter().zip(rects)
+            .map(|(child, rect)| (Rect { x: surrounding.left + rect.x, y: surrounding.top + rect.y, ..rect }, self.last[child]))
+            .collect();
+
+        // the baseline of a container is the baseline of its first child
+        let baseline = match &text {
+            Some(text) => text.baseline().map(|baseline| surrounding.top + baseline),
+            None => node.children.first().zip(children.first()).and_then(|(&child, (rect, _))| Some(rect.y + self.baseline(child)?)),
+        };
+
+        if let Some(oldest) = self.cache.get(&key).filter(|cached| cached.len() == CACHED_MEASUREMENTS).map(|cached| cached[0].constraint) {
+            self.evict(key, oldest);
+        }
+        self.cache.entry(key).or_default().push(Measurement { constraint, size, baseline, children, text });
+        self.fresh.insert(key);
+
+        size
+    }
+
+    fn text(&self, key: NodeKey, inner: Size) -> TextLayout {
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+        let style = TextStyle {
+            font: &data.font.name,
+            size: data.font.size * self.font_scale,
+            weight: data.font.weight,
+            small_caps: data.small_caps,
+            letter_spacing: data.letter_spacing,
+            line_height: data.line_height,
+        };
+        let wrap_width = (data.text_wrap && inner.width.is_finite()).then_some(inner.width);
+        self.measurer.measure(node.text.as_deref().unwrap_or(""), &style, wrap_width)
+    }
+
+    // The baseline of the node in its last layout, from its top.
+    fn baseline(&self, key: NodeKey) -> Option<Dip> {
+        let constraint = self.last[&key];
+        self.cache[&key].iter().find(|measurement| measurement.constraint == constraint)?.baseline
+    }
+
+    // The offsets from the top of the line putting the children aligned to the baseline on
+    // the lowest baseline, `None` for the other children. A child without a baseline is
+    // aligned by its bottom.
+    fn baselines(&self, children: &[NodeKey], heights: &[Dip], container: &Alignment) -> Vec<Option<Dip>> {
+        let baselines: Vec<Option<Dip>> = children.iter().zip(heights).map(|(&child, &height)| {
+            let alignment = align(&self.art.node(child).data.supplied.align_self, container);
+            (alignment.vertical == Some(VAlign::Baseline)).then(|| self.baseline(child).unwrap_or(height))
+        }).collect();
+
+        let lowest = baselines.iter().flatten().fold(0.0, |lowest: Dip, &baseline| lowest.max(baseline));
+        baselines.into_iter().map(|baseline| baseline.map(|baseline| lowest - baseline)).collect()
+    }
+
+    // ---------------------------------------------------------------------------
+    // Column and row
+    // ---------------------------------------------------------------------------
+
+    // The children one after the other on the main axis, the ones with an `expand` main
+    // dimension share the space the others left.
+    fn directional(&mut self, key: NodeKey, axis: Axis, inner: Size) -> (Size, Vec<Rect>) {
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+        let children = &node.children;
+        let gap = gap(data, axis) * children.len().saturating_sub(1) as Dip;
+
+        let order: Vec<usize> = match data.fill_strategy {
+            FillStrategy::ConstrainReverse => (0..children.len()).rev().collect(),
+            FillStrategy::Constrain | FillStrategy::ResizeToMax => (0..children.len()).collect(),
+        };
+
+        let mut sizes = vec![Size::default(); children.len()];
+        let mut free = inner.main(axis) - gap;
+        let mut expanding = Vec::new();
+        for i in order {
+            if dimension(&self.art.node(children[i]).data.supplied, axis).value == DimValue::Expand {
+                expanding.push(i);
+                continue;
+            }
+            sizes[i] = self.node(children[i], Size::along(axis, free.max(0.0), inner.cross(axis)), None);
+            free -= sizes[i].main(axis);
+        }
+
+        if !expanding.is_empty() {
+            let share = free.max(0.0) / expanding.len() as Dip;
+            for &i in &expanding {
+                sizes[i] = self.node(children[i], Size::along(axis, share, inner.cross(axis)), None);
+            }
+        }
+
+        // resized to the cross size of the largest child
+        if data.fill_strategy == FillStrategy::ResizeToMax {
+            let max = sizes.iter().map(|size| size.cross(axis)).fold(0.0, Dip::max);
+            for (i, &child) in children.iter().enumerate() {
+                sizes[i] = self.node(child, Size::along(axis, sizes[i].main(axis), max), Some(cross(axis)));
+            }
+        }
+
+        let mut cross_size = sizes.iter().map(|size| size.cross(axis)).fold(0.0, Dip::max);
+        if axis == Axis::Width {
+            let heights: Vec<Dip> = sizes.iter().map(|size| size.height).collect();
+            let offsets = self.baselines(children, &heights, &data.align_items);
+            for (offset, height) in offsets.into_iter().zip(heights) {
+                cross_size = cross_size.max(offset.unwrap_or(0.0) + height);
+            }
+        }
+
+        let content = Size::along(axis, sizes.iter().map(|size| size.main(axis)).sum::<Dip>() + gap, cross_size);
+        (content, sizes.into_iter().map(rect).collect())
+    }
+
+    fn place_directional(&self, key: NodeKey, axis: Axis, rects: &mut [Rect], content: Size, inner: Size) {
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+        let count = rects.len() as Dip;
+        let free = (inner.main(axis) - content.main(axis)).max(0.0);
+
+        let (mut cursor, between) = match data.spacing {
+            Some(Spacing::Between) if rects.len() > 1 => (0.0, free / (count - 1.0)),
+            Some(Spacing::Around) if !rects.is_empty() => (free / count / 2.0, free / count),
+            _ => (free * factor(&data.align_items, axis), 0.0),
+        };
+
+        let offsets = match axis {
+            Axis::Width => self.baselines(&node.children, &rects.iter().map(|rect| rect.height).collect::<Vec<_>>(), &data.align_items),
+            Axis::Height => vec![None; rects.len()],
+        };
+
+        for ((&child, rect), baseline) in node.children.iter().zip(rects).zip(offsets) {
+            let size = Size::new(rect.width, rect.height);
+            let alignment = align(&self.art.node(child).data.supplied.align_self, &data.align_items);
+            let offset = baseline.unwrap_or_else(|| (inner.cross(axis) - size.cross(axis)).max(0.0) * factor(&alignment, cross(axis)));
+            (rect.x, rect.y) = match axis {
+                Axis::Width => (cursor, offset),
+                Axis::Height => (offset, cursor),
+            };
+            cursor += size.main(axis) + gap(data, axis) + between;
+        }
+    }
+
+    // ---------------------------------------------------------------------------
+    // Box
+    // ---------------------------------------------------------------------------
+
+    // Each child in all the space of the box, at its position or aligned.
+    fn boxed(&mut self, key: NodeKey, inner: Size) -> (Size, Vec<Rect>) {
+        let children = &self.art.node(key).children;
+        let mut rects = Vec::with_capacity(children.len());
+        let mut content = Size::default();
+        for &child in children {
+            let size = self.node(child, inner, None);
+            let position = self.art.node(child).data.supplied.position.unwrap_or(Point { top: 0.0, left: 0.0 });
+            content.width = content.width.max(position.left + size.width);
+            content.height = content.height.max(position.top + size.height);
+            rects.push(rect(size));
+        }
+        (content, rects)
+    }
+
+    fn place_boxed(&self, key: NodeKey, rects: &mut [Rect], inner: Size) {
+        let node = self.art.node(key);
+        for (&child, rect) in node.children.iter().zip(rects) {
+            let supplied = &self.art.node(child).data.supplied;
+            (rect.x, rect.y) = match supplied.position {
+                Some(position) => (position.left, position.top),
+                None => {
+                    let alignment = align(&supplied.align_self, &node.data.supplied.align_items);
+                    (
+                        (inner.width - rect.width).max(0.0) * factor(&alignment, Axis::Width),
+                        (inner.height - rect.height).max(0.0) * factor(&alignment, Axis::Height),
+                    )
+                }
+            };
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Dimensions and alignment
+// ---------------------------------------------------------------------------
+
+// The space for the content of the node: its dimension in the proposed space without
+// the surrounding. Content sized nodes may use all the proposed space.
+fn inner_space(dimension: &Dimension, proposed: Dip, surrounding: Dip) -> Dip {
+    let outer = match dimension.value {
+        DimValue::Fixed(value) => value,
+        DimValue::Expand | DimValue::Container | DimValue::Content => proposed,
+    };
+    (clamp(dimension, outer) - surrounding).max(0.0)
+}
+
+// The outer size of the node, `expand` and `container` fall back to the content when the
+// proposed space is unlimited.
+fn resolve(dimension: &Dimension, proposed: Dip, content: Dip, stretch: bool) -> Dip {
+    let value = match dimension.value {
+        _ if stretch && proposed.is_finite() => proposed,
+        DimValue::Fixed(value) => value,
+        DimValue::Expand | DimValue::Container if proposed.is_finite() => proposed,
+        DimValue::Expand | DimValue::Container | DimValue::Content => content,
+    };
+    clamp(dimension, value)
+}
+
+// A rect of the size at the top-left corner.
+fn rect(size: Size) -> Rect {
+    Rect { x: 0.0, y: 0.0, width: size.width, height: size.height }
+}
+
+fn clamp(dimension: &Dimension, value: Dip) -> Dip {
+    let value = dimension.max.map_or(value, |max| value.min(max));
+    dimension.min.map_or(value, |min| value.max(min))
+}
+
+fn dimension(data: &SuppliedData, axis: Axis) -> &Dimension {
+    match axis {
+        Axis::Width => &data.width,
+        Axis::Height => &data.height,
+    }
+}
+
+fn gap(data: &SuppliedData, axis: Axis) -> Dip {
+    match axis {
+        Axis::Width => data.gap.width,
+        Axis::Height => data.gap.height,
+    }
+}
+
+fn cross(axis: Axis) -> Axis {
+    match axis {
+        Axis::Width => Axis::Height,
+        Axis::Height => Axis::Width,
+    }
+}
+
+// `align_self` has precedence over the `align_items` of the container.
+fn align(own: &Alignment, container: &Alignment) -> Alignment {
+    Alignment {
+        horizontal: own.horizontal.or(container.horizontal),
+        vertical: own.vertical.or(container.vertical),
+    }
+}
+
+// The part of the free space before the node on the axis. Outside of rows and flow boxes
+// there is no baseline, it is the top.
+fn factor(alignment: &Alignment, axis: Axis) -> Dip {
+    match axis {
+        Axis::Width => match alignment.horizontal {
+            None | Some(HAlign::Start) => 0.0,
+            Some(HAlign::Center) => 0.5,
+            Some(HAlign::End) => 1.0,
+        },
+        Axis::Height => match alignment.vertical {
+            None | Some(VAlign::Top) | Some(VAlign::Baseline) => 0.0,
+            Some(VAlign::Center) => 0.5,
+            Some(VAlign::Bottom) => 1.0,
+        },
+    }
+}
@@ -0,0 +1,67 @@
+// flow.rs
+use crate::art::NodeKey;
+use crate::art::render_data::*;
+
+use super::{Pass, Size, align, factor};
+
+impl Pass<'_> {
+    // The children in rows next to each other, a child that does not fit opens a new row
+    // below. `gap { width }` separates the children of a row, `gap { height }` the rows.
+    // Rows are aligned horizontally by `align_items`, children in their row vertically by
+    // their `align_self` or the `align_items` of the flow box, on a baseline per row.
+    pub(super) fn flow(&mut self, key: NodeKey, inner: Size) -> (Size, Vec<Rect>) {
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+
+        let mut rects = Vec::with_capacity(node.children.len());
+        let mut lines = Vec::new();        // index of the first child, width
+        let mut start = 0;
+        let mut x = 0.0;
+
+        for (i, &child) in node.children.iter().enumerate() {
+            let size = self.node(child, inner, None);
+            if i > start {
+                if x + data.gap.width + size.width > inner.width {
+                    lines.push((start, x));
+                    start = i;
+                    x = 0.0;
+                } else {
+                    x += data.gap.width;
+                }
+            }
+            rects.push(Rect { x, y: 0.0, width: size.width, height: size.height });
+            x += size.width;
+        }
+        if !rects.is_empty() {
+            lines.push((start, x));
+        }
+
+        let width = lines.iter().map(|&(_, width)| width).fold(0.0, Dip::max);
+        let available = if inner.width.is_finite() { inner.width } else { width };
+
+        let mut y = 0.0;
+        for (n, &(start, line_width)) in lines.iter().enumerate() {
+            let end = lines.get(n + 1).map_or(rects.len(), |&(next, _)| next);
+            let (rects, children) = (&mut rects[start..end], &node.children[start..end]);
+            let heights: Vec<Dip> = rects.iter().map(|rect| rect.height).collect();
+            let baselines = self.baselines(children, &heights, &data.align_items);
+            let line_height = heights.iter().zip(&baselines)
+                .map(|(height, baseline)| baseline.unwrap_or(0.0) + height)
+                .fold(0.0, Dip::max);
+
+            if n > 0 {
+                y += data.gap.height;
+            }
+            let shift = (available - line_width).max(0.0) * factor(&data.align_items, Axis::Width);
+            for ((rect, &child), baseline) in rects.iter_mut().zip(children).zip(baselines) {
+                let alignment = align(&self.art.node(child).data.supplied.align_self, &data.align_items);
+                rect.x += shift;
+                rect.y = y + baseline.unwrap_or((line_height - rect.height) * factor(&alignment, Axis::Height));
+            }
+            y += line_height;
+        }
+
+        let height = y;
+        (Size::new(width, height), rects)
+    }
+}
@@ -0,0 +1,177 @@
+// grid.rs
+use std::collections::HashSet;
+
+use crate::art::{Art, NodeKey};
+use crate::art::render_data::*;
+
+use super::{Pass, Size, align, factor};
+
+impl Pass<'_> {
+    // Children with a `grid_cell` are placed first, the others fill the free cells row by row.
+    // Columns are sized before rows, so the height of a child is measured in its columns.
+    pub(super) fn grid(&mut self, key: NodeKey, inner: Size) -> (Size, Vec<Rect>) {
+        let node = self.art.node(key);
+        let data = &node.data.supplied;
+        let cells = place(self.art, &node.children, data.grid_template.columns.len());
+
+        let column_count = cells.iter().map(|cell| cell.column + cell.column_span).max().unwrap_or(0);
+        let row_count = cells.iter().map(|cell| cell.row + cell.row_span).max().unwrap_or(0);
+        let columns = tracks(&data.grid_template.columns, column_count as usize);
+        let rows = tracks(&data.grid_template.rows, row_count as usize);
+
+        // the largest child placed only in the track, for the tracks sized to their content;
+        // the children spanning several tracks then grow them, see `grow`
+        let mut content = vec![0.0; columns.len()];
+        let mut spanning = Vec::new();
+        for (&child, cell) in node.children.iter().zip(&cells) {
+            if !sized(&columns, inner.width, cell.column, cell.column_span) {
+                continue;
+            }
+            let width = self.node(child, Size::UNLIMITED, None).width;
+            match cell.column_span {
+                1 => content[cell.column as usize] = Dip::max(content[cell.column as usize], width),
+                _ => spanning.push((cell.column, cell.column_span, width)),
+            }
+        }
+        grow(&columns, inner.width, &mut content, spanning, data.gap.width);
+        let widths = sizes(&columns, &content, inner.width, data.gap.width);
+
+        let mut content = vec![0.0; rows.len()];
+        let mut spanning = Vec::new();
+        for (&child, cell) in node.children.iter().zip(&cells) {
+            if !sized(&rows, inner.height, cell.row, cell.row_span) {
+                continue;
+            }
+            let width = span(&widths, cell.column, cell.column_span, data.gap.width).1;
+            let height = self.node(child, Size::new(width, Dip::INFINITY), None).height;
+            match cell.row_span {
+                1 => content[cell.row as usize] = Dip::max(content[cell.row as usize], height),
+                _ => spanning.push((cell.row, cell.row_span, height)),
+            }
+        }
+        grow(&rows, inner.height, &mut content, spanning, data.gap.height);
+        let heights = sizes(&rows, &content, inner.height, data.gap.height);
+
+        let mut rects = Vec::with_capacity(cells.len());
+        for (&child, cell) in node.children.iter().zip(&cells) {
+            let (x, width) = span(&widths, cell.column, cell.column_span, data.gap.width);
+            let (y, height) = span(&heights, cell.row, cell.row_span, data.gap.height);
+            let size = self.node(child, Size::new(width, height), None);
+
+            let alignment = align(&self.art.node(child).data.supplied.align_self, &data.align_items);
+            rects.push(Rect {
+                x: x + (width - size.width).max(0.0) * factor(&alignment, Axis::Width),
+                y: y + (height - size.height).max(0.0) * factor(&alignment, Axis::Height),
+                width: size.width,
+                height: size.height,
+            });
+        }
+
+        let total = |sizes: &[Dip], gap: Dip| sizes.iter().sum::<Dip>() + gap * sizes.len().saturating_sub(1) as Dip;
+        (Size::new(total(&widths, data.gap.width), total(&heights, data.gap.height)), rects)
+    }
+}
+
+// The cell of each child. Explicit cells may overlap, automatic ones take the first free
+// cell in the columns of the template, a single column without one.
+fn place(art: &Art, children: &[NodeKey], columns: usize) -> Vec<GridCell> {
+    let mut cells: Vec<Option<GridCell>> = children.iter().map(|&child| art.node(child).data.supplied.grid_cell).collect();
+    let mut taken: HashSet<(u32, u32)> = HashSet::new();
+    for cell in cells.iter().flatten() {
+        for row in cell.row..cell.row + cell.row_span.max(1) {
+            for column in cell.column..cell.column + cell.column_span.max(1) {
+                taken.insert((row, column));
+            }
+        }
+    }
+
+    let columns = columns.max(1) as u32;
+    let mut next = 0;
+    for cell in cells.iter_mut().filter(|cell| cell.is_none()) {
+        while taken.contains(&(next / columns, next % columns)) {
+            next += 1;
+        }
+        *cell = Some(GridCell { row: next / columns, column: next % columns, row_span: 1, column_span: 1 });
+        next += 1;
+    }
+
+    cells.into_iter().flatten().map(|cell| GridCell { row_span: cell.row_span.max(1), column_span: cell.column_span.max(1), ..cell }).collect()
+}
+
+// True if the track is sized to its content: content tracks, and fractions in unlimited space.
+fn measured(tracks: &[Track], available: Dip, track: usize) -> bool {
+    match tracks[track] {
+        Track::Content => true,
+        Track::Fraction(_) => !available.is_finite(),
+        Track::Fixed(_) => false,
+    }
+}
+
+// True if the child in `count` tracks from `first` sizes them: at least one of them is
+// measured, and none is a fraction that takes the free space instead.
+fn sized(tracks: &[Track], available: Dip, first: u32, count: u32) -> bool {
+    let range = first as usize..(first + count) as usize;
+    range.clone().any(|track| measured(tracks, available, track))
+        && range.into_iter().all(|track| measured(tracks, available, track) || matches!(tracks[track], Track::Fixed(_)))
+}
+
+// The measured tracks of each span grow by an equal share of what the spanning child needs
+// beyond the tracks, narrower spans first, so the wider ones see their result.
+fn grow(tracks: &[Track], available: Dip, content: &mut [Dip], mut spanning: Vec<(u32, u32, Dip)>, gap: Dip) {
+    spanning.sort_by_key(|&(_, count, _)| count);
+    for (first, count, needed) in spanning {
+        let range = first as usize..(first + count) as usize;
+        let current = range.clone()
+            .map(|track| match tracks[track] { Track::Fixed(value) => value, _ => content[track] })
+            .sum::<Dip>() + gap * (count - 1) as Dip;
+        if needed <= current {
+            continue;
+        }
+        let grown: Vec<usize> = range.filter(|&track| measured(tracks, available, track)).collect();
+        let share = (needed - current) / grown.len() as Dip;
+        for track in grown {
+            content[track] += share;
+        }
+    }
+}
+
+// The template, extended with content sized tracks up to `count`.
+fn tracks(template: &[Track], count: usize) -> Vec<Track> {
+    let mut tracks = template.to_vec();
+    if tracks.len() < count {
+        tracks.resize(count, Track::Content);
+    }
+    tracks
+}
+
+// Fixed and content tracks first, fractions share the rest by weight. In unlimited space
+// there is no rest, fractions are sized to their content.
+fn sizes(tracks: &[Track], content: &[Dip], available: Dip, gap: Dip) -> Vec<Dip> {
+    let mut sizes: Vec<Dip> = tracks.iter().zip(content).map(|(track, &content)| match track {
+        Track::Fixed(value) => *value,
+        Track::Content => content,
+        Track::Fraction(_) => if available.is_finite() { 0.0 } else { content },
+    }).collect();
+
+    if available.is_finite() {
+        let weights: f32 = tracks.iter().map(|track| match track { Track::Fraction(weight) => *weight, _ => 0.0 }).sum();
+        let gaps = gap * tracks.len().saturating_sub(1) as Dip;
+        let free = (available - gaps - sizes.iter().sum::<Dip>()).max(0.0);
+        if weights > 0.0 {
+            for (size, track) in sizes.iter_mut().zip(tracks) {
+                if let Track::Fraction(weight) = track {
+                    *size = free * weight / weights;
+                }
+            }
+        }
+    }
+    sizes
+}
+
+// The start and the size of `count` tracks from `first`, with the gaps between them.
+fn span(sizes: &[Dip], first: u32, count: u32, gap: Dip) -> (Dip, Dip) {
+    let (first, count) = (first as usize, count as usize);
+    let start = sizes[..first].iter().map(|size| size + gap).sum();
+    let size = sizes[first..first + count].iter().sum::<Dip>() + gap * (count - 1) as Dip;
+    (start, size)
+}
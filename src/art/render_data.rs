@@ -0,0 +1,365 @@
+// render_data.rs
+use std::rc::Rc;
+
+use crate::art::text::TextLayout;
+
+pub use crate::value::{Axis, FillStrategy, HAlign, RelativeH, RelativeV, ScrollDirection, Spacing, VAlign};
+
+/// Device independent pixel, every dimension of the render data is in DIP.
+pub type Dip = f32;
+
+// ---------------------------------------------------------------------------
+// Dirty mask
+// ---------------------------------------------------------------------------
+
+// One bit per field of the supplied render data, set when the field has been changed
+// since the last render.
+
+pub const DIRTY_PADDING:        u64 = 1 << 0;
+pub const DIRTY_BORDER:         u64 = 1 << 1;
+pub const DIRTY_MARGIN:         u64 = 1 << 2;
+pub const DIRTY_WIDTH:          u64 = 1 << 3;
+pub const DIRTY_HEIGHT:         u64 = 1 << 4;
+pub const DIRTY_POSITION:       u64 = 1 << 5;
+pub const DIRTY_FILL_STRATEGY:  u64 = 1 << 6;
+pub const DIRTY_GAP:            u64 = 1 << 7;
+pub const DIRTY_ALIGN_SELF:     u64 = 1 << 8;
+pub const DIRTY_ALIGN_ITEMS:    u64 = 1 << 9;
+pub const DIRTY_ALIGN_RELATIVE: u64 = 1 << 10;
+pub const DIRTY_SPACING:        u64 = 1 << 11;
+pub const DIRTY_SCROLL:         u64 = 1 << 12;
+pub const DIRTY_COLOR:          u64 = 1 << 13;
+pub const DIRTY_BACKGROUND:     u64 = 1 << 14;
+pub const DIRTY_CORNER_RADIUS:  u64 = 1 << 15;
+pub const DIRTY_SHADOW:         u64 = 1 << 16;
+pub const DIRTY_FONT:           u64 = 1 << 17;
+pub const DIRTY_LINE_HEIGHT:    u64 = 1 << 18;
+pub const DIRTY_NO_SELECT:      u64 = 1 << 19;
+pub const DIRTY_TEXT_WRAP:      u64 = 1 << 20;
+pub const DIRTY_UNDERLINE:      u64 = 1 << 21;
+pub const DIRTY_SMALL_CAPS:     u64 = 1 << 22;
+pub const DIRTY_LETTER_SPACING: u64 = 1 << 23;
+pub const DIRTY_POINTER_EVENTS: u64 = 1 << 24;
+pub const DIRTY_TEXT:           u64 = 1 << 25; // the content of a text node
+pub const DIRTY_GRID_TEMPLATE:  u64 = 1 << 26;
+pub const DIRTY_GRID_CELL:      u64 = 1 << 27;
+
+/// Fields that change the layout: the node and its container have to be laid out again.
+pub const LAYOUT_MASK: u64 = DIRTY_PADDING | DIRTY_BORDER | DIRTY_MARGIN | DIRTY_WIDTH | DIRTY_HEIGHT
+    | DIRTY_POSITION | DIRTY_FILL_STRATEGY | DIRTY_GAP | DIRTY_ALIGN_SELF | DIRTY_ALIGN_ITEMS
+    | DIRTY_ALIGN_RELATIVE | DIRTY_SPACING | DIRTY_SCROLL | DIRTY_FONT | DIRTY_LINE_HEIGHT
+    | DIRTY_TEXT_WRAP | DIRTY_SMALL_CAPS | DIRTY_LETTER_SPACING | DIRTY_TEXT | DIRTY_GRID_TEMPLATE
+    | DIRTY_GRID_CELL;
+
+/// Fields the adapter paints, a change generates a `Patch::SetPaint`.
+pub const PAINT_MASK: u64 = DIRTY_BORDER | DIRTY_COLOR | DIRTY_BACKGROUND | DIRTY_CORNER_RADIUS
+    | DIRTY_SHADOW | DIRTY_FONT | DIRTY_LINE_HEIGHT | DIRTY_NO_SELECT | DIRTY_UNDERLINE
+    | DIRTY_SMALL_CAPS | DIRTY_LETTER_SPACING | DIRTY_POINTER_EVENTS | DIRTY_TEXT;
+
+// ---------------------------------------------------------------------------
+// Values
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Surrounding {
+    pub top: Dip,
+    pub right: Dip,
+    pub bottom: Dip,
+    pub left: Dip,
+}
+
+impl Surrounding {
+    pub const fn all(value: Dip) -> Self {
+        Self { top: value, right: value, bottom: value, left: value }
+    }
+
+    pub fn horizontal(&self) -> Dip {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> Dip {
+        self.top + self.bottom
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub opacity: u8,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::rgba(0);
+    pub const BLACK: Color = Color::rgba(0x0000_00ff);
+
+    /// `0xRRGGBBAA`
+    pub const fn rgba(rgba: u32) -> Self {
+        let [red, green, blue, opacity] = rgba.to_be_bytes();
+        Self { red, green, blue, opacity }
+    }
+
+    /// `0xRRGGBB` with an opacity between 0 and 1.
+    pub fn rgb(rgb: u32, opacity: f32) -> Self {
+        let [_, red, green, blue] = rgb.to_be_bytes();
+        Self { red, green, blue, opacity: (opacity.clamp(0.0, 1.0) * 255.0).round() as u8 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DimValue {
+    Fixed(Dip),
+    Expand,
+    Container,
+    Content,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dimension {
+    pub value: DimValue,
+    pub min: Option<Dip>,
+    pub max: Option<Dip>,
+}
+
+impl Default for Dimension {
+    fn default() -> Self {
+        Self { value: DimValue::Content, min: None, max: None }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Point {
+    pub top: Dip,
+    pub left: Dip,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Gap {
+    pub width: Dip,
+    pub height: Dip,
+}
+
+/// `None` => not specified, `align_self` falls back to the `align_items` of the container,
+/// that one to the start.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Alignment {
+    pub horizontal: Option<HAlign>,
+    pub vertical: Option<VAlign>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct RelativeAlignment {
+    pub horizontal: Option<RelativeH>,
+    pub vertical: Option<RelativeV>,
+}
+
+/// The size of a grid column or row.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Track {
+    Fixed(Dip),
+    Content,                       // the largest child placed only in this track
+    Fraction(f32),                 // a weight of the space the other tracks left
+}
+
+/// Tracks missing from the template are sized to their content.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct GridTemplate {
+    pub columns: Rc<[Track]>,
+    pub rows: Rc<[Track]>,
+}
+
+/// The tracks a grid child covers, indices start at 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GridCell {
+    pub row: u32,
+    pub column: u32,
+    pub row_span: u32,
+    pub column_span: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum Background {
+    #[default]
+    None,
+    Color(Color),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Shadow {
+    pub color: Color,
+    pub offset_x: Dip,
+    pub offset_y: Dip,
+    pub deviation: Dip,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Font {
+    pub name: Rc<str>,             // empty => the default font of the adapter
+    pub size: f32,                 // SP
+    pub weight: u32,
+    pub color: Color,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self { name: Rc::from(""), size: 16.0, weight: 400, color: Color::BLACK }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Rect {
+    pub x: Dip,
+    pub y: Dip,
+    pub width: Dip,
+    pub height: Dip,
+}
+
+// ---------------------------------------------------------------------------
+// Render data
+// ---------------------------------------------------------------------------
+
+/// The render data of a node: what the instructions supply and what the layout derives.
+#[derive(Clone, Default, Debug)]
+pub struct RenderData {
+    pub dirty_mask: u64,           // DIRTY_* bits of the fields changed since the last render
+    pub supplied: SuppliedData,
+    pub derived: DerivedData,
+}
+
+/// Set by the instruction applier, each field is the result of the instructions of the
+/// node applied in order over the defaults.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SuppliedData {
+    pub padding: Surrounding,
+    pub border: Surrounding,
+    pub border_color: Color,
+    pub margin: Surrounding,
+    pub width: Dimension,
+    pub height: Dimension,
+    pub position: Option<Point>,
+    pub fill_strategy: FillStrategy,
+    pub gap: Gap,
+    pub align_self: Alignment,
+    pub align_items: Alignment,
+    pub align_relative: RelativeAlignment,
+    pub spacing: Option<Spacing>,
+    pub scroll: Option<ScrollDirection>,
+    pub color: Option<Color>,
+    pub background: Background,
+    pub corner_radius: Surrounding,  // top-left, top-right, bottom-right, bottom-left
+    pub shadow: Option<Shadow>,
+    pub font: Font,
+    pub line_height: Option<Dip>,    // None => from the font
+    pub no_select: bool,
+    pub text_wrap: bool,
+    pub underline: bool,
+    pub small_caps: bool,
+    pub letter_spacing: f32,
+    pub pointer_events: bool,
+    pub grid_template: GridTemplate,
+    pub grid_cell: Option<GridCell>,   // None => the next free cell
+}
+
+impl Default for SuppliedData {
+    fn default() -> Self {
+        Self {
+            padding: Surrounding::default(),
+            border: Surrounding::default(),
+            border_color: Color::BLACK,
+            margin: Surrounding::default(),
+            width: Dimension::default(),
+            height: Dimension::default(),
+            position: None,
+            fill_strategy: FillStrategy::default(),
+            gap: Gap::default(),
+            align_self: Alignment::default(),
+            align_items: Alignment::default(),
+            align_relative: RelativeAlignment::default(),
+            spacing: None,
+            scroll: None,
+            color: None,
+            background: Background::None,
+            corner_radius: Surrounding::default(),
+            shadow: None,
+            font: Font::default(),
+            line_height: None,
+            no_select: false,
+            text_wrap: true,
+            underline: false,
+            small_caps: false,
+            letter_spacing: 0.0,
+            pointer_events: true,
+            grid_template: GridTemplate::default(),
+            grid_cell: None,
+        }
+    }
+}
+
+impl SuppliedData {
+    /// Replace the fields that differ from `next`, returns the DIRTY_* bits of those.
+    pub fn update(&mut self, next: SuppliedData) -> u64 {
+        let mut mask = 0;
+
+        macro_rules! fields {
+            ($($field:ident => $bit:expr,)*) => {
+                $(
+                    if self.$field != next.$field {
+                        self.$field = next.$field;
+                        mask |= $bit;
+                    }
+                )*
+            };
+        }
+
+        fields! {
+            padding => DIRTY_PADDING,
+            border => DIRTY_BORDER,
+            border_color => DIRTY_BORDER,
+            margin => DIRTY_MARGIN,
+            width => DIRTY_WIDTH,
+            height => DIRTY_HEIGHT,
+            position => DIRTY_POSITION,
+            fill_strategy => DIRTY_FILL_STRATEGY,
+            gap => DIRTY_GAP,
+            align_self => DIRTY_ALIGN_SELF,
+            align_items => DIRTY_ALIGN_ITEMS,
+            align_relative => DIRTY_ALIGN_RELATIVE,
+            spacing => DIRTY_SPACING,
+            scroll => DIRTY_SCROLL,
+            color => DIRTY_COLOR,
+            background => DIRTY_BACKGROUND,
+            corner_radius => DIRTY_CORNER_RADIUS,
+            shadow => DIRTY_SHADOW,
+            font => DIRTY_FONT,
+            line_height => DIRTY_LINE_HEIGHT,
+            no_select => DIRTY_NO_SELECT,
+            text_wrap => DIRTY_TEXT_WRAP,
+            underline => DIRTY_UNDERLINE,
+            small_caps => DIRTY_SMALL_CAPS,
+            letter_spacing => DIRTY_LETTER_SPACING,
+            pointer_events => DIRTY_POINTER_EVENTS,
+            grid_template => DIRTY_GRID_TEMPLATE,
+            grid_cell => DIRTY_GRID_CELL,
+        }
+
+        mask
+    }
+
+    /// The surrounding on each side: margin, border and padding.
+    pub fn surrounding(&self) -> Surrounding {
+        Surrounding {
+            top: self.margin.top + self.border.top + self.padding.top,
+            right: self.margin.right + self.border.right + self.padding.right,
+            bottom: self.margin.bottom + self.border.bottom + self.padding.bottom,
+            left: self.margin.left + self.border.left + self.padding.left,
+        }
+    }
+}
+
+/// Computed by the layout engine.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct DerivedData {
+    pub bounds: Rect,              // margin included, relative to the top-left of the parent's bounds
+    pub text: Option<Rc<TextLayout>>,  // the lines of a text node, relative to its content box
+}
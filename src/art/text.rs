@@ -0,0 +1,136 @@
+// text.rs
+use std::ops::Range;
+
+use crate::art::render_data::{Dip, Rect};
+
+/// What the size of a text depends on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextStyle<'a> {
+    pub font: &'a str,             // empty => the default font
+    pub size: Dip,                 // the font size (SP) times the font scale of the engine
+    pub weight: u32,
+    pub small_caps: bool,
+    pub letter_spacing: f32,
+    pub line_height: Option<Dip>,  // None => from the font
+}
+
+/// A line of a laid out text.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LineBox {
+    pub text: Range<usize>,        // bytes of the text, without the whitespace at the break
+    pub bounds: Rect,              // relative to the top-left corner of the text
+    pub baseline: Dip,             // from the top of the text
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct TextLayout {
+    pub lines: Vec<LineBox>,
+    pub width: Dip,
+    pub height: Dip,
+}
+
+impl TextLayout {
+    /// The baseline of the first line, from the top of the text.
+    pub fn baseline(&self) -> Option<Dip> {
+        self.lines.first().map(|line| line.baseline)
+    }
+}
+
+/// Breaks a text into lines and measures them. The layout engine depends on it for the
+/// intrinsic size and the baseline of text nodes, so the result must be the same for the
+/// same input.
+pub trait TextMeasurer {
+    /// Lines are broken at `\n`, and at whitespace to fit `wrap_width` when there is one.
+    fn measure(&self, text: &str, style: &TextStyle, wrap_width: Option<Dip>) -> TextLayout;
+}
+
+/// Fixed metrics in em, the same for every font and weight: each character has the same
+/// advance, small caps included. Deterministic on every platform, for tests and headless rendering.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MonospaceMeasurer {
+    pub advance: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_height: f32,          // when the style has none
+}
+
+impl Default for MonospaceMeasurer {
+    fn default() -> Self {
+        Self { advance: 0.5, ascent: 0.75, descent: 0.25, line_height: 1.25 }
+    }
+}
+
+impl TextMeasurer for MonospaceMeasurer {
+    fn measure(&self, text: &str, style: &TextStyle, wrap_width: Option<Dip>) -> TextLayout {
+        if text.is_empty() {
+            return TextLayout::default();
+        }
+
+        let advance = style.size * self.advance + style.letter_spacing;
+        let line_height = style.line_height.unwrap_or(style.size * self.line_height);
+        // the half of the leading is above the ascent
+        let baseline = (line_height - style.size * (self.ascent + self.descent)) / 2.0 + style.size * self.ascent;
+
+        let mut layout = TextLayout::default();
+        for (n, range) in break_lines(text, advance, wrap_width).into_iter().enumerate() {
+            let width = text[range.clone()].chars().count() as Dip * advance;
+            let y = n as Dip * line_height;
+            layout.width = layout.width.max(width);
+            layout.height = y + line_height;
+            layout.lines.push(LineBox { text: range, bounds: Rect { x: 0.0, y, width, height: line_height }, baseline: y + baseline });
+        }
+        layout
+    }
+}
+
+// Greedy line breaking: a line takes as many words as fit, a word wider than the line is
+// broken between two characters.
+fn break_lines(text: &str, advance: Dip, wrap_width: Option<Dip>) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for paragraph in text.split('\n') {
+        let mut start = offset;
+        let mut count = 0;                             // characters from `start`
+        let mut space_start = start;
+        let mut after_space = None;                    // visible end, next start, characters until there
+        let mut in_space = false;
+
+        for (i, c) in paragraph.char_indices() {
+            let i = offset + i;
+            if c.is_whitespace() {
+                if !in_space {
+                    space_start = i;
+                }
+                in_space = true;
+                count += 1;
+                after_space = Some((space_start, i + c.len_utf8(), count));
+                continue;
+            }
+            in_space = false;
+
+            if let Some(width) = wrap_width && count > 0 && (count + 1) as Dip * advance > width {
+                match after_space.take() {
+                    Some((end, next, taken)) => {
+                        if end > start {
+                            lines.push(start..end);
+                        }
+                        start = next;
+                        count -= taken;
+                    }
+                    None => {
+                        lines.push(start..i);
+                        start = i;
+                        count = 0;
+                    }
+                }
+            }
+            count += 1;
+        }
+
+        let end = offset + paragraph.trim_end().len();
+        lines.push(start..end.max(start));
+        offset += paragraph.len() + 1;
+    }
+    lines
+}
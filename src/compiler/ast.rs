@@ -1,20 +1,38 @@
 // ast.rs
 use proc_macro2::Span;
-use syn::{Ident, Expr, Token};
+use syn::Ident;
 
 pub struct FragmentFile {
+    pub styles: Vec<StyleDecl>,
     pub fragments: Vec<FragmentDecl>,
 }
 
+/// A named instruction chain, `style Card = padding { 16 } .. border { Gray, 1 } .. corner_radius { 8 }`.
+/// Nodes and other styles refer to it by name in their chains.
+pub struct StyleDecl {
+    pub name: Ident,               // Card, starts with an uppercase letter
+    pub chain: Vec<Modifier>,      // instructions and style references, in order
+    pub span: Span,
+}
+
 pub struct FragmentDecl {
     pub name: Ident,
     pub params: Vec<Param>,        // label: String
+    pub slots: Vec<SlotParam>,     // slot header, after the params
     pub body: Block,               // build stmts
     pub span: Span,
 }
 
+/// A block the caller passes to the fragment. The slot named `children` receives the
+/// block of the call site, other slots are filled with `slot name { ... }`.
+pub struct SlotParam {
+    pub name: Ident,
+    pub span: Span,
+}
+
 pub struct Param {
     pub name: Ident,
+    pub kind: StoreKind,           // writable label: String, Readable when omitted
     pub ty: syn::Type,             // keep full Rust type for better checks
     pub span: Span,
 }
@@ -24,23 +42,39 @@ pub struct Block {
     pub span: Span,
 }
 
+#[allow(clippy::large_enum_variant)] // built once per macro invocation, boxing buys nothing
 pub enum BuildStmt {
     Store(StoreDecl),              // store count = 0
     Node(NodeDecl),                // column { ... } .. modifier { ... }
     If(IfStmt),                    // if { } else { }
+    Match(MatchStmt),              // match (mode) { Mode::List => { } ... }
     Let(LetStmt),                  // let x = expr
+    Provide(ProvideStmt),          // provide Theme = theme
+    Use(UseStmt),                  // use theme = Theme
+    Slot(SlotStmt),                // slot header - place the block the caller passed
     Expr(syn::Expr),               // bare expression handler, if you allow it
 }
 
 pub struct StoreDecl {
     pub kind: StoreKind,           // Const/Readable/Derived/Writable
     pub name: Ident,               // count
+    pub ty: Option<syn::Type>,     // store count: u64 = 0, None => inferred from literal
     pub init: StoreInit,           // literal/expr/derive spec
+    pub eq: Option<EqSpec>,        // .. eq { ptr }, None => macro decides
     pub span: Span,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum StoreKind { Const, Readable, Derived, Writable }
 
+pub enum EqSpec {
+    Always,                        // eq { always } -> EqStrategy::Always
+    Ptr,                           // eq { ptr } -> mk_ptr_eq_fn, Rc/Arc identity
+    Deep,                          // eq { deep } -> mk_eq_fn
+    Fingerprint,                   // eq { fingerprint } -> mk_fingerprint_fn
+    Fn(syn::Path),                 // eq { same_row } -> fn(&T, &T) -> bool wrapped into EqFn
+}
+
 pub enum StoreInit {
     Literal(syn::Expr),            // numbers/strings/arrays/styles etc.
     Derived(DerivedSpec),          // derived { uses: [...], body: Expr }
@@ -54,11 +88,18 @@ pub struct DerivedSpec {
 pub struct NodeDecl {
     pub name: Ident,               // column / button / text / SomeFragment
     pub args: Vec<NodeArg>,        // passed stores/consts/handlers
-    pub children: Option<Block>,   // nested block
+    pub children: Option<Block>,   // nested block, the `children` slot of user fragments
+    pub slots: Vec<SlotFill>,      // slot header { ... } in the nested block
     pub chain: Vec<Modifier>,      // after `..` chain
     pub span: Span,
 }
 
+pub struct SlotFill {
+    pub name: Ident,
+    pub body: Block,
+    pub span: Span,
+}
+
 pub enum NodeArg {
     // explicit forms let lowering decide which OP_ARG_* to pick
     Pass(Ident),                   // an existing store by name
@@ -76,12 +117,20 @@ pub struct EventHandler {
 
 pub enum BlockOrExpr { Block(Block), Expr(syn::Expr) }
 
+#[derive(Clone)]
 pub struct Modifier {
-    pub name: Ident,               // text_small
-    pub arg: Option<syn::Expr>,    // style { ... } or padding { 16 }
+    pub name: Ident,               // text_small, or a style: Card
+    pub args: Vec<ModifierArg>,    // padding { 16 } or padding { top: 8 left: 4 }
+}
+
+#[derive(Clone)]
+pub struct ModifierArg {
+    pub name: Option<Ident>,       // None => positional
+    pub value: syn::Expr,
 }
 
 pub struct IfStmt {
+    pub keep: bool,                // keep if { } - hide the not selected arms instead of dropping them
     pub cond: syn::Expr,
     pub then_block: Block,
     pub else_arm: Option<ElseArm>,
@@ -89,7 +138,37 @@ pub struct IfStmt {
 
 pub enum ElseArm { Block(Block), If(Box<IfStmt>) }
 
+pub struct MatchStmt {
+    pub keep: bool,                // keep match { } - as for `IfStmt`
+    pub scrutinee: syn::Expr,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
+}
+
+pub struct MatchArm {
+    pub pat: syn::Pat,
+    pub guard: Option<syn::Expr>,  // Pat if guard => { }
+    pub body: Block,
+}
+
 pub struct LetStmt {
     pub name: Ident,
     pub value: syn::Expr,
 }
+
+pub struct SlotStmt {
+    pub name: Ident,
+    pub span: Span,
+}
+
+pub struct ProvideStmt {
+    pub key: syn::Path,            // context key type, implements fir::ContextKey
+    pub store: Ident,              // the provided store
+    pub span: Span,
+}
+
+pub struct UseStmt {
+    pub name: Ident,               // local name of the context store
+    pub key: syn::Path,            // context key type
+    pub span: Span,
+}
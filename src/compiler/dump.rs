@@ -0,0 +1,136 @@
+// dump.rs
+use std::fmt::Write;
+
+use quote::ToTokens;
+
+use crate::compiler::ast::*;
+
+/// A readable dump of the AST, one statement per line, blocks indented. Used by the
+/// snapshot tests of the AST passes.
+pub fn dump_file(file: &FragmentFile) -> String {
+    let mut out = String::new();
+    for style in &file.styles {
+        writeln!(out, "style {} = {}", style.name, chain(&style.chain).trim_start_matches(" .. ")).unwrap();
+    }
+    for fragment in &file.fragments {
+        let mut params: Vec<String> = fragment.params.iter().map(|param| {
+            let kind = if param.kind == StoreKind::Writable { "writable " } else { "" };
+            format!("{kind}{}: {}", param.name, tokens(&param.ty))
+        }).collect();
+        params.extend(fragment.slots.iter().map(|slot| format!("slot {}", slot.name)));
+
+        writeln!(out, "{}({})", fragment.name, params.join(", ")).unwrap();
+        dump_block(&fragment.body, 1, &mut out);
+    }
+    out
+}
+
+fn dump_block(block: &Block, depth: usize, out: &mut String) {
+    for stmt in &block.items {
+        dump_stmt(stmt, depth, out);
+    }
+}
+
+fn line(depth: usize, text: &str, out: &mut String) {
+    writeln!(out, "{}{text}", "  ".repeat(depth)).unwrap();
+}
+
+fn dump_stmt(stmt: &BuildStmt, depth: usize, out: &mut String) {
+    match stmt {
+        BuildStmt::Store(store) => {
+            let kind = format!("{:?}", store.kind).to_lowercase();
+            let ty = store.ty.as_ref().map(|ty| format!(": {}", tokens(ty))).unwrap_or_default();
+            let init = match &store.init {
+                StoreInit::Literal(value) => tokens(value),
+                StoreInit::Derived(spec) => derived(spec),
+            };
+            line(depth, &format!("store {kind} {}{ty} = {init}", store.name), out);
+        }
+        BuildStmt::Node(node) => {
+            let args: Vec<String> = node.args.iter().map(arg).collect();
+            line(depth, &format!("{}({}){}", node.name, args.join(", "), chain(&node.chain)), out);
+            if let Some(children) = &node.children {
+                dump_block(children, depth + 1, out);
+            }
+            for fill in &node.slots {
+                line(depth + 1, &format!("slot {}", fill.name), out);
+                dump_block(&fill.body, depth + 2, out);
+            }
+        }
+        BuildStmt::If(stmt) => dump_if(stmt, "", depth, out),
+        BuildStmt::Match(stmt) => {
+            let keep = if stmt.keep { "keep " } else { "" };
+            line(depth, &format!("{keep}match ({})", tokens(&stmt.scrutinee)), out);
+            for arm in &stmt.arms {
+                let guard = arm.guard.as_ref().map(|guard| format!(" if {}", tokens(guard))).unwrap_or_default();
+                line(depth + 1, &format!("{}{guard} =>", tokens(&arm.pat)), out);
+                dump_block(&arm.body, depth + 2, out);
+            }
+        }
+        BuildStmt::Let(stmt) => line(depth, &format!("let {} = {}", stmt.name, tokens(&stmt.value)), out),
+        BuildStmt::Provide(provide) => line(depth, &format!("provide {} = {}", tokens(&provide.key), provide.store), out),
+        BuildStmt::Use(use_stmt) => line(depth, &format!("use {} = {}", use_stmt.name, tokens(&use_stmt.key)), out),
+        BuildStmt::Slot(slot) => line(depth, &format!("slot {}", slot.name), out),
+        BuildStmt::Expr(expr) => line(depth, &tokens(expr), out),
+    }
+}
+
+fn dump_if(stmt: &IfStmt, prefix: &str, depth: usize, out: &mut String) {
+    let keep = if stmt.keep { "keep " } else { "" };
+    line(depth, &format!("{prefix}{keep}if ({})", tokens(&stmt.cond)), out);
+    dump_block(&stmt.then_block, depth + 1, out);
+    match &stmt.else_arm {
+        Some(ElseArm::Block(block)) => {
+            line(depth, "else", out);
+            dump_block(block, depth + 1, out);
+        }
+        Some(ElseArm::If(stmt)) => dump_if(stmt, "else ", depth, out),
+        None => {}
+    }
+}
+
+fn arg(arg: &NodeArg) -> String {
+    match arg {
+        NodeArg::Pass(name) => name.to_string(),
+        NodeArg::Const(value) => tokens(value),
+        NodeArg::Readable(value) => format!("readable {}", tokens(value)),
+        NodeArg::Derived(spec) => derived(spec),
+        NodeArg::Writable(value) => format!("writable {}", tokens(value)),
+        NodeArg::EventHandler(handler) => {
+            let body = match &handler.body {
+                BlockOrExpr::Expr(expr) => tokens(expr),
+                BlockOrExpr::Block(block) => {
+                    let mut body = String::new();
+                    dump_block(block, 0, &mut body);
+                    body.lines().collect::<Vec<_>>().join("; ")
+                }
+            };
+            format!("{} {{ {body} }}", handler.name)
+        }
+    }
+}
+
+// ` .. name { a name: b }` for each modifier
+fn chain(chain: &[Modifier]) -> String {
+    let mut text = String::new();
+    for modifier in chain {
+        write!(text, " .. {}", modifier.name).unwrap();
+        if !modifier.args.is_empty() {
+            let args: Vec<String> = modifier.args.iter().map(|arg| match &arg.name {
+                Some(name) => format!("{name}: {}", tokens(&arg.value)),
+                None => tokens(&arg.value),
+            }).collect();
+            write!(text, " {{ {} }}", args.join(" ")).unwrap();
+        }
+    }
+    text
+}
+
+fn derived(spec: &DerivedSpec) -> String {
+    let uses: Vec<String> = spec.uses.iter().map(|name| name.to_string()).collect();
+    format!("derived [{}] {}", uses.join(", "), tokens(&spec.body))
+}
+
+fn tokens(item: &impl ToTokens) -> String {
+    item.to_token_stream().to_string()
+}
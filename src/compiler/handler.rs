@@ -0,0 +1,364 @@
+// handler.rs
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{ToTokens, format_ident};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{BinOp, Error, Expr, Ident, Result, Token, parse_quote};
+
+use crate::compiler::ast::*;
+
+/// A store an event handler can reference, as the lowering resolves it.
+pub struct HandlerStore {
+    pub index: u16,                // store index of the fragment
+    pub kind: StoreKind,
+    pub ty: Option<syn::Type>,     // None => the type could not be inferred
+}
+
+pub struct LoweredHandler {
+    pub uses: Vec<u16>,            // store indices of the fragment, `__stores` of the function
+    pub item: syn::ItemFn,         // a `fir::HandlerFn`
+}
+
+/// Rewrite the handler body into a `fir::HandlerFn` named `eh_<index>`. Store reads become
+/// typed `get_value` calls returning a clone of the value, assignments `set_value` /
+/// `update_value` calls. Macro arguments are rewritten when they parse as expressions,
+/// stores in other macro input are errors.
+pub fn lower_handler(
+    handler: &EventHandler,
+    index: usize,
+    resolve: &dyn Fn(&Ident) -> Option<HandlerStore>,
+) -> Result<LoweredHandler> {
+    let mut body: syn::Block = match &handler.body {
+        BlockOrExpr::Expr(expr) => parse_quote!({ #expr; }),
+        BlockOrExpr::Block(block) => {
+            let mut stmts = Vec::new();
+            for item in &block.items {
+                stmts.push(match item {
+                    BuildStmt::Expr(expr) => parse_quote!(#expr;),
+                    BuildStmt::Let(stmt) => {
+                        let (name, value) = (&stmt.name, &stmt.value);
+                        parse_quote!(let #name = #value;)
+                    }
+                    _ => return Err(Error::new(block.span, "event handlers may contain only expressions and `let`")),
+                });
+            }
+            syn::Block { brace_token: Default::default(), stmts }
+        }
+    };
+
+    let mut rewriter = Rewriter { resolve, uses: Vec::new(), locals: Vec::new(), errors: Vec::new() };
+    rewriter.visit_block_mut(&mut body);
+
+    if let Some(error) = rewriter.errors.into_iter().reduce(|mut all, error| { all.combine(error); all }) {
+        return Err(error);
+    }
+
+    let name = format_ident!("eh_{}", index);
+    let item = parse_quote! {
+        fn #name(__stores: &[::fluxum::store::StoreKey], __rt: &mut ::fluxum::store::StoreEffects) #body
+    };
+
+    Ok(LoweredHandler { uses: rewriter.uses, item })
+}
+
+struct Rewriter<'a> {
+    resolve: &'a dyn Fn(&Ident) -> Option<HandlerStore>,
+    uses: Vec<u16>,
+    locals: Vec<Ident>,            // handler-local bindings, they shadow stores
+    errors: Vec<Error>,
+}
+
+impl Rewriter<'_> {
+    // The store the expression names, if it is a bare store name.
+    fn store(&self, expr: &Expr) -> Option<(Ident, HandlerStore)> {
+        let Expr::Path(path) = expr else { return None };
+        let name = path.path.get_ident()?;
+        if path.qself.is_some() {
+            return None;
+        }
+        self.named(name).map(|store| (name.clone(), store))
+    }
+
+    // The store of the name, unless a local shadows it.
+    fn named(&self, name: &Ident) -> Option<HandlerStore> {
+        if self.locals.contains(name) {
+            return None;
+        }
+        (self.resolve)(name)
+    }
+
+    // Index of the store in `__stores`.
+    fn slot(&mut self, index: u16) -> usize {
+        match self.uses.iter().position(|i| *i == index) {
+            Some(slot) => slot,
+            None => {
+                self.uses.push(index);
+                self.uses.len() - 1
+            }
+        }
+    }
+
+    fn ty(&mut self, name: &Ident, store: &HandlerStore) -> Option<syn::Type> {
+        if store.ty.is_none() {
+            self.errors.push(Error::new(
+                name.span(),
+                format!("cannot infer the type of store `{name}`, declare it as `store {name}: Type = ...`"),
+            ));
+        }
+        store.ty.clone()
+    }
+
+    fn writable(&mut self, name: &Ident, store: &HandlerStore) -> bool {
+        let kind = match store.kind {
+            StoreKind::Writable => return true,
+            StoreKind::Readable => "readable",
+            StoreKind::Const => "const",
+            StoreKind::Derived => "derived",
+        };
+        self.errors.push(Error::new(name.span(), format!("cannot assign to `{name}`, it is a {kind} store")));
+        false
+    }
+
+    // `store = value`
+    fn set(&mut self, name: &Ident, store: &HandlerStore, value: &Expr) -> Option<Expr> {
+        if !self.writable(name, store) { return None; }
+        let ty = self.ty(name, store)?;
+        let slot = self.slot(store.index);
+        Some(parse_quote!({
+            let __value: ::std::boxed::Box<#ty> = ::std::boxed::Box::new(#value);
+            __rt.set_value(__stores[#slot], __value);
+        }))
+    }
+
+    // `store += value` and the other compound assignments
+    fn update(&mut self, name: &Ident, store: &HandlerStore, op: &BinOp, value: &Expr) -> Option<Expr> {
+        if !self.writable(name, store) { return None; }
+        let ty = self.ty(name, store)?;
+        let slot = self.slot(store.index);
+        Some(parse_quote!({
+            let mut __value = ::std::option::Option::Some(#value);
+            __rt.update_value(__stores[#slot], &mut |__current| {
+                if let ::std::option::Option::Some(__value) = __value.take() {
+                    *__current.downcast_mut::<#ty>().unwrap() #op __value;
+                }
+                true
+            });
+        }))
+    }
+
+    fn get(&mut self, name: &Ident, store: &HandlerStore) -> Option<Expr> {
+        let ty = self.ty(name, store)?;
+        let slot = self.slot(store.index);
+        Some(parse_quote!(::std::clone::Clone::clone(__rt.get_value(__stores[#slot]).downcast_ref::<#ty>().unwrap())))
+    }
+
+    // Format strings capture names themselves, `"{count}"` would not read the store.
+    fn check_captures(&mut self, lit: &syn::LitStr) {
+        let text = lit.value();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start + 1..];
+            if let Some(after) = rest.strip_prefix('{') {
+                rest = after;
+                continue;
+            }
+            let end = rest.find(['}', ':']).unwrap_or(rest.len());
+            if let Ok(name) = syn::parse_str::<Ident>(rest[..end].trim()) && self.named(&name).is_some() {
+                self.errors.push(Error::new(
+                    lit.span(),
+                    format!("a format string cannot capture store `{name}`, pass it as an argument"),
+                ));
+            }
+        }
+    }
+
+    // Store names in macro input that is not a list of expressions.
+    fn check_tokens(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(name) if self.named(&name).is_some() => self.errors.push(Error::new(
+                    name.span(),
+                    format!("cannot use store `{name}` in this macro, read it into a `let` first"),
+                )),
+                TokenTree::Group(group) => self.check_tokens(group.stream()),
+                _ => {}
+            }
+        }
+    }
+
+    // Assignment to a field or an element of a store, these would write through a shared reference.
+    fn check_part(&mut self, mut target: &Expr) {
+        loop {
+            target = match target {
+                Expr::Field(field) => &field.base,
+                Expr::Index(index) => &index.expr,
+                Expr::Paren(paren) => &paren.expr,
+                _ => break,
+            };
+            if let Some((name, _)) = self.store(target) {
+                self.errors.push(Error::new_spanned(
+                    target,
+                    format!("cannot assign to a part of store `{name}`, assign the whole value or use a lens store"),
+                ));
+                return;
+            }
+        }
+    }
+}
+
+fn is_compound(op: &BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::AddAssign(_) | BinOp::SubAssign(_) | BinOp::MulAssign(_) | BinOp::DivAssign(_)
+            | BinOp::RemAssign(_) | BinOp::BitXorAssign(_) | BinOp::BitAndAssign(_) | BinOp::BitOrAssign(_)
+            | BinOp::ShlAssign(_) | BinOp::ShrAssign(_)
+    )
+}
+
+impl VisitMut for Rewriter<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        let replacement = match expr {
+            Expr::Assign(assign) => {
+                self.visit_expr_mut(&mut assign.right);
+                match self.store(&assign.left) {
+                    Some((name, store)) => self.set(&name, &store, &assign.right),
+                    None => {
+                        self.check_part(&assign.left);
+                        self.visit_expr_mut(&mut assign.left);
+                        return;
+                    }
+                }
+            }
+            Expr::Binary(binary) if is_compound(&binary.op) => {
+                self.visit_expr_mut(&mut binary.right);
+                match self.store(&binary.left) {
+                    Some((name, store)) => self.update(&name, &store, &binary.op, &binary.right),
+                    None => {
+                        self.check_part(&binary.left);
+                        self.visit_expr_mut(&mut binary.left);
+                        return;
+                    }
+                }
+            }
+            Expr::Path(_) => match self.store(expr) {
+                Some((name, store)) => self.get(&name, &store),
+                None => return,
+            },
+            _ => {
+                visit_mut::visit_expr_mut(self, expr);
+                return;
+            }
+        };
+
+        // on error the expression is left as it is, the errors are reported together
+        if let Some(replacement) = replacement {
+            *expr = replacement;
+        }
+    }
+
+    fn visit_field_value_mut(&mut self, field: &mut syn::FieldValue) {
+        visit_mut::visit_field_value_mut(self, field);
+        // the shorthand `Point { x }` prints only the member, a store read needs `x: <read>`
+        if field.colon_token.is_none() && !matches!(&field.expr, Expr::Path(_)) {
+            field.colon_token = Some(Default::default());
+        }
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        let visible = self.locals.len();
+        visit_mut::visit_block_mut(self, block);
+        self.locals.truncate(visible);
+    }
+
+    fn visit_local_mut(&mut self, local: &mut syn::Local) {
+        // the initializer is evaluated before the binding shadows anything
+        if let Some(init) = &mut local.init {
+            self.visit_local_init_mut(init);
+        }
+        bindings(&local.pat, &mut self.locals);
+    }
+
+    fn visit_expr_closure_mut(&mut self, closure: &mut syn::ExprClosure) {
+        let visible = self.locals.len();
+        for input in &closure.inputs {
+            bindings(input, &mut self.locals);
+        }
+        self.visit_expr_mut(&mut closure.body);
+        self.locals.truncate(visible);
+    }
+
+    fn visit_arm_mut(&mut self, arm: &mut syn::Arm) {
+        let visible = self.locals.len();
+        bindings(&arm.pat, &mut self.locals);
+        if let Some((_, guard)) = &mut arm.guard {
+            self.visit_expr_mut(guard);
+        }
+        self.visit_expr_mut(&mut arm.body);
+        self.locals.truncate(visible);
+    }
+
+    fn visit_expr_for_loop_mut(&mut self, for_loop: &mut syn::ExprForLoop) {
+        self.visit_expr_mut(&mut for_loop.expr);
+        let visible = self.locals.len();
+        bindings(&for_loop.pat, &mut self.locals);
+        self.visit_block_mut(&mut for_loop.body);
+        self.locals.truncate(visible);
+    }
+
+    // `let` in a condition binds for the rest of the condition and the body, not the `else`
+    fn visit_expr_let_mut(&mut self, expr: &mut syn::ExprLet) {
+        self.visit_expr_mut(&mut expr.expr);
+        bindings(&expr.pat, &mut self.locals);
+    }
+
+    fn visit_expr_if_mut(&mut self, expr: &mut syn::ExprIf) {
+        let visible = self.locals.len();
+        self.visit_expr_mut(&mut expr.cond);
+        self.visit_block_mut(&mut expr.then_branch);
+        self.locals.truncate(visible);
+        if let Some((_, else_branch)) = &mut expr.else_branch {
+            self.visit_expr_mut(else_branch);
+        }
+    }
+
+    fn visit_expr_while_mut(&mut self, expr: &mut syn::ExprWhile) {
+        let visible = self.locals.len();
+        self.visit_expr_mut(&mut expr.cond);
+        self.visit_block_mut(&mut expr.body);
+        self.locals.truncate(visible);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        let Ok(mut args) = mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) else {
+            self.check_tokens(mac.tokens.clone());
+            return;
+        };
+        for arg in args.iter_mut() {
+            if let Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }) = arg {
+                self.check_captures(lit);
+            }
+            self.visit_expr_mut(arg);
+        }
+        mac.tokens = args.into_token_stream();
+    }
+}
+
+/// Identifiers bound by the pattern.
+pub fn bindings(pat: &syn::Pat, out: &mut Vec<Ident>) {
+    match pat {
+        syn::Pat::Ident(ident) => {
+            out.push(ident.ident.clone());
+            if let Some((_, pat)) = &ident.subpat {
+                bindings(pat, out);
+            }
+        }
+        syn::Pat::Type(typed) => bindings(&typed.pat, out),
+        syn::Pat::Reference(reference) => bindings(&reference.pat, out),
+        syn::Pat::Tuple(tuple) => tuple.elems.iter().for_each(|pat| bindings(pat, out)),
+        syn::Pat::TupleStruct(tuple) => tuple.elems.iter().for_each(|pat| bindings(pat, out)),
+        syn::Pat::Struct(structure) => structure.fields.iter().for_each(|field| bindings(&field.pat, out)),
+        syn::Pat::Slice(slice) => slice.elems.iter().for_each(|pat| bindings(pat, out)),
+        syn::Pat::Or(or) => or.cases.iter().for_each(|pat| bindings(pat, out)),
+        _ => {}
+    }
+}
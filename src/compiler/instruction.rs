@@ -0,0 +1,576 @@
+// instruction.rs
+use proc_macro2::Span;
+use syn::{Error, Expr, Ident, Result};
+
+use crate::compiler::ast::Modifier;
+
+// the keywords of the instructions are the values of the render data
+pub use crate::value::{Axis, FillStrategy, HAlign, RelativeH, RelativeV, ScrollDirection, Spacing, VAlign};
+
+/// A standard instruction, see `doc/10_language/standard_instructions.md`. Shorthands are
+/// expanded, values are Rust expressions whose types rustc checks in the generated code.
+pub enum Instruction {
+    Surrounding { kind: SurroundingKind, sides: Sides, color: Option<Expr> },
+    Dimension { axis: Axis, value: DimValue, min: Option<Expr>, max: Option<Expr> },
+    Position { top: Expr, left: Expr },
+    FillStrategy(FillStrategy),
+    Gap { width: Option<Expr>, height: Option<Expr> },
+    Align { target: AlignTarget, horizontal: Option<HAlign>, vertical: Option<VAlign> },
+    AlignRelative { horizontal: Option<RelativeH>, vertical: Option<RelativeV> },
+    Spacing(Spacing),
+    Scroll(ScrollDirection),
+    Color(ColorValue),
+    Background(Background),
+    CornerRadius(Sides),
+    Shadow { color: Expr, offset_x: Option<Expr>, offset_y: Option<Expr>, deviation: Option<Expr> },
+    Font { name: Option<Expr>, size: Option<Expr>, weight: Option<Expr>, color: Option<Expr> },
+    LineHeight(Expr),
+    NoSelect,
+    TextWrap(bool),
+    Underline,
+    SmallCaps,
+    LetterSpacing(Expr),
+    PointerEvents(bool),
+    GridTemplate { columns: Option<Vec<Track>>, rows: Option<Vec<Track>> },
+    GridCell { row: Expr, column: Expr, row_span: Option<Expr>, column_span: Option<Expr> },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurroundingKind { Padding, Border, Margin }
+
+/// DIP values of the four sides, `None` => not specified.
+#[derive(Default)]
+pub struct Sides {
+    pub top: Option<Expr>,
+    pub right: Option<Expr>,
+    pub bottom: Option<Expr>,
+    pub left: Option<Expr>,
+}
+
+pub enum DimValue {
+    Fixed(Expr),                   // DIP
+    Expand,
+    Container,
+    Content,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AlignTarget { SelfNode, Items }
+
+pub enum ColorValue {
+    Rgba(Expr),
+    Rgb { rgb: Expr, opacity: Expr },
+}
+
+pub enum Background {
+    Color { color: Expr, opacity: Option<Expr> },
+    Gradient(Expr),
+    Image(Expr),
+}
+
+/// A grid track: `120`, `content` or `1fr`.
+pub enum Track {
+    Fixed(Expr),                   // DIP
+    Content,
+    Fraction(f32),
+}
+
+const H_ALIGN: &[(&str, HAlign)] = &[("start", HAlign::Start), ("center", HAlign::Center), ("end", HAlign::End)];
+
+const V_ALIGN: &[(&str, VAlign)] = &[
+    ("top", VAlign::Top), ("center", VAlign::Center), ("baseline", VAlign::Baseline), ("bottom", VAlign::Bottom),
+];
+
+const SIDES: &[&str] = &["top", "right", "bottom", "left", "horizontal", "vertical"];
+
+/// Parse the instruction chain of a node. All errors of the chain are reported together:
+/// unknown instructions and arguments, missing and invalid values, and conflicting instructions.
+pub fn parse_instructions(chain: &[Modifier]) -> Result<Vec<Instruction>> {
+    let mut parser = Parser { out: Vec::new(), errors: Vec::new(), scroll: None, content: Vec::new() };
+
+    for modifier in chain {
+        parser.modifier(modifier);
+    }
+    parser.conflicts();
+
+    match parser.errors.into_iter().reduce(|mut all, error| { all.combine(error); all }) {
+        Some(error) => Err(error),
+        None => Ok(parser.out),
+    }
+}
+
+struct Parser {
+    out: Vec<Instruction>,
+    errors: Vec<Error>,
+    scroll: Option<(ScrollDirection, Span)>,
+    content: Vec<Axis>,            // dimensions sized to the content
+}
+
+impl Parser {
+    fn modifier(&mut self, m: &Modifier) {
+        let name = m.name.to_string();
+
+        if let Some(rest) = name.strip_prefix("align_self_") {
+            return self.align_shorthand(m, AlignTarget::SelfNode, rest);
+        }
+        if let Some(rest) = name.strip_prefix("align_items_") {
+            return self.align_shorthand(m, AlignTarget::Items, rest);
+        }
+
+        match name.as_str() {
+            "padding" => self.surrounding(m, SurroundingKind::Padding),
+            "border" => self.surrounding(m, SurroundingKind::Border),
+            "margin" => self.surrounding(m, SurroundingKind::Margin),
+
+            "width" => self.dimension(m, Axis::Width),
+            "height" => self.dimension(m, Axis::Height),
+            "fit_content" => self.dimensions(m, &[Axis::Width, Axis::Height], || DimValue::Content),
+            "fill_width" => self.dimensions(m, &[Axis::Width], || DimValue::Container),
+            "fill_height" => self.dimensions(m, &[Axis::Height], || DimValue::Container),
+            "fill" => self.dimensions(m, &[Axis::Width, Axis::Height], || DimValue::Container),
+            "expand" => self.dimensions(m, &[Axis::Width, Axis::Height], || DimValue::Expand),
+
+            "position" => {
+                let args = self.args(m, &["top", "left"], 0);
+                if let (Some(top), Some(left)) = (self.required(m, &args, "top"), self.required(m, &args, "left")) {
+                    self.out.push(Instruction::Position { top, left });
+                }
+            }
+
+            "fill_strategy" => {
+                let args = self.args(m, &[], 1);
+                let options = [
+                    ("constrain", FillStrategy::Constrain),
+                    ("constrain_reverse", FillStrategy::ConstrainReverse),
+                    ("resize_to_max", FillStrategy::ResizeToMax),
+                ];
+                if let Some(strategy) = self.positional_keyword(m, &args, &options) {
+                    self.out.push(Instruction::FillStrategy(strategy));
+                }
+            }
+            "constrain" => self.flag(m, Instruction::FillStrategy(FillStrategy::Constrain)),
+            "constrain_reverse" => self.flag(m, Instruction::FillStrategy(FillStrategy::ConstrainReverse)),
+            "resize_to_max" => self.flag(m, Instruction::FillStrategy(FillStrategy::ResizeToMax)),
+
+            "gap" => {
+                let args = self.args(m, &["width", "height"], 1);
+                let all = args.first();
+                let width = args.get("width").or_else(|| all.clone());
+                let height = args.get("height").or(all);
+                if width.is_none() && height.is_none() {
+                    self.error(m.name.span(), "`gap` needs `width`, `height` or a value for both");
+                }
+                self.out.push(Instruction::Gap { width, height });
+            }
+
+            "align_self" => self.align(m, AlignTarget::SelfNode),
+            "align_items" => self.align(m, AlignTarget::Items),
+            "align_relative" => {
+                let args = self.args(m, &["horizontal", "vertical"], 0);
+                let horizontal = args.get("horizontal").and_then(|value| self.keyword(&value, "horizontal", &[
+                    ("before", RelativeH::Before), ("start", RelativeH::Start), ("center", RelativeH::Center),
+                    ("end", RelativeH::End), ("after", RelativeH::After),
+                ]));
+                let vertical = args.get("vertical").and_then(|value| self.keyword(&value, "vertical", &[
+                    ("above", RelativeV::Above), ("start", RelativeV::Start), ("center", RelativeV::Center),
+                    ("end", RelativeV::End), ("below", RelativeV::Below),
+                ]));
+                self.out.push(Instruction::AlignRelative { horizontal, vertical });
+            }
+
+            "space_around" => self.flag(m, Instruction::Spacing(Spacing::Around)),
+            "space_between" => self.flag(m, Instruction::Spacing(Spacing::Between)),
+
+            "scroll" => {
+                let args = self.args(m, &[], 1);
+                let options = [
+                    ("horizontal", ScrollDirection::Horizontal),
+                    ("vertical", ScrollDirection::Vertical),
+                    ("both", ScrollDirection::Both),
+                ];
+                if let Some(direction) = self.positional_keyword(m, &args, &options) {
+                    self.scroll = Some((direction, m.name.span()));
+                    self.out.push(Instruction::Scroll(direction));
+                }
+            }
+
+            "color" => {
+                let args = self.args(m, &["rgba", "rgb", "opacity"], 0);
+                match (args.get("rgba"), args.get("rgb"), args.get("opacity")) {
+                    (Some(rgba), None, None) => self.out.push(Instruction::Color(ColorValue::Rgba(rgba))),
+                    (None, Some(rgb), Some(opacity)) => self.out.push(Instruction::Color(ColorValue::Rgb { rgb, opacity })),
+                    _ => self.error(m.name.span(), "`color` needs either `rgba`, or `rgb` and `opacity`"),
+                }
+            }
+
+            "background" => {
+                let args = self.args(m, &["color", "opacity", "gradient", "image"], 0);
+                match (args.get("color"), args.get("gradient"), args.get("image")) {
+                    (Some(color), None, None) => {
+                        self.out.push(Instruction::Background(Background::Color { color, opacity: args.get("opacity") }));
+                    }
+                    (None, Some(gradient), None) if args.get("opacity").is_none() => {
+                        self.out.push(Instruction::Background(Background::Gradient(gradient)));
+                    }
+                    (None, None, Some(image)) if args.get("opacity").is_none() => {
+                        self.out.push(Instruction::Background(Background::Image(image)));
+                    }
+                    _ => self.error(m.name.span(), "`background` needs one of `color` (with an optional `opacity`), `gradient` or `image`"),
+                }
+            }
+
+            "corner_radius" => {
+                let args = self.args(m, SIDES, 1);
+                if let Some(sides) = self.sides(m, &args) {
+                    self.out.push(Instruction::CornerRadius(sides));
+                }
+            }
+
+            "shadow" => {
+                let args = self.args(m, &["color", "offset_x", "offset_y", "deviation"], 0);
+                if let Some(color) = self.required(m, &args, "color") {
+                    self.out.push(Instruction::Shadow {
+                        color,
+                        offset_x: args.get("offset_x"),
+                        offset_y: args.get("offset_y"),
+                        deviation: args.get("deviation"),
+                    });
+                }
+            }
+
+            "font" => {
+                let args = self.args(m, &["name", "size", "weight", "color"], 0);
+                let font = Instruction::Font {
+                    name: args.get("name"),
+                    size: args.get("size"),
+                    weight: args.get("weight"),
+                    color: args.get("color"),
+                };
+                if args.named.is_empty() {
+                    self.error(m.name.span(), "`font` needs at least one of `name`, `size`, `weight`, `color`");
+                }
+                self.out.push(font);
+            }
+
+            "line_height" => {
+                if let Some(height) = self.value(m, "height") {
+                    self.out.push(Instruction::LineHeight(height));
+                }
+            }
+            "letter_spacing" => {
+                if let Some(value) = self.value(m, "value") {
+                    self.out.push(Instruction::LetterSpacing(value));
+                }
+            }
+            "text_wrap" => {
+                let args = self.args(m, &[], 1);
+                if let Some(wrap) = self.positional_keyword(m, &args, &[("none", false), ("wrap", true)]) {
+                    self.out.push(Instruction::TextWrap(wrap));
+                }
+            }
+
+            "grid_template" => {
+                let args = self.args(m, &["columns", "rows"], 0);
+                let columns = args.get("columns").and_then(|value| self.tracks(&value, "columns"));
+                let rows = args.get("rows").and_then(|value| self.tracks(&value, "rows"));
+                if args.named.is_empty() {
+                    self.error(m.name.span(), "`grid_template` needs `columns`, `rows` or both");
+                }
+                self.out.push(Instruction::GridTemplate { columns, rows });
+            }
+            "grid_cell" => {
+                let args = self.args(m, &["row", "column", "row_span", "column_span"], 0);
+                if let (Some(row), Some(column)) = (self.required(m, &args, "row"), self.required(m, &args, "column")) {
+                    self.out.push(Instruction::GridCell { row, column, row_span: args.get("row_span"), column_span: args.get("column_span") });
+                }
+            }
+
+            "no_select" => self.flag(m, Instruction::NoSelect),
+            "underline" => self.flag(m, Instruction::Underline),
+            "small_caps" => self.flag(m, Instruction::SmallCaps),
+            "no_pointer_events" => self.flag(m, Instruction::PointerEvents(false)),
+            "with_pointer_events" => self.flag(m, Instruction::PointerEvents(true)),
+
+            _ => self.error(m.name.span(), format!("unknown instruction `{name}`")),
+        }
+    }
+
+    // `padding`, `border`, `margin`
+    fn surrounding(&mut self, m: &Modifier, kind: SurroundingKind) {
+        let names: &[&str] = match kind {
+            SurroundingKind::Border => &["top", "right", "bottom", "left", "horizontal", "vertical", "color"],
+            _ => SIDES,
+        };
+        let border = matches!(kind, SurroundingKind::Border);
+        let mut args = self.args(m, names, if border { 2 } else { 1 });
+
+        // `border { Gray, 1 }`: the color, then the width of all sides
+        let mut color = args.get("color");
+        if args.positional.len() == 2 {
+            let value = args.positional.remove(0);
+            match color {
+                Some(_) => self.error(m.name.span(), format!("`{}` has a color value and a `color` argument", m.name)),
+                None => color = Some(value.clone()),
+            }
+        }
+
+        if let Some(sides) = self.sides(m, &args) {
+            self.out.push(Instruction::Surrounding { kind, sides, color });
+        }
+    }
+
+    // A value for all sides, `horizontal` / `vertical` for two, then the sides themselves,
+    // the more specific one wins.
+    fn sides(&mut self, m: &Modifier, args: &Args) -> Option<Sides> {
+        let all = args.first();
+        let horizontal = args.get("horizontal").or_else(|| all.clone());
+        let vertical = args.get("vertical").or(all);
+
+        let sides = Sides {
+            top: args.get("top").or_else(|| vertical.clone()),
+            right: args.get("right").or_else(|| horizontal.clone()),
+            bottom: args.get("bottom").or(vertical),
+            left: args.get("left").or(horizontal),
+        };
+
+        if sides.top.is_none() && sides.right.is_none() && sides.bottom.is_none() && sides.left.is_none() {
+            self.error(m.name.span(), format!("`{}` needs at least one side", m.name));
+            return None;
+        }
+        Some(sides)
+    }
+
+    fn dimension(&mut self, m: &Modifier, axis: Axis) {
+        let args = self.args(m, &["min", "max"], 1);
+        let Some(value) = args.positional.first() else {
+            self.error(m.name.span(), format!("`{}` needs a value: a DIP, `expand`, `container` or `content`", m.name));
+            return;
+        };
+        let value = match keyword(value).as_deref() {
+            Some("expand") => DimValue::Expand,
+            Some("container") => DimValue::Container,
+            Some("content") => DimValue::Content,
+            _ => DimValue::Fixed((*value).clone()),
+        };
+        if matches!(value, DimValue::Content) {
+            self.content.push(axis);
+        }
+        self.out.push(Instruction::Dimension { axis, value, min: args.get("min"), max: args.get("max") });
+    }
+
+    // Dimension shorthands.
+    fn dimensions(&mut self, m: &Modifier, axes: &[Axis], value: fn() -> DimValue) {
+        self.flag_args(m);
+        for &axis in axes {
+            let value = value();
+            if matches!(value, DimValue::Content) {
+                self.content.push(axis);
+            }
+            self.out.push(Instruction::Dimension { axis, value, min: None, max: None });
+        }
+    }
+
+    // `[120, content, 1fr, 2fr]`
+    fn tracks(&mut self, value: &Expr, what: &str) -> Option<Vec<Track>> {
+        let Expr::Array(array) = value else {
+            self.errors.push(Error::new_spanned(value, format!("`{what}` needs a list of tracks, `[120, content, 1fr]`")));
+            return None;
+        };
+
+        let mut tracks = Vec::new();
+        for element in &array.elems {
+            let track = match element {
+                _ if keyword(element).as_deref() == Some("content") => Track::Content,
+                Expr::Lit(lit) => match fraction(&lit.lit) {
+                    Some(Some(weight)) => Track::Fraction(weight),
+                    Some(None) => {
+                        self.errors.push(Error::new_spanned(element, "a fraction needs a positive weight"));
+                        continue;
+                    }
+                    None => Track::Fixed(element.clone()),
+                },
+                _ => Track::Fixed(element.clone()),
+            };
+            tracks.push(track);
+        }
+        Some(tracks)
+    }
+
+    fn align(&mut self, m: &Modifier, target: AlignTarget) {
+        let args = self.args(m, &["horizontal", "vertical"], 0);
+        let horizontal = args.get("horizontal").and_then(|value| self.keyword(&value, "horizontal", H_ALIGN));
+        let vertical = args.get("vertical").and_then(|value| self.keyword(&value, "vertical", V_ALIGN));
+        if args.named.is_empty() {
+            self.error(m.name.span(), format!("`{}` needs `horizontal`, `vertical` or both", m.name));
+        }
+        self.out.push(Instruction::Align { target, horizontal, vertical });
+    }
+
+    // `align_items_center`, `align_self_start_top`
+    fn align_shorthand(&mut self, m: &Modifier, target: AlignTarget, rest: &str) {
+        self.flag_args(m);
+
+        let (horizontal, vertical) = match rest {
+            "center" => (Some(HAlign::Center), Some(VAlign::Center)),
+            _ => match rest.split_once('_') {
+                Some((h, v)) => (lookup(H_ALIGN, h), lookup(V_ALIGN, v)),
+                None => (None, None),
+            },
+        };
+
+        match (horizontal, vertical) {
+            (Some(h), Some(v)) => self.out.push(Instruction::Align { target, horizontal: Some(h), vertical: Some(v) }),
+            _ => self.error(m.name.span(), format!("unknown instruction `{}`, expected `<horizontal>_<vertical>` after the target", m.name)),
+        }
+    }
+
+    // An instruction without arguments.
+    fn flag(&mut self, m: &Modifier, instruction: Instruction) {
+        self.flag_args(m);
+        self.out.push(instruction);
+    }
+
+    fn flag_args(&mut self, m: &Modifier) {
+        if !m.args.is_empty() {
+            self.error(m.name.span(), format!("`{}` takes no arguments", m.name));
+        }
+    }
+
+    // A single value, positional or named.
+    fn value(&mut self, m: &Modifier, name: &str) -> Option<Expr> {
+        let args = self.args(m, &[name], 1);
+        let value = args.get(name).or_else(|| args.first());
+        if value.is_none() {
+            self.error(m.name.span(), format!("`{}` needs a value", m.name));
+        }
+        value
+    }
+
+    // Check the arguments against the named ones the instruction accepts and the number of
+    // positional ones.
+    fn args<'m>(&mut self, m: &'m Modifier, names: &[&str], positional: usize) -> Args<'m> {
+        let mut args = Args { positional: Vec::new(), named: Vec::new() };
+
+        for arg in &m.args {
+            match &arg.name {
+                None => args.positional.push(&arg.value),
+                Some(name) if !names.iter().any(|n| name == n) => {
+                    let expected = match names.is_empty() {
+                        true => String::from("it takes no named arguments"),
+                        false => format!("expected {}", one_of(names)),
+                    };
+                    self.error(name.span(), format!("`{}` has no argument `{name}`, {expected}", m.name));
+                }
+                Some(name) if args.named.iter().any(|(n, _)| *n == name) => {
+                    self.error(name.span(), format!("argument `{name}` is given twice"));
+                }
+                Some(name) => args.named.push((name, &arg.value)),
+            }
+        }
+
+        if args.positional.len() > positional {
+            let message = match positional {
+                0 => format!("`{}` takes only named arguments", m.name),
+                1 => format!("`{}` takes one value", m.name),
+                n => format!("`{}` takes {n} values", m.name),
+            };
+            self.error(m.name.span(), message);
+        }
+
+        args
+    }
+
+    fn required(&mut self, m: &Modifier, args: &Args, name: &str) -> Option<Expr> {
+        let value = args.get(name);
+        if value.is_none() {
+            self.error(m.name.span(), format!("`{}` needs `{name}`", m.name));
+        }
+        value
+    }
+
+    fn positional_keyword<T: Copy>(&mut self, m: &Modifier, args: &Args, options: &[(&str, T)]) -> Option<T> {
+        match args.positional.first() {
+            Some(value) => self.keyword(value, &m.name.to_string(), options),
+            None => {
+                let names: Vec<&str> = options.iter().map(|(name, _)| *name).collect();
+                self.error(m.name.span(), format!("`{}` needs one of {}", m.name, one_of(&names)));
+                None
+            }
+        }
+    }
+
+    fn keyword<T: Copy>(&mut self, value: &Expr, what: &str, options: &[(&str, T)]) -> Option<T> {
+        let found = keyword(value).and_then(|name| lookup(options, &name));
+        if found.is_none() {
+            let names: Vec<&str> = options.iter().map(|(name, _)| *name).collect();
+            self.errors.push(Error::new_spanned(value, format!("expected {} for `{what}`", one_of(&names))));
+        }
+        found
+    }
+
+    // A scrolled axis cannot be sized to its content.
+    fn conflicts(&mut self) {
+        let Some((direction, span)) = self.scroll else { return };
+        let axes: &[(Axis, &str, &str)] = match direction {
+            ScrollDirection::Horizontal => &[(Axis::Width, "horizontal", "width")],
+            ScrollDirection::Vertical => &[(Axis::Height, "vertical", "height")],
+            ScrollDirection::Both => &[(Axis::Width, "both", "width"), (Axis::Height, "both", "height")],
+        };
+        for &(axis, scroll, dim) in axes {
+            if self.content.contains(&axis) {
+                self.error(span, format!("`scroll {{ {scroll} }}` conflicts with `{dim} {{ content }}` on the same node"));
+            }
+        }
+    }
+
+    fn error(&mut self, span: Span, message: impl Into<String>) {
+        self.errors.push(Error::new(span, message.into()));
+    }
+}
+
+struct Args<'m> {
+    positional: Vec<&'m Expr>,
+    named: Vec<(&'m Ident, &'m Expr)>,
+}
+
+impl Args<'_> {
+    fn first(&self) -> Option<Expr> {
+        self.positional.first().map(|value| (*value).clone())
+    }
+
+    fn get(&self, name: &str) -> Option<Expr> {
+        self.named.iter().find(|(n, _)| *n == name).map(|(_, value)| (*value).clone())
+    }
+}
+
+// The identifier of a single-segment path expression, `center` in `horizontal: center`.
+fn keyword(value: &Expr) -> Option<String> {
+    match value {
+        Expr::Path(path) if path.qself.is_none() => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+// The weight of `2fr` or `0.5fr`, `Some(None)` when it is not positive, `None` when the
+// literal is not a fraction.
+fn fraction(lit: &syn::Lit) -> Option<Option<f32>> {
+    let weight = match lit {
+        syn::Lit::Int(int) if int.suffix() == "fr" => int.base10_digits().parse::<f32>(),
+        syn::Lit::Float(float) if float.suffix() == "fr" => float.base10_digits().parse::<f32>(),
+        _ => return None,
+    };
+    Some(weight.ok().filter(|weight| *weight > 0.0))
+}
+
+fn lookup<T: Copy>(options: &[(&str, T)], name: &str) -> Option<T> {
+    options.iter().find(|(n, _)| *n == name).map(|(_, value)| *value)
+}
+
+fn one_of(names: &[&str]) -> String {
+    let names: Vec<String> = names.iter().map(|name| format!("`{name}`")).collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
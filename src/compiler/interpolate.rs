@@ -0,0 +1,176 @@
+// interpolate.rs
+use syn::visit::{self, Visit};
+use syn::{Error, Expr, Ident, LitStr, Result, parse_quote};
+
+use crate::compiler::ast::DerivedSpec;
+
+/// Turn a string literal with `${...}` placeholders into a derived spec computing the string
+/// with `format!`. Returns `None` if the literal has no `${`, a spec without uses if its
+/// placeholders use no store: its body is a constant.
+///
+/// - `${name}` must name a store, `${expr}` may use stores and any other Rust items,
+/// - `${price:.2}` passes the format spec after the last top-level `:` to `format!`,
+/// - `$${` is a literal `${`.
+pub fn interpolate(value: &Expr, is_store: &dyn Fn(&Ident) -> bool) -> Result<Option<DerivedSpec>> {
+    let Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }) = value else { return Ok(None) };
+
+    let text = lit.value();
+    if !text.contains("${") {
+        return Ok(None);
+    }
+
+    let mut format = String::new();
+    let mut args: Vec<Expr> = Vec::new();
+    let mut uses: Vec<Ident> = Vec::new();
+    let mut rest = text.as_str();
+
+    while let Some(start) = rest.find('$') {
+        escape(&rest[..start], &mut format);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            format.push_str("${{");
+            rest = after;
+            continue;
+        }
+        let Some(after) = rest.strip_prefix("${") else {
+            format.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let Some(end) = placeholder_end(after) else {
+            return Err(Error::new(lit.span(), "unterminated `${` in string literal"));
+        };
+        let (source, spec) = split_spec(&after[..end]);
+        rest = &after[end + 1..];
+
+        let expr: Expr = syn::parse_str(source.trim())
+            .map_err(|e| Error::new(lit.span(), format!("invalid expression in `${{{source}}}`: {e}")))?;
+
+        if let Expr::Path(path) = &expr && let Some(name) = path.path.get_ident() && !is_store(name) {
+            return Err(Error::new(lit.span(), format!("cannot find store `{name}` in this fragment")));
+        }
+
+        let mut refs = StoreRefs { is_store, found: &mut uses };
+        refs.visit_expr(&expr);
+
+        match spec {
+            Some(spec) => format.push_str(&format!("{{:{spec}}}")),
+            None => format.push_str("{}"),
+        }
+        args.push(expr);
+    }
+    escape(rest, &mut format);
+
+    let format = LitStr::new(&format, lit.span());
+    Ok(Some(DerivedSpec { uses, body: parse_quote!(::std::format!(#format #(, #args)*)) }))
+}
+
+// Escape `{` and `}` of the literal text for `format!`.
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            c => out.push(c),
+        }
+    }
+}
+
+// The index of the `}` closing the placeholder, nested braces (blocks, struct literals) included.
+fn placeholder_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Split `expr:spec` at the last `:` outside of brackets that is not part of a `::` path,
+// when what follows it is a format spec: `|a: i32| a` is a closure, not `|a` and ` i32| a`.
+fn split_spec(text: &str) -> (&str, Option<&str>) {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut split = None;
+
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b':' if depth == 0 => {
+                let path = bytes.get(i + 1) == Some(&b':') || (i > 0 && bytes[i - 1] == b':');
+                if !path { split = Some(i); }
+            }
+            _ => {}
+        }
+    }
+
+    match split {
+        Some(i) if is_format_spec(&text[i + 1..]) => (&text[..i], Some(&text[i + 1..])),
+        _ => (text, None),
+    }
+}
+
+// `[[fill]align][sign]['#']['0'][width]['.' precision][type]`, as `std::fmt` parses it.
+fn is_format_spec(spec: &str) -> bool {
+    let is_align = |c: char| matches!(c, '<' | '^' | '>');
+    let mut rest = spec;
+    let mut chars = rest.chars();
+    if let (Some(fill), Some(align)) = (chars.next(), chars.next()) && is_align(align) {
+        rest = &rest[fill.len_utf8() + 1..];
+    } else if rest.starts_with(is_align) {
+        rest = &rest[1..];
+    }
+    rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    rest = rest.strip_prefix('#').unwrap_or(rest);
+    rest = rest.strip_prefix('0').unwrap_or(rest);
+    rest = count(rest);
+    if let Some(precision) = rest.strip_prefix('.') {
+        rest = match precision.strip_prefix('*') {
+            Some(rest) => rest,
+            None if count(precision).len() < precision.len() => count(precision),
+            None => return false,
+        };
+    }
+    matches!(rest, "" | "?" | "x?" | "X?" | "o" | "x" | "X" | "b" | "e" | "E" | "p")
+}
+
+// Skip a width or a precision: an integer, or an argument `1$` / `name$`.
+fn count(text: &str) -> &str {
+    let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let name = text.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(text.len());
+    match text[name..].strip_prefix('$') {
+        Some(rest) if name > 0 => rest,
+        _ => &text[digits..],
+    }
+}
+
+/// The stores an expression uses, in order of first use.
+pub fn stores_used(expr: &Expr, is_store: &dyn Fn(&Ident) -> bool) -> Vec<Ident> {
+    let mut found = Vec::new();
+    StoreRefs { is_store, found: &mut found }.visit_expr(expr);
+    found
+}
+
+// Collects the store names an expression uses.
+struct StoreRefs<'a> {
+    is_store: &'a dyn Fn(&Ident) -> bool,
+    found: &'a mut Vec<Ident>,
+}
+
+impl<'ast> Visit<'ast> for StoreRefs<'_> {
+    fn visit_expr_path(&mut self, path: &'ast syn::ExprPath) {
+        if path.qself.is_none() && let Some(name) = path.path.get_ident() && (self.is_store)(name)
+            && !self.found.contains(name)
+        {
+            self.found.push(name.clone());
+        }
+        visit::visit_expr_path(self, path);
+    }
+}
@@ -0,0 +1,713 @@
+// lower.rs
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{Error, Ident, Result, parse_quote};
+
+use crate::compiler::ast::*;
+use crate::compiler::handler::{HandlerStore, LoweredHandler, bindings, lower_handler};
+use crate::compiler::instruction::{Instruction, parse_instructions};
+use crate::compiler::interpolate::{interpolate, stores_used};
+use crate::compiler::resolve::{Warning, resolve_file};
+use crate::compiler::style::{StyleTable, is_reference, style_table};
+use crate::compiler::surround::{CapabilityTable, surround_file};
+use crate::fir::*;
+
+/// The FIR of a fragment as the compiler builds it. Code generation turns this into
+/// a `fir::FragmentIR` static.
+pub struct LoweredFragment {
+    pub name: Ident,
+    pub node_count: u16,
+    pub ext_store_count: u16,
+    pub own_store_count: u16,
+    pub resources: Vec<LoweredResource>,
+    pub dependencies: Vec<Ident>,           // fragments instantiated by OP_BEGIN
+    pub events_handlers: Vec<LoweredHandler>,
+    pub derived_handlers: Vec<LoweredDerive>,
+    pub eq_strategies: Vec<syn::Expr>,      // `EqStrategy` expressions, `eq` of OP_READABLE / OP_WRITABLE
+    pub contexts: Vec<syn::Path>,           // context key types, `ContextDesc::of::<K>()`
+    pub slots: Vec<LoweredSlot>,            // blocks passed to slots of the children
+    pub nested: Vec<LoweredFragment>,       // branch arms and slot fills, `<name>_arm_<n>` and `<name>_slot_<n>`, also in `dependencies`
+    pub ops: Vec<u8>,
+}
+
+/// An entry of the resource table, code generation turns it into a `fir::ResourceFn`.
+pub enum LoweredResource {
+    Value(syn::Expr),                       // the value expression
+    Style(Ident),                           // the instructions of a `LoweredStyle`, shared by the nodes using it
+    Instructions(Vec<ChainPart>),           // the instruction chain of a node, in chain order
+}
+
+pub enum ChainPart {
+    Style(u32),                             // a `LoweredResource::Style` of the table
+    Inline(Vec<Instruction>),
+}
+
+/// A style of the file, code generation emits one static per style.
+pub struct LoweredStyle {
+    pub name: Ident,
+    pub instructions: Vec<Instruction>,     // references expanded
+}
+
+pub struct LoweredDerive {
+    pub uses: Vec<u16>,                     // store indices of the fragment
+    pub body: DeriveBody,
+}
+
+pub enum DeriveBody {
+    Value(syn::Expr),                       // an expression of the stores in `uses`, by name
+    Instructions(Vec<ChainPart>),           // an instruction chain whose arguments use the stores
+}
+
+pub struct LoweredSlot {
+    pub fragment: u16,                      // dependency index of the slot fill fragment
+    pub uses: Vec<u16>,                     // store indices of the fragment
+}
+
+/// The slot names of the fragments of a file by fragment name, in declaration order.
+pub type SlotSignatures = HashMap<String, Vec<Ident>>;
+
+pub fn slot_signatures(file: &FragmentFile) -> SlotSignatures {
+    file.fragments.iter()
+        .map(|f| (f.name.to_string(), f.slots.iter().map(|slot| slot.name.clone()).collect()))
+        .collect()
+}
+
+/// Resolve and lower every fragment of the file. Resolution errors of all fragments are
+/// reported together, lowering stops at the first error.
+pub fn lower_file(file: &FragmentFile) -> Result<(Vec<LoweredFragment>, Vec<Warning>)> {
+    let warnings = resolve_file(file)?;
+    let signatures = slot_signatures(file);
+    let (styles, _) = style_table(&file.styles);
+    let fragments = file.fragments.iter()
+        .map(|decl| lower_fragment_with(decl, &signatures, &styles))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((fragments, warnings))
+}
+
+/// Lower the styles of the file, all errors of the styles are reported together.
+pub fn lower_styles(file: &FragmentFile) -> Result<Vec<LoweredStyle>> {
+    let (styles, errors) = style_table(&file.styles);
+    if let Some(error) = errors.into_iter().reduce(|mut all, error| { all.combine(error); all }) {
+        return Err(error);
+    }
+    styles.iter()
+        .map(|(name, chain)| Ok(LoweredStyle { name: name.clone(), instructions: parse_instructions(chain)? }))
+        .collect()
+}
+
+/// The whole compiler pipeline: rewrite the AST with `surround_file`, then resolve and lower it.
+pub fn compile_file(file: &mut FragmentFile, capabilities: &CapabilityTable) -> Result<(Vec<LoweredFragment>, Vec<Warning>)> {
+    surround_file(file, capabilities);
+    lower_file(file)
+}
+
+/// Lower a fragment on its own, the fragments it uses are treated as having no slots and
+/// there are no styles.
+pub fn lower_fragment(decl: &FragmentDecl) -> Result<LoweredFragment> {
+    lower_fragment_with(decl, &SlotSignatures::new(), &StyleTable::default())
+}
+
+pub fn lower_fragment_with(decl: &FragmentDecl, signatures: &SlotSignatures, styles: &StyleTable) -> Result<LoweredFragment> {
+    let mut lowering = Lowering::new(decl.name.clone(), signatures, styles);
+
+    for param in &decl.params {
+        lowering.declare(&param.name, param.kind, Some(param.ty.clone()));
+    }
+    for slot in &decl.slots {
+        lowering.declare_slot(&slot.name);
+    }
+    lowering.out.ext_store_count = lowering.store_count;
+
+    lowering.block(&decl.body)?;
+
+    Ok(lowering.out)
+}
+
+struct Lowering<'a> {
+    out: LoweredFragment,
+    names: Vec<Symbol>,                     // visible stores, innermost last
+    store_count: u16,
+    signatures: &'a SlotSignatures,
+    styles: &'a StyleTable,
+    style_stores: HashMap<String, u16>,     // const stores of the styles, shared by the nodes
+    probing: bool,                          // only the used stores matter, nested blocks are not lowered
+}
+
+#[derive(Clone)]
+struct Symbol {
+    name: Ident,
+    index: u16,
+    kind: StoreKind,
+    ty: Option<syn::Type>,
+    used: Cell<bool>,                       // referenced by the lowered ops, arms capture only these
+    slot: bool,                             // the store holds a slot fill, not a value
+}
+
+impl<'a> Lowering<'a> {
+    fn new(name: Ident, signatures: &'a SlotSignatures, styles: &'a StyleTable) -> Self {
+        let mut lowering = Lowering {
+            out: LoweredFragment {
+                name,
+                node_count: 0,
+                ext_store_count: 0,
+                own_store_count: 0,
+                resources: Vec::new(),
+                dependencies: Vec::new(),
+                events_handlers: Vec::new(),
+                derived_handlers: Vec::new(),
+                eq_strategies: Vec::new(),
+                contexts: Vec::new(),
+                slots: Vec::new(),
+                nested: Vec::new(),
+                ops: Vec::new(),
+            },
+            names: Vec::new(),
+            store_count: 0,
+            signatures,
+            styles,
+            style_stores: HashMap::new(),
+            probing: false,
+        };
+        encode_op(&mut lowering.out.ops, OP_VERSION, FIR_VERSION);
+        lowering
+    }
+
+    // The fragment of a branch arm or a slot fill, its external stores are the captured ones.
+    fn nested(&self, name: &Ident, captured: &[Symbol], block: &Block, probing: bool) -> Result<Lowering<'a>> {
+        let mut lowering = Lowering::new(name.clone(), self.signatures, self.styles);
+        lowering.probing = probing;
+        for symbol in captured {
+            match symbol.slot {
+                true => lowering.declare_slot(&symbol.name),
+                false => lowering.declare(&symbol.name, symbol.kind, symbol.ty.clone()),
+            }
+        }
+        lowering.out.ext_store_count = lowering.store_count;
+        lowering.block(block)?;
+        Ok(lowering)
+    }
+
+    fn block(&mut self, block: &Block) -> Result<()> {
+        // names declared in the block are visible only in the block
+        let visible = self.names.len();
+
+        for stmt in &block.items {
+            self.stmt(stmt)?;
+        }
+
+        self.names.truncate(visible);
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &BuildStmt) -> Result<()> {
+        match stmt {
+            BuildStmt::Store(store) => self.store(store),
+            BuildStmt::Node(node) => self.node(node),
+            BuildStmt::Provide(provide) => {
+                let store = self.resolve(&provide.store)?;
+                let context = self.context(&provide.key);
+                self.op(OP_PROVIDE, provide_arg(store, context));
+                Ok(())
+            }
+            BuildStmt::Use(use_stmt) => {
+                let context = self.context(&use_stmt.key);
+                self.op(OP_USE, context as u32);
+                let key = &use_stmt.key;
+                let ty = parse_quote!(<#key as ::fluxum::fir::ContextKey>::Value);
+                self.declare(&use_stmt.name, StoreKind::Readable, Some(ty));
+                Ok(())
+            }
+            BuildStmt::Slot(slot) => {
+                let Some(symbol) = self.names.iter().rev().find(|symbol| symbol.slot && symbol.name == slot.name) else {
+                    return Err(Error::new(slot.name.span(), format!("cannot find slot `{}` in this fragment", slot.name)));
+                };
+                symbol.used.set(true);
+                self.op(OP_SLOT, symbol.index as u32);
+                Ok(())
+            }
+            BuildStmt::If(stmt) => self.if_stmt(stmt),
+            BuildStmt::Match(stmt) => self.match_stmt(stmt),
+            BuildStmt::Let(stmt) => Err(unsupported(stmt.name.span(), "`let`")),
+            BuildStmt::Expr(_) => Err(unsupported(Span::call_site(), "bare expression")),
+        }
+    }
+
+    fn store(&mut self, store: &StoreDecl) -> Result<()> {
+        if store.eq.is_some() && !matches!(store.kind, StoreKind::Readable | StoreKind::Writable) {
+            return Err(Error::new(store.span, "only readable and writable stores have an `eq` strategy"));
+        }
+
+        // a literal interpolating stores is a derived store, whatever the declared kind, one
+        // interpolating no store is formatted once
+        let mut formatted = None;
+        if let StoreInit::Literal(value) = &store.init && let Some(spec) = self.interpolate(value)? {
+            if spec.uses.is_empty() {
+                formatted = Some(spec.body);
+            } else if store.eq.is_some() {
+                return Err(Error::new(store.span, "an interpolated literal is a derived store, it has no `eq` strategy"));
+            } else {
+                let arg = self.derive(&spec)?;
+                self.op(OP_DERIVED, arg);
+                self.declare(&store.name, StoreKind::Derived, Some(parse_quote!(::std::string::String)));
+                self.out.own_store_count += 1;
+                return Ok(());
+            }
+        }
+
+        let (op, arg) = match (&store.kind, &store.init) {
+            (StoreKind::Derived, StoreInit::Derived(spec)) => (OP_DERIVED, self.derive(spec)?),
+            (StoreKind::Derived, StoreInit::Literal(_)) => {
+                return Err(Error::new(store.span, "derived store needs a derive spec"));
+            }
+            (_, StoreInit::Derived(_)) => {
+                return Err(Error::new(store.span, "only derived stores can have a derive spec"));
+            }
+            (kind, StoreInit::Literal(value)) => {
+                let resource = self.resource(formatted.as_ref().unwrap_or(value));
+                match kind {
+                    StoreKind::Const => (OP_CONST, resource),
+                    StoreKind::Readable => (OP_READABLE, store_arg(resource as u16, self.eq_strategy(store, formatted.is_some())?)),
+                    _ => (OP_WRITABLE, store_arg(resource as u16, self.eq_strategy(store, formatted.is_some())?)),
+                }
+            }
+        };
+
+        self.op(op, arg);
+        let ty = store_type(store, formatted.is_some());
+        self.declare(&store.name, store.kind, ty);
+        self.out.own_store_count += 1;
+        Ok(())
+    }
+
+    fn node(&mut self, node: &NodeDecl) -> Result<()> {
+        // the store of a style is created before the node, the first time it is used
+        let instructions = self.instructions(&node.chain)?;
+
+        let dependency = self.dependency(&node.name);
+        self.op(OP_BEGIN, dependency);
+        self.out.node_count += 1;
+
+        for arg in &node.args {
+            let (op, arg) = match arg {
+                NodeArg::Pass(name) => (OP_ARG_PASS, self.resolve(name)? as u32),
+                NodeArg::Const(value) => match self.interpolate(value)? {
+                    Some(spec) if spec.uses.is_empty() => (OP_ARG_CONST, self.resource(&spec.body)),
+                    Some(spec) => (OP_ARG_DERIVED, self.derive(&spec)?),
+                    None => (OP_ARG_CONST, self.resource(value)),
+                },
+                NodeArg::Readable(value) => (OP_ARG_READABLE, self.resource(value)),
+                NodeArg::Derived(spec) => (OP_ARG_DERIVED, self.derive(spec)?),
+                NodeArg::Writable(value) => (OP_ARG_WRITABLE, self.resource(value)),
+                NodeArg::EventHandler(handler) => (OP_ARG_EH, self.handler(handler)?),
+            };
+            self.op(op, arg);
+        }
+
+        // the blocks passed to slots are fragments of their own, the rest is inline
+        let slots = self.signatures.get(&node.name.to_string()).map_or(&[][..], Vec::as_slice);
+        if let Some(fill) = node.slots.iter().find(|fill| !slots.contains(&fill.name) || fill.name == "children") {
+            return Err(Error::new(fill.name.span(), format!("`{}` has no slot `{}`", node.name, fill.name)));
+        }
+        for slot in slots {
+            let block = match slot == "children" {
+                true => node.children.as_ref(),
+                false => node.slots.iter().find(|fill| fill.name == *slot).map(|fill| &fill.body),
+            };
+            let Some(block) = block else {
+                return Err(Error::new(node.span, format!("`{}` expects a block for slot `{slot}`", node.name)));
+            };
+            let arg = self.slot_fill(block)?;
+            self.op(OP_ARG_SLOT, arg);
+        }
+
+        if let Some((op, arg)) = instructions {
+            self.op(op, arg);
+        }
+
+        if let Some(children) = &node.children && !slots.iter().any(|slot| slot == "children") {
+            self.block(children)?;
+        }
+
+        self.op(OP_END, 0);
+        Ok(())
+    }
+
+    // The argument passing the instructions of the chain: the store of the style when the
+    // chain is a single style, a derived store when arguments use stores, a const store of
+    // its own otherwise.
+    fn instructions(&mut self, chain: &[Modifier]) -> Result<Option<(u8, u32)>> {
+        if let [modifier] = chain && is_reference(modifier) {
+            return Ok(Some((OP_ARG_PASS, self.style_store(modifier)? as u32)));
+        }
+
+        // interpolated strings become their `format!`, as for node arguments
+        let mut uses: Vec<Ident> = Vec::new();
+        let mut reactive = chain.to_vec();
+        for arg in reactive.iter_mut().filter(|modifier| !is_reference(modifier)).flat_map(|modifier| &mut modifier.args) {
+            let used = match self.interpolate(&arg.value)? {
+                Some(spec) => {
+                    arg.value = spec.body;
+                    spec.uses
+                }
+                None => stores_used(&arg.value, &|name| self.symbol(name).is_some()),
+            };
+            for name in used {
+                if !uses.contains(&name) {
+                    uses.push(name);
+                }
+            }
+        }
+        let chain = &reactive[..];
+
+        // runs of instructions between the styles
+        let mut parts = Vec::new();
+        let mut start = 0;
+        for (i, modifier) in chain.iter().enumerate().filter(|(_, modifier)| is_reference(modifier)) {
+            if start < i {
+                parts.push(ChainPart::Inline(parse_instructions(&chain[start..i])?));
+            }
+            parts.push(ChainPart::Style(self.style_resource(modifier)?));
+            start = i + 1;
+        }
+        if start < chain.len() {
+            parts.push(ChainPart::Inline(parse_instructions(&chain[start..])?));
+        }
+
+        if parts.is_empty() {
+            return Ok(None);
+        }
+        match uses.is_empty() {
+            true => Ok(Some((OP_ARG_CONST, self.push_resource(LoweredResource::Instructions(parts))))),
+            false => Ok(Some((OP_ARG_DERIVED, self.push_derive(&uses, DeriveBody::Instructions(parts))?))),
+        }
+    }
+
+    fn style_store(&mut self, modifier: &Modifier) -> Result<u16> {
+        if let Some(&index) = self.style_stores.get(&modifier.name.to_string()) {
+            return Ok(index);
+        }
+        let resource = self.style_resource(modifier)?;
+        self.op(OP_CONST, resource);
+        self.out.own_store_count += 1;
+        let index = self.store_count;
+        self.store_count += 1;
+        self.style_stores.insert(modifier.name.to_string(), index);
+        Ok(index)
+    }
+
+    fn style_resource(&mut self, modifier: &Modifier) -> Result<u32> {
+        self.styles.reference(modifier)?;
+        let shared = self.out.resources.iter().position(|resource| matches!(resource, LoweredResource::Style(name) if *name == modifier.name));
+        Ok(match shared {
+            Some(index) => index as u32,
+            None => self.push_resource(LoweredResource::Style(modifier.name.clone())),
+        })
+    }
+
+    fn slot_fill(&mut self, block: &Block) -> Result<u32> {
+        let (fragment, uses) = self.fragment_of("slot", block)?;
+        self.out.slots.push(LoweredSlot { fragment: fragment as u16, uses });
+        Ok(self.out.slots.len() as u32 - 1)
+    }
+
+    // An `if` chain is a branch: the condition store selects the arm, each arm is a
+    // fragment of its own, instantiated with the stores of this fragment it uses.
+    fn if_stmt(&mut self, stmt: &IfStmt) -> Result<()> {
+        let mut conds: Vec<&syn::Expr> = Vec::new();
+        let mut blocks: Vec<&Block> = Vec::new();
+        let mut next = Some(stmt);
+        while let Some(stmt) = next.take() {
+            conds.push(&stmt.cond);
+            blocks.push(&stmt.then_block);
+            match &stmt.else_arm {
+                Some(ElseArm::If(stmt)) => next = Some(stmt),
+                Some(ElseArm::Block(block)) => blocks.push(block),
+                None => {}
+            }
+        }
+
+        let condition = self.condition(&conds)?;
+        self.branch(condition, &blocks, stmt.keep)
+    }
+
+    // A `match` is a branch as well, the condition is the index of the matching arm. The
+    // generated `match` has no catch-all arm, so rustc checks it for exhaustiveness.
+    fn match_stmt(&mut self, stmt: &MatchStmt) -> Result<()> {
+        for arm in &stmt.arms {
+            let mut bound = Vec::new();
+            bindings(&arm.pat, &mut bound);
+            // a bare identifier is a binding unless it names a constant or a unit variant in
+            // scope, which only rustc can tell; so only the prelude `None` is accepted
+            if let Some(name) = bound.iter().find(|name| *name != "None") {
+                return Err(Error::new(name.span(), format!(
+                    "`{name}` in a `match` pattern is a binding or a name rustc has to resolve, neither is supported: bind the data of a variant with a derived store, write constants and variants as paths (`Self::{name}`)"
+                )));
+            }
+        }
+
+        let scrutinee = &stmt.scrutinee;
+        let is_store = |name: &Ident| self.symbol(name).is_some();
+        let mut uses = stores_used(scrutinee, &is_store);
+        for guard in stmt.arms.iter().filter_map(|arm| arm.guard.as_ref()) {
+            for name in stores_used(guard, &is_store) {
+                if !uses.contains(&name) {
+                    uses.push(name);
+                }
+            }
+        }
+
+        let arms = stmt.arms.iter().enumerate().map(|(i, arm)| {
+            let (pat, index) = (&arm.pat, arm_index(i));
+            match &arm.guard {
+                Some(guard) => quote!(#pat if #guard => #index,),
+                None => quote!(#pat => #index,),
+            }
+        });
+        let body = parse_quote!(match #scrutinee { #(#arms)* });
+
+        let condition = self.derived_store(DerivedSpec { uses, body })?;
+        let blocks: Vec<&Block> = stmt.arms.iter().map(|arm| &arm.body).collect();
+        self.branch(condition, &blocks, stmt.keep)
+    }
+
+    fn branch(&mut self, condition: u16, blocks: &[&Block], keep: bool) -> Result<()> {
+        let mut arms = Vec::new();
+        for block in blocks {
+            arms.push(self.fragment_of("arm", block)?);
+        }
+
+        self.op(OP_BRANCH, condition as u32);
+        for (dependency, captured) in arms {
+            self.op(OP_ARM, dependency);
+            for index in captured {
+                self.op(OP_ARG_PASS, index as u32);
+            }
+        }
+        self.op(OP_BRANCH_END, keep as u32);
+        Ok(())
+    }
+
+    // The condition store: a `bool` store when the chain is `if flag`, a derived `u32` arm
+    // index otherwise.
+    fn condition(&mut self, conds: &[&syn::Expr]) -> Result<u16> {
+        if let [syn::Expr::Path(path)] = conds && let Some(name) = path.path.get_ident()
+            && let Some(symbol) = self.symbol(name)
+            && matches!(&symbol.ty, Some(syn::Type::Path(ty)) if ty.path.is_ident("bool"))
+        {
+            return Ok(symbol.index);
+        }
+
+        let mut uses: Vec<Ident> = Vec::new();
+        for cond in conds {
+            for name in stores_used(cond, &|name| self.symbol(name).is_some()) {
+                if !uses.contains(&name) {
+                    uses.push(name);
+                }
+            }
+        }
+
+        let none = arm_index(conds.len());
+        let mut body: syn::Expr = parse_quote!(#none);
+        for (i, cond) in conds.iter().enumerate().rev() {
+            let i = arm_index(i);
+            body = parse_quote!(if #cond { #i } else { #body });
+        }
+
+        self.derived_store(DerivedSpec { uses, body })
+    }
+
+    // An anonymous derived store, returns its index.
+    fn derived_store(&mut self, spec: DerivedSpec) -> Result<u16> {
+        let arg = self.derive(&spec)?;
+        self.op(OP_DERIVED, arg);
+        self.out.own_store_count += 1;
+        let index = self.store_count;
+        self.store_count += 1;
+        Ok(index)
+    }
+
+    // Lower the block into a fragment, returning its dependency index and the stores it captures.
+    fn fragment_of(&mut self, kind: &str, block: &Block) -> Result<(u32, Vec<u16>)> {
+        let name = format_ident!("{}_{}_{}", self.out.name, kind, self.out.nested.len());
+
+        // the probe sees every visible store and finds the ones the block uses; it only probes
+        // the blocks nested in it, so a block is probed once per enclosing fragment, not
+        // lowered twice per level
+        let probe = self.nested(&name, &self.names, block, true)?;
+        let captured: Vec<Symbol> = probe.names.into_iter().filter(|symbol| symbol.used.get()).collect();
+
+        let indices = captured.iter()
+            .map(|symbol| {
+                let outer = self.names.iter().rev().find(|outer| outer.name == symbol.name && outer.slot == symbol.slot).unwrap();
+                outer.used.set(true);
+                outer.index
+            })
+            .collect();
+
+        if self.probing {
+            return Ok((0, indices));
+        }
+
+        let nested = self.nested(&name, &captured, block, false)?;
+        self.out.nested.push(nested.out);
+        Ok((self.dependency(&name), indices))
+    }
+
+    fn derive(&mut self, spec: &DerivedSpec) -> Result<u32> {
+        self.push_derive(&spec.uses, DeriveBody::Value(spec.body.clone()))
+    }
+
+    fn push_derive(&mut self, uses: &[Ident], body: DeriveBody) -> Result<u32> {
+        let uses = uses.iter().map(|name| self.resolve(name)).collect::<Result<Vec<_>>>()?;
+        self.out.derived_handlers.push(LoweredDerive { uses, body });
+        Ok(self.out.derived_handlers.len() as u32 - 1)
+    }
+
+    fn interpolate(&self, value: &syn::Expr) -> Result<Option<DerivedSpec>> {
+        interpolate(value, &|name| self.symbol(name).is_some())
+    }
+
+    fn handler(&mut self, handler: &EventHandler) -> Result<u32> {
+        let index = self.out.events_handlers.len();
+        let lowered = lower_handler(handler, index, &|name| {
+            self.symbol(name).map(|symbol| HandlerStore { index: symbol.index, kind: symbol.kind, ty: symbol.ty.clone() })
+        })?;
+        self.out.events_handlers.push(lowered);
+        Ok(index as u32)
+    }
+
+    // The `eq` of the store op: the declared strategy, deep comparison for literals and
+    // `Always` for other values.
+    fn eq_strategy(&mut self, store: &StoreDecl, formatted: bool) -> Result<u16> {
+        let spec = match (&store.eq, &store.init) {
+            (Some(EqSpec::Always), _) => return Ok(0),
+            (Some(spec), _) => spec,
+            (None, StoreInit::Literal(value)) if formatted || literal_type(value).is_some() => &EqSpec::Deep,
+            (None, _) => return Ok(0),
+        };
+        let Some(ty) = store_type(store, formatted) else {
+            let name = &store.name;
+            return Err(Error::new(
+                name.span(),
+                format!("cannot infer the type of store `{name}` for its `eq` strategy, declare it as `store {name}: Type = ...`"),
+            ));
+        };
+
+        let strategy: syn::Expr = match spec {
+            EqSpec::Deep => parse_quote!(::fluxum::store::EqStrategy::Compare(::fluxum::store::mk_eq_fn::<#ty>())),
+            EqSpec::Ptr => parse_quote!(::fluxum::store::EqStrategy::Compare(::fluxum::store::mk_ptr_eq_fn::<#ty>())),
+            EqSpec::Fingerprint => parse_quote!(::fluxum::store::EqStrategy::Fingerprint(::fluxum::store::mk_fingerprint_fn::<#ty>())),
+            EqSpec::Fn(path) => parse_quote!(::fluxum::store::EqStrategy::Compare(|old, new| {
+                match (old.downcast_ref::<#ty>(), new.downcast_ref::<#ty>()) {
+                    (::std::option::Option::Some(old), ::std::option::Option::Some(new)) => #path(old, new),
+                    _ => false,
+                }
+            })),
+            EqSpec::Always => unreachable!(),
+        };
+        self.out.eq_strategies.push(strategy);
+        Ok(self.out.eq_strategies.len() as u16)
+    }
+
+    fn resource(&mut self, value: &syn::Expr) -> u32 {
+        self.push_resource(LoweredResource::Value(value.clone()))
+    }
+
+    fn push_resource(&mut self, resource: LoweredResource) -> u32 {
+        self.out.resources.push(resource);
+        self.out.resources.len() as u32 - 1
+    }
+
+    fn dependency(&mut self, name: &Ident) -> u32 {
+        index_of(&mut self.out.dependencies, name, |a, b| a == b)
+    }
+
+    fn context(&mut self, key: &syn::Path) -> u16 {
+        index_of(&mut self.out.contexts, key, same_path) as u16
+    }
+
+    fn declare(&mut self, name: &Ident, kind: StoreKind, ty: Option<syn::Type>) {
+        self.names.push(Symbol { name: name.clone(), index: self.store_count, kind, ty, used: Cell::new(false), slot: false });
+        self.store_count += 1;
+    }
+
+    // A slot is a const store holding the `linker::BoundSlot`.
+    fn declare_slot(&mut self, name: &Ident) {
+        self.declare(name, StoreKind::Const, None);
+        self.names.last_mut().unwrap().slot = true;
+    }
+
+    fn symbol(&self, name: &Ident) -> Option<&Symbol> {
+        let symbol = self.names.iter().rev().find(|symbol| symbol.name == *name && !symbol.slot)?;
+        symbol.used.set(true);
+        Some(symbol)
+    }
+
+    fn resolve(&self, name: &Ident) -> Result<u16> {
+        match self.symbol(name) {
+            Some(symbol) => Ok(symbol.index),
+            None => Err(Error::new(name.span(), format!("cannot find store `{name}` in this fragment"))),
+        }
+    }
+
+    fn op(&mut self, opcode: u8, arg: u32) {
+        encode_op(&mut self.out.ops, opcode, arg);
+    }
+}
+
+// Index of the item in the table, appended when not there yet.
+fn index_of<T: Clone>(table: &mut Vec<T>, item: &T, same: impl Fn(&T, &T) -> bool) -> u32 {
+    match table.iter().position(|t| same(t, item)) {
+        Some(index) => index as u32,
+        None => {
+            table.push(item.clone());
+            table.len() as u32 - 1
+        }
+    }
+}
+
+// The declared type of the store, or the type of its literal.
+fn store_type(store: &StoreDecl, formatted: bool) -> Option<syn::Type> {
+    store.ty.clone().or_else(|| match &store.init {
+        _ if formatted => Some(parse_quote!(::std::string::String)),
+        StoreInit::Literal(value) => literal_type(value),
+        StoreInit::Derived(_) => None,
+    })
+}
+
+// The type of a store initialized with a literal, as rustc would infer it.
+fn literal_type(value: &syn::Expr) -> Option<syn::Type> {
+    let syn::Expr::Lit(lit) = value else {
+        return match value {
+            syn::Expr::Unary(unary) if matches!(unary.op, syn::UnOp::Neg(_)) => literal_type(&unary.expr),
+            syn::Expr::Paren(paren) => literal_type(&paren.expr),
+            _ => None,
+        };
+    };
+
+    Some(match &lit.lit {
+        syn::Lit::Int(int) if int.suffix().is_empty() => parse_quote!(i32),
+        syn::Lit::Float(float) if float.suffix().is_empty() => parse_quote!(f64),
+        syn::Lit::Int(int) => syn::parse_str(int.suffix()).ok()?,
+        syn::Lit::Float(float) => syn::parse_str(float.suffix()).ok()?,
+        syn::Lit::Bool(_) => parse_quote!(bool),
+        syn::Lit::Str(_) => parse_quote!(&'static str),
+        syn::Lit::Char(_) => parse_quote!(char),
+        _ => return None,
+    })
+}
+
+fn arm_index(index: usize) -> syn::LitInt {
+    syn::LitInt::new(&format!("{index}u32"), Span::call_site())
+}
+
+fn same_path(a: &syn::Path, b: &syn::Path) -> bool {
+    a.segments.len() == b.segments.len() && a.segments.iter().zip(&b.segments).all(|(a, b)| a.ident == b.ident)
+}
+
+fn unsupported(span: Span, what: &str) -> Error {
+    Error::new(span, format!("{what} is not supported by the lowering yet"))
+}
@@ -1 +1,9 @@
-mod ast;
\ No newline at end of file
+pub mod ast;
+pub mod dump;
+pub mod handler;
+pub mod instruction;
+pub mod interpolate;
+pub mod lower;
+pub mod resolve;
+pub mod style;
+pub mod surround;
@@ -0,0 +1,386 @@
+// resolve.rs
+use std::collections::HashMap;
+
+use proc_macro2::Span;
+use syn::visit::{self, Visit};
+use syn::{Error, Ident, Result};
+
+use crate::compiler::ast::*;
+use crate::compiler::instruction::parse_instructions;
+use crate::compiler::interpolate::interpolate;
+use crate::compiler::style::{StyleTable, style_table};
+
+/// A diagnostic that does not stop the compilation.
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Check the names of every fragment in the file before lowering. All errors are
+/// reported at once, combined into one `syn::Error`; unused stores and shadowed names
+/// are returned as warnings.
+pub fn resolve_file(file: &FragmentFile) -> Result<Vec<Warning>> {
+    let signatures: HashMap<String, &FragmentDecl> =
+        file.fragments.iter().map(|f| (f.name.to_string(), f)).collect();
+
+    let (styles, errors) = style_table(&file.styles);
+
+    let mut resolver = Resolver {
+        signatures,
+        styles,
+        broken_styles: Vec::new(),
+        symbols: Vec::new(),
+        scopes: Vec::new(),
+        errors,
+        warnings: Vec::new(),
+    };
+
+    // the table lists the styles after the ones they refer to
+    for (name, chain) in resolver.styles.iter() {
+        let decl = file.styles.iter().find(|decl| decl.name == *name).unwrap();
+        if decl.chain.iter().any(|modifier| resolver.broken_styles.contains(&modifier.name)) {
+            resolver.broken_styles.push(name.clone());
+        } else if let Err(error) = parse_instructions(chain) {
+            resolver.errors.push(error);
+            resolver.broken_styles.push(name.clone());
+        }
+    }
+
+    let mut seen: Vec<&Ident> = Vec::new();
+    for fragment in &file.fragments {
+        if seen.contains(&&fragment.name) {
+            resolver.error(fragment.name.span(), format!("fragment `{}` is declared twice", fragment.name));
+        }
+        seen.push(&fragment.name);
+        resolver.fragment(fragment);
+    }
+
+    match resolver.errors.into_iter().reduce(|mut all, error| { all.combine(error); all }) {
+        Some(error) => Err(error),
+        None => Ok(resolver.warnings),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SymbolKind {
+    Param(StoreKind),
+    Store(StoreKind),
+    Use,                           // context store, readable
+    Let,                           // plain value, not a store
+    Slot,                          // placed with `slot name`, not a store
+}
+
+impl SymbolKind {
+    fn describe(self) -> &'static str {
+        match self {
+            SymbolKind::Param(kind) | SymbolKind::Store(kind) => match kind {
+                StoreKind::Const => "a const store",
+                StoreKind::Readable => "a readable store",
+                StoreKind::Derived => "a derived store",
+                StoreKind::Writable => "a writable store",
+            },
+            SymbolKind::Use => "a context store",
+            SymbolKind::Let => "a `let` value",
+            SymbolKind::Slot => "a slot",
+        }
+    }
+
+    fn is_writable(self) -> bool {
+        matches!(self, SymbolKind::Param(StoreKind::Writable) | SymbolKind::Store(StoreKind::Writable))
+    }
+}
+
+struct Symbol {
+    name: Ident,
+    kind: SymbolKind,
+    used: bool,
+}
+
+struct Resolver<'a> {
+    signatures: HashMap<String, &'a FragmentDecl>,
+    styles: StyleTable,
+    broken_styles: Vec<Ident>,     // styles with invalid instructions
+    symbols: Vec<Symbol>,
+    scopes: Vec<usize>,            // start of each open block in `symbols`
+    errors: Vec<Error>,
+    warnings: Vec<Warning>,
+}
+
+impl Resolver<'_> {
+    fn fragment(&mut self, fragment: &FragmentDecl) {
+        self.scopes.push(self.symbols.len());
+        for param in &fragment.params {
+            self.declare(&param.name, SymbolKind::Param(param.kind));
+        }
+        for slot in &fragment.slots {
+            self.declare(&slot.name, SymbolKind::Slot);
+        }
+        // the body shares the scope of the parameters
+        for stmt in &fragment.body.items {
+            self.stmt(stmt);
+        }
+        self.close();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(self.symbols.len());
+        for stmt in &block.items {
+            self.stmt(stmt);
+        }
+        self.close();
+    }
+
+    // Close the innermost scope, reporting its unused stores.
+    fn close(&mut self) {
+        let start = self.scopes.pop().unwrap_or(0);
+        for symbol in self.symbols.drain(start..) {
+            if !symbol.used && matches!(symbol.kind, SymbolKind::Store(_) | SymbolKind::Use) {
+                self.warnings.push(Warning {
+                    span: symbol.name.span(),
+                    message: format!("store `{}` is never used", symbol.name),
+                });
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &BuildStmt) {
+        match stmt {
+            BuildStmt::Store(store) => {
+                match &store.init {
+                    StoreInit::Literal(value) => self.expr(value),
+                    StoreInit::Derived(spec) => self.derived(spec),
+                }
+                self.declare(&store.name, SymbolKind::Store(store.kind));
+            }
+            BuildStmt::Node(node) => self.node(node),
+            BuildStmt::If(stmt) => self.if_stmt(stmt),
+            BuildStmt::Match(stmt) => {
+                self.expr(&stmt.scrutinee);
+                for arm in &stmt.arms {
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.block(&arm.body);
+                }
+            }
+            BuildStmt::Let(stmt) => {
+                self.expr(&stmt.value);
+                self.declare(&stmt.name, SymbolKind::Let);
+            }
+            BuildStmt::Expr(expr) => self.expr(expr),
+            BuildStmt::Provide(provide) => {
+                self.reference(&provide.store);
+            }
+            BuildStmt::Use(use_stmt) => self.declare(&use_stmt.name, SymbolKind::Use),
+            BuildStmt::Slot(slot) => match self.lookup(&slot.name) {
+                Some(index) if self.symbols[index].kind == SymbolKind::Slot => self.symbols[index].used = true,
+                _ => self.error(slot.name.span(), format!("cannot find slot `{}` in this fragment", slot.name)),
+            },
+        }
+    }
+
+    fn node(&mut self, node: &NodeDecl) {
+        let callee = self.signatures.get(&node.name.to_string()).copied();
+        let params = callee.map(|callee| callee.params.as_slice());
+
+        // event handlers are not positional parameters
+        let positional = node.args.iter().filter(|arg| !matches!(arg, NodeArg::EventHandler(_)));
+
+        for (position, arg) in positional.enumerate() {
+            let param = params.and_then(|params| params.get(position));
+
+            let kind = match arg {
+                NodeArg::Pass(name) => self.reference(name),
+                NodeArg::Const(value) => { self.expr(value); Some(SymbolKind::Store(StoreKind::Const)) }
+                NodeArg::Readable(value) => { self.expr(value); Some(SymbolKind::Store(StoreKind::Readable)) }
+                NodeArg::Derived(spec) => { self.derived(spec); Some(SymbolKind::Store(StoreKind::Derived)) }
+                NodeArg::Writable(value) => { self.expr(value); Some(SymbolKind::Store(StoreKind::Writable)) }
+                NodeArg::EventHandler(_) => unreachable!(),
+            };
+
+            if let (Some(param), Some(kind)) = (param, kind) && param.kind == StoreKind::Writable && !kind.is_writable() {
+                let what = match arg {
+                    NodeArg::Pass(name) => format!("`{name}` is {}", kind.describe()),
+                    _ => format!("the argument is {}", kind.describe()),
+                };
+                self.error(
+                    arg_span(arg, node),
+                    format!("{what}, but `{}` expects a writable store for `{}`", node.name, param.name),
+                );
+            }
+        }
+
+        if let Some(params) = params {
+            let count = node.args.iter().filter(|arg| !matches!(arg, NodeArg::EventHandler(_))).count();
+            if count != params.len() {
+                let plural = if params.len() == 1 { "" } else { "s" };
+                self.error(node.span, format!("`{}` takes {} argument{plural}, {count} given", node.name, params.len()));
+            }
+        }
+
+        for arg in &node.args {
+            if let NodeArg::EventHandler(handler) = arg {
+                match &handler.body {
+                    BlockOrExpr::Expr(expr) => self.mark_used(expr),
+                    BlockOrExpr::Block(block) => block.items.iter().for_each(|stmt| match stmt {
+                        BuildStmt::Expr(expr) => self.mark_used(expr),
+                        BuildStmt::Let(stmt) => self.mark_used(&stmt.value),
+                        _ => {}
+                    }),
+                }
+            }
+        }
+
+        // styles with errors are reported once, with the style
+        let broken = node.chain.iter().any(|modifier| self.broken_styles.contains(&modifier.name));
+        match self.styles.expand(&node.chain) {
+            Ok(chain) if !broken => if let Err(error) = parse_instructions(&chain) {
+                self.errors.push(error);
+            },
+            Ok(_) => {}
+            Err(error) => self.errors.push(error),
+        }
+        if callee.is_some() && let Some(modifier) = node.chain.first() {
+            self.error(modifier.name.span(), format!("`{}` is a fragment of this file, instructions apply to standard fragments", node.name));
+        }
+        for modifier in &node.chain {
+            for arg in &modifier.args {
+                self.expr(&arg.value);
+            }
+        }
+
+        if let Some(callee) = callee {
+            self.slot_fills(node, callee);
+        } else if let Some(fill) = node.slots.first() {
+            self.error(fill.span, format!("`{}` has no slots", node.name));
+        }
+
+        if let Some(children) = &node.children {
+            self.block(children);
+        }
+        for fill in &node.slots {
+            self.block(&fill.body);
+        }
+    }
+
+    // Every slot of the callee must be filled exactly once, the `children` slot by the
+    // block of the call site.
+    fn slot_fills(&mut self, node: &NodeDecl, callee: &FragmentDecl) {
+        for (i, fill) in node.slots.iter().enumerate() {
+            if !callee.slots.iter().any(|slot| slot.name == fill.name) || fill.name == "children" {
+                self.error(fill.name.span(), format!("`{}` has no slot `{}`", node.name, fill.name));
+            } else if node.slots[..i].iter().any(|other| other.name == fill.name) {
+                self.error(fill.name.span(), format!("slot `{}` is filled twice", fill.name));
+            }
+        }
+
+        for slot in &callee.slots {
+            let filled = match slot.name == "children" {
+                true => node.children.is_some(),
+                false => node.slots.iter().any(|fill| fill.name == slot.name),
+            };
+            if !filled {
+                self.error(node.span, format!("`{}` expects a block for slot `{}`", node.name, slot.name));
+            }
+        }
+    }
+
+    fn if_stmt(&mut self, stmt: &IfStmt) {
+        self.expr(&stmt.cond);
+        self.block(&stmt.then_block);
+        match &stmt.else_arm {
+            Some(ElseArm::Block(block)) => self.block(block),
+            Some(ElseArm::If(stmt)) => self.if_stmt(stmt),
+            None => {}
+        }
+    }
+
+    fn derived(&mut self, spec: &DerivedSpec) {
+        for name in &spec.uses {
+            self.reference(name);
+        }
+    }
+
+    // An expression: interpolated literals must name existing stores, everything else
+    // only marks the stores it mentions as used.
+    fn expr(&mut self, expr: &syn::Expr) {
+        match interpolate(expr, &|name| self.lookup(name).is_some()) {
+            Ok(Some(spec)) => self.derived(&spec),
+            Ok(None) => self.mark_used(expr),
+            Err(error) => self.errors.push(error),
+        }
+    }
+
+    fn mark_used(&mut self, expr: &syn::Expr) {
+        let mut names = Names(Vec::new());
+        names.visit_expr(expr);
+        for name in names.0 {
+            if let Some(index) = self.lookup(&name) {
+                self.symbols[index].used = true;
+            }
+        }
+    }
+
+    // A position that must name a store (or value) of the fragment.
+    fn reference(&mut self, name: &Ident) -> Option<SymbolKind> {
+        match self.lookup(name) {
+            Some(index) if self.symbols[index].kind == SymbolKind::Slot => {
+                self.symbols[index].used = true;
+                self.error(name.span(), format!("`{name}` is a slot, place it with `slot {name}`"));
+                None
+            }
+            Some(index) => {
+                self.symbols[index].used = true;
+                Some(self.symbols[index].kind)
+            }
+            None => {
+                self.error(name.span(), format!("cannot find store `{name}` in this fragment"));
+                None
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Ident, kind: SymbolKind) {
+        let scope = *self.scopes.last().unwrap_or(&0);
+
+        if let Some(index) = self.lookup(name) {
+            if index >= scope {
+                self.error(name.span(), format!("`{name}` is declared twice in this block"));
+            } else {
+                self.warnings.push(Warning {
+                    span: name.span(),
+                    message: format!("`{name}` shadows {} of an enclosing block", self.symbols[index].kind.describe()),
+                });
+            }
+        }
+
+        self.symbols.push(Symbol { name: name.clone(), kind, used: false });
+    }
+
+    fn lookup(&self, name: &Ident) -> Option<usize> {
+        self.symbols.iter().rposition(|symbol| symbol.name == *name)
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(Error::new(span, message));
+    }
+}
+
+fn arg_span(arg: &NodeArg, node: &NodeDecl) -> Span {
+    match arg {
+        NodeArg::Pass(name) => name.span(),
+        _ => node.span,
+    }
+}
+
+// Single-segment paths of an expression, the candidates for store references.
+struct Names(Vec<Ident>);
+
+impl<'ast> Visit<'ast> for Names {
+    fn visit_expr_path(&mut self, path: &'ast syn::ExprPath) {
+        if let Some(name) = path.path.get_ident() {
+            self.0.push(name.clone());
+        }
+        visit::visit_expr_path(self, path);
+    }
+}
@@ -0,0 +1,117 @@
+// style.rs
+use syn::{Error, Ident, Result};
+
+use crate::compiler::ast::*;
+
+/// The styles of a file with their references expanded. A reference is replaced by the
+/// chain of the style where it stands, so the instructions after it override the style and
+/// the style overrides the instructions before it.
+#[derive(Default)]
+pub struct StyleTable {
+    styles: Vec<(Ident, Vec<Modifier>)>,
+}
+
+impl StyleTable {
+    /// The expanded chain of the style.
+    pub fn get(&self, name: &Ident) -> Option<&[Modifier]> {
+        self.styles.iter().find(|(n, _)| n == name).map(|(_, chain)| chain.as_slice())
+    }
+
+    /// The styles in dependency order: each style comes after the styles it refers to.
+    pub fn iter(&self) -> impl Iterator<Item = (&Ident, &[Modifier])> {
+        self.styles.iter().map(|(name, chain)| (name, chain.as_slice()))
+    }
+
+    /// The chain with its style references expanded.
+    pub fn expand(&self, chain: &[Modifier]) -> Result<Vec<Modifier>> {
+        let mut out = Vec::new();
+        let mut errors = Vec::new();
+        for modifier in chain {
+            match is_reference(modifier) {
+                true => match self.reference(modifier) {
+                    Ok(style) => out.extend_from_slice(style),
+                    Err(error) => errors.push(error),
+                },
+                false => out.push(modifier.clone()),
+            }
+        }
+        match errors.into_iter().reduce(|mut all, error| { all.combine(error); all }) {
+            Some(error) => Err(error),
+            None => Ok(out),
+        }
+    }
+
+    /// The expanded chain of the style the modifier refers to.
+    pub fn reference(&self, modifier: &Modifier) -> Result<&[Modifier]> {
+        let Some(style) = self.get(&modifier.name) else {
+            return Err(Error::new(modifier.name.span(), format!("cannot find style `{}`", modifier.name)));
+        };
+        if !modifier.args.is_empty() {
+            return Err(Error::new(modifier.name.span(), format!("style `{}` takes no arguments", modifier.name)));
+        }
+        Ok(style)
+    }
+}
+
+/// Instructions are snake case, styles start with an uppercase letter.
+pub fn is_reference(modifier: &Modifier) -> bool {
+    modifier.name.to_string().starts_with(char::is_uppercase)
+}
+
+/// Expand the styles of a file. Broken styles stay in the table with what could be
+/// expanded, so the nodes using them report no further errors.
+pub fn style_table(decls: &[StyleDecl]) -> (StyleTable, Vec<Error>) {
+    let mut builder = Builder { decls, table: StyleTable::default(), open: Vec::new(), errors: Vec::new() };
+
+    for (i, decl) in decls.iter().enumerate() {
+        if decls[..i].iter().any(|other| other.name == decl.name) {
+            builder.errors.push(Error::new(decl.name.span(), format!("style `{}` is declared twice", decl.name)));
+            continue;
+        }
+        if !decl.name.to_string().starts_with(char::is_uppercase) {
+            builder.errors.push(Error::new(decl.name.span(), format!("style `{}` must start with an uppercase letter", decl.name)));
+        }
+        builder.style(decl);
+    }
+
+    (builder.table, builder.errors)
+}
+
+struct Builder<'a> {
+    decls: &'a [StyleDecl],
+    table: StyleTable,
+    open: Vec<&'a Ident>,          // styles being expanded, to find cycles
+    errors: Vec<Error>,
+}
+
+impl<'a> Builder<'a> {
+    // Expand the style after the styles it refers to.
+    fn style(&mut self, decl: &'a StyleDecl) {
+        if self.table.get(&decl.name).is_some() {
+            return;
+        }
+        self.open.push(&decl.name);
+
+        let mut chain = Vec::new();
+        for modifier in &decl.chain {
+            if !is_reference(modifier) {
+                chain.push(modifier.clone());
+                continue;
+            }
+            if self.open.contains(&&modifier.name) {
+                self.errors.push(Error::new(modifier.name.span(), format!("style `{}` refers to itself", modifier.name)));
+                continue;
+            }
+            if let Some(referred) = self.decls.iter().find(|other| other.name == modifier.name) {
+                self.style(referred);
+            }
+            match self.table.reference(modifier) {
+                Ok(style) => chain.extend_from_slice(style),
+                Err(error) => self.errors.push(error),
+            }
+        }
+
+        self.open.pop();
+        self.table.styles.push((decl.name.clone(), chain));
+    }
+}
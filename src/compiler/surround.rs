@@ -0,0 +1,119 @@
+// surround.rs
+use std::collections::HashSet;
+
+use syn::Ident;
+
+use crate::compiler::ast::*;
+use crate::compiler::style::{StyleTable, is_reference, style_table};
+
+/// The instructions of the surrounding, see `doc/10_language/box_model.md`.
+pub const SURROUNDING: [&str; 3] = ["padding", "border", "margin"];
+
+/// What the fragments can do, by fragment name. Fragments missing from the table,
+/// user fragments included, have no capabilities.
+#[derive(Default)]
+pub struct CapabilityTable {
+    surrounding: HashSet<String>,  // fragments drawing their own surrounding
+}
+
+impl CapabilityTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The standard containers accept surrounding, the basic fragments do not.
+    pub fn standard() -> Self {
+        let mut table = Self::new();
+        for name in ["box", "column", "row", "flow_box", "grid", "split_pane"] {
+            table.accept_surrounding(name);
+        }
+        table
+    }
+
+    pub fn accept_surrounding(&mut self, fragment: &str) -> &mut Self {
+        self.surrounding.insert(fragment.to_string());
+        self
+    }
+
+    pub fn accepts_surrounding(&self, fragment: &str) -> bool {
+        self.surrounding.contains(fragment)
+    }
+}
+
+/// Wrap every node that receives surrounding instructions but does not accept them into a
+/// `box` carrying those instructions. The other instructions stay on the node. Styles with
+/// surrounding instructions are expanded on the nodes wrapped, the other nodes keep sharing them.
+pub fn surround_file(file: &mut FragmentFile, table: &CapabilityTable) {
+    // broken styles are reported by the resolver
+    let (styles, _) = style_table(&file.styles);
+    for fragment in &mut file.fragments {
+        surround_block(&mut fragment.body, table, &styles);
+    }
+}
+
+pub fn surround_block(block: &mut Block, table: &CapabilityTable, styles: &StyleTable) {
+    for stmt in &mut block.items {
+        match stmt {
+            BuildStmt::Node(node) => surround_node(node, table, styles),
+            BuildStmt::If(stmt) => surround_if(stmt, table, styles),
+            BuildStmt::Match(stmt) => {
+                for arm in &mut stmt.arms {
+                    surround_block(&mut arm.body, table, styles);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn surround_if(stmt: &mut IfStmt, table: &CapabilityTable, styles: &StyleTable) {
+    surround_block(&mut stmt.then_block, table, styles);
+    match &mut stmt.else_arm {
+        Some(ElseArm::Block(block)) => surround_block(block, table, styles),
+        Some(ElseArm::If(stmt)) => surround_if(stmt, table, styles),
+        None => {}
+    }
+}
+
+fn surround_node(node: &mut NodeDecl, table: &CapabilityTable, styles: &StyleTable) {
+    if let Some(children) = &mut node.children {
+        surround_block(children, table, styles);
+    }
+    for fill in &mut node.slots {
+        surround_block(&mut fill.body, table, styles);
+    }
+
+    let surrounded = |modifier: &Modifier| match is_reference(modifier) {
+        true => styles.get(&modifier.name).is_some_and(|chain| chain.iter().any(is_surrounding)),
+        false => is_surrounding(modifier),
+    };
+    if table.accepts_surrounding(&node.name.to_string()) || !node.chain.iter().any(surrounded) {
+        return;
+    }
+
+    // the styles to split are expanded in place, keeping the order of the chain
+    let chain = std::mem::take(&mut node.chain).into_iter()
+        .flat_map(|modifier| match surrounded(&modifier) && is_reference(&modifier) {
+            true => styles.get(&modifier.name).unwrap().to_vec(),
+            false => vec![modifier],
+        })
+        .collect::<Vec<_>>();
+    let (surrounding, rest) = chain.into_iter().partition(is_surrounding);
+    node.chain = rest;
+
+    let span = node.span;
+    let wrapper = NodeDecl {
+        name: Ident::new("box", node.name.span()),
+        args: Vec::new(),
+        children: None,
+        slots: Vec::new(),
+        chain: surrounding,
+        span,
+    };
+    let inner = std::mem::replace(node, wrapper);
+    node.children = Some(Block { items: vec![BuildStmt::Node(inner)], span });
+}
+
+fn is_surrounding(modifier: &Modifier) -> bool {
+    SURROUNDING.iter().any(|name| modifier.name == name)
+}
@@ -0,0 +1,170 @@
+use std::any::{Any, TypeId};
+
+use crate::store::{EqFn, EqStrategy, StoreEffects, StoreKey};
+
+// ---------------------------------------------------------------------------
+// Binary format
+// ---------------------------------------------------------------------------
+
+// The first byte of an instruction: opcode in the low 6 bits, `length of argument - 1`
+// in the high 2 bits. The argument follows in little-endian order.
+
+pub const ARG_LEN_1: u8 = 0x00;
+pub const ARG_LEN_2: u8 = 0x01 << 6;
+pub const ARG_LEN_3: u8 = 0x02 << 6;
+pub const ARG_LEN_4: u8 = 0x03 << 6;
+
+const OPCODE_MASK: u8 = 0x3f;
+
+pub const FIR_VERSION: u32 = 1;
+
+// ---------------------------------------------------------------------------
+// Instruction set
+// ---------------------------------------------------------------------------
+
+pub const OP_VERSION:       u8 = 0; // version of the IR format
+
+pub const OP_CONST:         u8 = 1; // create a const store
+pub const OP_READABLE:      u8 = 2; // create a readable store, arg: resource | eq << 16
+pub const OP_DERIVED:       u8 = 3; // create a derived store
+pub const OP_WRITABLE:      u8 = 4; // create a writable store, arg: resource | eq << 16
+
+pub const OP_BEGIN:         u8 = 5; // create a new fragment instance
+pub const OP_ARG_PASS:      u8 = 6; // pass through an existing store to the current fragment instance
+pub const OP_ARG_CONST:     u8 = 7; // create a const store and use it as an external store for the current fragment instance
+pub const OP_ARG_READABLE:  u8 = 8; // create a readable store and use it as an external store for the current fragment instance
+pub const OP_ARG_DERIVED:   u8 = 9; // create a derived store and use it as an external store for the current fragment instance
+pub const OP_ARG_WRITABLE:  u8 = 10; // create a writable store and use it as an external store for the current fragment instance
+pub const OP_ARG_EH:        u8 = 11; // event handler
+
+pub const OP_PROVIDE:       u8 = 12; // provide a store to the descendants of the current instance, arg: store | context << 16
+pub const OP_USE:           u8 = 13; // look up a context store in the instance tree, arg: context
+
+pub const OP_BRANCH:        u8 = 14; // begin a conditional subtree, arg: condition store (u32 arm index or bool)
+pub const OP_ARM:           u8 = 15; // an arm of the branch, arg: dependency, followed by OP_ARG_PASS of the stores it uses
+pub const OP_BRANCH_END:    u8 = 16; // end of the branch, arg: 1 keeps the state of the not selected arms
+
+pub const OP_ARG_SLOT:      u8 = 17; // bind a slot fill to the stores of the fragment and pass it, arg: slot
+pub const OP_SLOT:          u8 = 18; // instantiate the slot fill passed in a store, arg: store
+
+pub const OP_END:           u8 = 62; // end of the current fragment instance
+
+/// Append an instruction to `ops`, using the shortest argument length that fits `arg`.
+pub fn encode_op(ops: &mut Vec<u8>, opcode: u8, arg: u32) {
+    debug_assert!(opcode <= OPCODE_MASK);
+
+    let len = match arg {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xff_ffff => 3,
+        _ => 4,
+    };
+
+    ops.push(opcode | ((len - 1) << 6));
+    ops.extend_from_slice(&arg.to_le_bytes()[..len as usize]);
+}
+
+/// Iterates over the `(opcode, argument)` pairs of an instruction stream.
+pub struct OpReader<'a> {
+    ops: &'a [u8],
+}
+
+impl<'a> OpReader<'a> {
+    pub fn new(ops: &'a [u8]) -> Self {
+        Self { ops }
+    }
+}
+
+impl Iterator for OpReader<'_> {
+    type Item = (u8, u32);
+
+    fn next(&mut self) -> Option<(u8, u32)> {
+        let (&first, rest) = self.ops.split_first()?;
+        let len = (first >> 6) as usize + 1;
+
+        if rest.len() < len {
+            panic!("truncated FIR instruction stream");
+        }
+
+        let mut bytes = [0u8; 4];
+        bytes[..len].copy_from_slice(&rest[..len]);
+        self.ops = &rest[len..];
+
+        Some((first & OPCODE_MASK, u32::from_le_bytes(bytes)))
+    }
+}
+
+/// Argument of `OP_PROVIDE`.
+pub fn provide_arg(store: u16, context: u16) -> u32 {
+    store as u32 | (context as u32) << 16
+}
+
+/// Argument of `OP_READABLE` and `OP_WRITABLE`, `eq` is `0` for `EqStrategy::Always`,
+/// `n` for `eq_strategies[n - 1]`.
+pub fn store_arg(resource: u16, eq: u16) -> u32 {
+    resource as u32 | (eq as u32) << 16
+}
+
+// ---------------------------------------------------------------------------
+// Fragment IR
+// ---------------------------------------------------------------------------
+
+/// Creates the value of a resource, stores are initialized from these.
+pub type ResourceFn = fn() -> Box<dyn Any>;
+
+/// Computes the value of a derived store from the values of the stores it uses.
+pub type DeriveFn = fn(uses: &[&dyn Any]) -> Box<dyn Any>;
+
+pub struct DeriveDesc {
+    pub uses: &'static [u16],      // store indices of the fragment
+    pub derive: DeriveFn,
+    pub eq: Option<EqFn>,
+}
+
+/// Event handler generated from a handler block. `stores` are the stores the handler
+/// uses, in the order of `EventHandlerDesc::uses`.
+pub type HandlerFn = fn(stores: &[StoreKey], rt: &mut StoreEffects);
+
+pub struct EventHandlerDesc {
+    pub uses: &'static [u16],      // store indices of the fragment
+    pub handler: HandlerFn,
+}
+
+/// A block passed to a slot of a child fragment. The block is a fragment of its own
+/// (`dependencies[fragment]`), its external stores are `uses` of the passing fragment.
+pub struct SlotDesc {
+    pub fragment: u16,
+    pub uses: &'static [u16],      // store indices of the fragment
+}
+
+/// A typed context key. The key type identifies the context in the instance tree,
+/// `Value` is the type of the store value provided under it.
+pub trait ContextKey: 'static {
+    type Value: 'static;
+}
+
+/// A context the fragment provides or uses, `ContextDesc::of::<Theme>()`.
+pub struct ContextDesc {
+    pub id: fn() -> TypeId,
+    pub name: fn() -> &'static str,
+}
+
+impl ContextDesc {
+    pub const fn of<K: ContextKey>() -> Self {
+        Self { id: TypeId::of::<K>, name: std::any::type_name::<K> }
+    }
+}
+
+pub struct FragmentIR {
+    pub node_count: u16,
+    pub ext_store_count: u16,
+    pub own_store_count: u16,
+    pub resources: &'static [ResourceFn],
+    pub dependencies: &'static [&'static FragmentIR],
+    pub events_handlers: &'static [EventHandlerDesc],
+    pub derived_handlers: &'static [DeriveDesc],
+    pub eq_strategies: &'static [EqStrategy],
+    pub contexts: &'static [ContextDesc],
+    pub slots: &'static [SlotDesc],
+    pub ops: &'static [u8],
+}
@@ -0,0 +1,177 @@
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use smallvec::SmallVec;
+use thunderdome::{Arena, Index};
+
+use crate::fir::{ContextKey, FragmentIR};
+use crate::store::{StoreEffects, StoreKey, SubscriptionKey};
+
+pub type InstanceKey = Index;
+
+pub struct FragmentInst {
+    pub parent: Option<InstanceKey>,
+    pub ir: &'static FragmentIR,
+    pub stores: SmallVec<[StoreKey; 8]>, // external stores first, then the ones created by the ops
+    pub internal_stores: SmallVec<[StoreKey; 8]>, // used for cleanup when the instance is dropped
+    pub children: SmallVec<[InstanceKey; 8]>, // used for cleanup when the instance is dropped
+    pub contexts: SmallVec<[(TypeId, StoreKey); 2]>, // stores provided to the descendants
+    pub subscriptions: SmallVec<[SubscriptionKey; 2]>, // used for cleanup when the instance is dropped
+    pub branch: Option<Branch>, // set for the holder instances of conditional subtrees
+    pub hidden: bool, // a kept, currently not selected branch arm
+}
+
+/// A conditional subtree. The holder instance is created where the condition is in the
+/// tree, the selected arm is mounted as its only visible child.
+pub struct Branch {
+    pub condition: StoreKey,
+    pub arms: SmallVec<[BranchArm; 2]>,
+    pub keep: bool, // hide the not selected arms instead of freeing them
+    pub selected: Option<usize>,
+    pub mounted: SmallVec<[Option<InstanceKey>; 2]>, // per arm, the selected one and the kept ones
+}
+
+pub struct BranchArm {
+    pub ir: &'static FragmentIR,
+    pub args: SmallVec<[StoreKey; 4]>, // the stores of the enclosing fragment the arm uses
+}
+
+/// Holders of branches whose condition has changed, see `linker::update_branches`.
+pub type BranchQueue = Rc<RefCell<Vec<InstanceKey>>>;
+
+pub trait InstanceRuntime {
+    /// Create an empty instance of `ir` as a child of `parent`.
+    fn create_instance(&mut self, ir: &'static FragmentIR, parent: Option<InstanceKey>) -> InstanceKey;
+
+    /// Free the instance, its descendants and their internal stores.
+    fn free_instance(&mut self, key: InstanceKey, stores: &mut StoreEffects);
+
+    fn contains(&self, key: InstanceKey) -> bool;
+
+    fn instance(&self, key: InstanceKey) -> &FragmentInst;
+
+    fn instance_mut(&mut self, key: InstanceKey) -> &mut FragmentInst;
+
+    /// Provide `store` under the context `id` to the descendants of the instance.
+    /// Providing the same context again replaces the store.
+    fn provide(&mut self, key: InstanceKey, id: TypeId, store: StoreKey);
+
+    /// Look up the context `id`, starting at the instance and walking up towards the root.
+    fn lookup(&self, key: InstanceKey, id: TypeId) -> Option<StoreKey>;
+
+    /// The queue branch condition subscriptions push their holders into.
+    fn branch_queue(&self) -> BranchQueue;
+}
+
+#[derive(Default)]
+pub struct InstanceRuntimeImpl {
+    instances: Arena<FragmentInst>,
+    branch_queue: BranchQueue,
+}
+
+impl InstanceRuntimeImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of live instances.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Typed `provide`.
+    pub fn provide_context<K: ContextKey>(&mut self, key: InstanceKey, store: StoreKey) {
+        self.provide(key, TypeId::of::<K>(), store);
+    }
+
+    /// Typed `lookup`.
+    pub fn context<K: ContextKey>(&self, key: InstanceKey) -> Option<StoreKey> {
+        self.lookup(key, TypeId::of::<K>())
+    }
+}
+
+impl InstanceRuntime for InstanceRuntimeImpl {
+    fn create_instance(&mut self, ir: &'static FragmentIR, parent: Option<InstanceKey>) -> InstanceKey {
+        let key = self.instances.insert(FragmentInst {
+            parent,
+            ir,
+            stores: SmallVec::new(),
+            internal_stores: SmallVec::new(),
+            children: SmallVec::new(),
+            contexts: SmallVec::new(),
+            subscriptions: SmallVec::new(),
+            branch: None,
+            hidden: false,
+        });
+
+        if let Some(parent) = parent {
+            match self.instances.get_mut(parent) {
+                Some(parent) => parent.children.push(key),
+                None => panic!("attempt to create instance under non-existent parent"),
+            }
+        }
+
+        key
+    }
+
+    fn free_instance(&mut self, key: InstanceKey, stores: &mut StoreEffects) {
+        let Some(inst) = self.instances.remove(key) else { return };
+
+        for sub in inst.subscriptions {
+            stores.unsubscribe(sub);
+        }
+        for child in inst.children {
+            self.free_instance(child, stores);
+        }
+        for store in inst.internal_stores {
+            stores.free_store(store);
+        }
+
+        if let Some(parent) = inst.parent.and_then(|parent| self.instances.get_mut(parent)) {
+            parent.children.retain(|child| *child != key);
+        }
+    }
+
+    fn contains(&self, key: InstanceKey) -> bool {
+        self.instances.contains(key)
+    }
+
+    fn instance(&self, key: InstanceKey) -> &FragmentInst {
+        match self.instances.get(key) {
+            Some(inst) => inst,
+            None => panic!("attempt to access non-existent instance"),
+        }
+    }
+
+    fn instance_mut(&mut self, key: InstanceKey) -> &mut FragmentInst {
+        match self.instances.get_mut(key) {
+            Some(inst) => inst,
+            None => panic!("attempt to access non-existent instance"),
+        }
+    }
+
+    fn provide(&mut self, key: InstanceKey, id: TypeId, store: StoreKey) {
+        let inst = self.instance_mut(key);
+        match inst.contexts.iter_mut().find(|(context, _)| *context == id) {
+            Some(entry) => entry.1 = store,
+            None => inst.contexts.push((id, store)),
+        }
+    }
+
+    fn branch_queue(&self) -> BranchQueue {
+        self.branch_queue.clone()
+    }
+
+    fn lookup(&self, key: InstanceKey, id: TypeId) -> Option<StoreKey> {
+        let mut current = Some(key);
+        while let Some(key) = current {
+            let inst = self.instances.get(key)?;
+            if let Some((_, store)) = inst.contexts.iter().find(|(context, _)| *context == id) {
+                return Some(*store);
+            }
+            current = inst.parent;
+        }
+        None
+    }
+}
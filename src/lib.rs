@@ -1,2 +1,7 @@
 pub mod store;
-mod compiler;
+pub mod compiler;
+pub mod fir;
+pub mod instance;
+pub mod linker;
+pub mod art;
+pub mod value;
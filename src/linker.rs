@@ -0,0 +1,482 @@
+use std::any::{Any, TypeId};
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use smallvec::SmallVec;
+
+use crate::fir::*;
+use crate::instance::{Branch, BranchArm, InstanceKey, InstanceRuntime};
+use crate::store::{ConstBoxed, DerivedStore, EmittingStore, EqStrategy, StoreCallback, StoreEffects, StoreKey};
+
+/// An error of `link`. Errors in the FIR itself are framework errors, the linker panics on them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkError {
+    /// A context is used, but neither an ancestor of `parent` nor the linked fragments
+    /// provide it. Holds the type name of the context key.
+    ContextNotProvided(&'static str),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::ContextNotProvided(name) => write!(f, "context not provided: {name}"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Link `ir` into a new instance under `parent`. `args` are the external stores of the
+/// instance, in parameter order. Before creating anything, checks that every context used
+/// by the fragment, its children and its branch arms is provided.
+pub fn link(
+    ir: &'static FragmentIR,
+    args: &[StoreKey],
+    parent: Option<InstanceKey>,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) -> Result<InstanceKey, LinkError> {
+    let outer = |id| parent.is_some_and(|parent| instances.lookup(parent, id).is_some());
+    if let Some(context) = missing_context(ir, &outer) {
+        return Err(LinkError::ContextNotProvided((context.name)()));
+    }
+    Ok(instantiate(ir, args, parent, instances, stores))
+}
+
+fn instantiate(
+    ir: &'static FragmentIR,
+    args: &[StoreKey],
+    parent: Option<InstanceKey>,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) -> InstanceKey {
+    let key = instances.create_instance(ir, parent);
+    run(key, args, instances, stores);
+    key
+}
+
+// The first context used by `ir`, its children or its arms that neither `outer` nor the
+// fragments provide. A provide is visible to the uses after it in its instance and in the
+// children of the instance, as in `run`. Slot fills are not followed, the component
+// linking them may provide their contexts; OP_USE checks them when they are linked.
+fn missing_context(ir: &'static FragmentIR, outer: &dyn Fn(TypeId) -> bool) -> Option<&'static ContextDesc> {
+    // the contexts provided to the instance and to each open child
+    let mut levels: SmallVec<[SmallVec<[TypeId; 2]>; 8]> = SmallVec::new();
+    levels.push(SmallVec::new());
+
+    for (op, arg) in OpReader::new(ir.ops) {
+        match op {
+            OP_PROVIDE => {
+                let id = (ir.contexts[(arg >> 16) as usize].id)();
+                levels.last_mut().unwrap().push(id);
+            }
+            OP_USE => {
+                let context = &ir.contexts[arg as usize];
+                let id = (context.id)();
+                if !levels.iter().any(|level| level.contains(&id)) && !outer(id) {
+                    return Some(context);
+                }
+            }
+            OP_BEGIN | OP_ARM => {
+                let provided = |id: TypeId| levels.iter().any(|level| level.contains(&id)) || outer(id);
+                if let Some(context) = missing_context(ir.dependencies[arg as usize], &provided) {
+                    return Some(context);
+                }
+                // the nodes nested in the child are linked under it, they see what it provides
+                if op == OP_BEGIN {
+                    levels.push(own_contexts(ir.dependencies[arg as usize]));
+                }
+            }
+            OP_END => {
+                levels.pop();
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The contexts the fragment provides on its own instance, not on its children.
+fn own_contexts(ir: &'static FragmentIR) -> SmallVec<[TypeId; 2]> {
+    let mut contexts = SmallVec::new();
+    let mut depth = 0;
+    for (op, arg) in OpReader::new(ir.ops) {
+        match op {
+            OP_BEGIN => depth += 1,
+            OP_END => depth -= 1,
+            OP_PROVIDE if depth == 0 => contexts.push((ir.contexts[(arg >> 16) as usize].id)()),
+            _ => {}
+        }
+    }
+    contexts
+}
+
+// A child instance between OP_BEGIN and OP_END. Its own ops run when its arguments
+// are complete, that is at the first instruction that is not an argument.
+struct OpenChild {
+    key: InstanceKey,
+    args: SmallVec<[StoreKey; 4]>,
+    linked: bool,
+}
+
+// A branch between OP_BRANCH and OP_BRANCH_END, collecting its arms.
+struct OpenBranch {
+    holder: InstanceKey,
+    condition: StoreKey,
+    arms: SmallVec<[BranchArm; 2]>,
+}
+
+// The holder of a conditional subtree, it has no ops of its own.
+static BRANCH: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 0,
+    own_store_count: 0,
+    resources: &[],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[],
+};
+
+// Execute the ops of the instance.
+fn run(key: InstanceKey, args: &[StoreKey], instances: &mut dyn InstanceRuntime, stores: &mut StoreEffects) {
+    let ir = instances.instance(key).ir;
+    instances.instance_mut(key).stores.extend_from_slice(args);
+
+    let mut open: SmallVec<[OpenChild; 8]> = SmallVec::new();
+    let mut branch: Option<OpenBranch> = None;
+
+    for (op, arg) in OpReader::new(ir.ops) {
+        if let Some(branch) = &mut branch && op != OP_ARM && op != OP_BRANCH_END {
+            if op != OP_ARG_PASS || branch.arms.is_empty() {
+                panic!("FIR: opcode {op} inside of a branch");
+            }
+            let store = store_at(instances, key, arg);
+            branch.arms.last_mut().unwrap().args.push(store);
+            continue;
+        }
+
+        if (OP_ARG_PASS..=OP_ARG_EH).contains(&op) || op == OP_ARG_SLOT {
+            let store = match op {
+                OP_ARG_PASS => store_at(instances, key, arg),
+                OP_ARG_CONST => create_store(OP_CONST, arg, key, instances, stores),
+                OP_ARG_READABLE => create_store(OP_READABLE, arg, key, instances, stores),
+                OP_ARG_DERIVED => create_store(OP_DERIVED, arg, key, instances, stores),
+                OP_ARG_WRITABLE => create_store(OP_WRITABLE, arg, key, instances, stores),
+                OP_ARG_SLOT => bind_slot(&ir.slots[arg as usize], key, instances, stores),
+                _ => bind_handler(&ir.events_handlers[arg as usize], key, instances, stores),
+            };
+            match open.last_mut() {
+                Some(child) if !child.linked => child.args.push(store),
+                _ => panic!("FIR: argument outside of a fragment instance"),
+            }
+            continue;
+        }
+
+        // the arguments of the innermost child are complete
+        if let Some(child) = open.last_mut() && !child.linked {
+            child.linked = true;
+            run(child.key, &child.args, instances, stores);
+        }
+
+        let current = open.last().map_or(key, |child| child.key);
+
+        match op {
+            OP_VERSION => {
+                if arg != FIR_VERSION {
+                    panic!("FIR: unsupported version {arg}");
+                }
+            }
+
+            OP_CONST | OP_READABLE | OP_DERIVED | OP_WRITABLE => {
+                let store = create_store(op, arg, key, instances, stores);
+                instances.instance_mut(key).stores.push(store);
+            }
+
+            OP_BEGIN => {
+                let child = instances.create_instance(ir.dependencies[arg as usize], Some(current));
+                open.push(OpenChild { key: child, args: SmallVec::new(), linked: false });
+            }
+
+            OP_END => {
+                if open.pop().is_none() {
+                    panic!("FIR: OP_END without OP_BEGIN");
+                }
+            }
+
+            OP_PROVIDE => {
+                let store = store_at(instances, key, arg & 0xffff);
+                let context = &ir.contexts[(arg >> 16) as usize];
+                instances.provide(current, (context.id)(), store);
+            }
+
+            OP_USE => {
+                let context = &ir.contexts[arg as usize];
+                let Some(store) = instances.lookup(current, (context.id)()) else {
+                    panic!("context not provided: {}", (context.name)());
+                };
+                // not internal: the store belongs to the provider
+                instances.instance_mut(key).stores.push(store);
+            }
+
+            OP_SLOT => {
+                let store = store_at(instances, key, arg);
+                let Some(slot) = stores.get_value(store).downcast_ref::<BoundSlot>() else {
+                    panic!("FIR: store {arg} is not a slot");
+                };
+                let slot = slot.clone();
+                instantiate(slot.ir, &slot.stores, Some(current), instances, stores);
+            }
+
+            OP_BRANCH => {
+                let holder = instances.create_instance(&BRANCH, Some(current));
+                let condition = store_at(instances, key, arg);
+                branch = Some(OpenBranch { holder, condition, arms: SmallVec::new() });
+            }
+
+            OP_ARM => match &mut branch {
+                Some(branch) => branch.arms.push(BranchArm { ir: ir.dependencies[arg as usize], args: SmallVec::new() }),
+                None => panic!("FIR: OP_ARM without OP_BRANCH"),
+            },
+
+            OP_BRANCH_END => {
+                let Some(OpenBranch { holder, condition, arms }) = branch.take() else {
+                    panic!("FIR: OP_BRANCH_END without OP_BRANCH");
+                };
+                mount_branch(holder, condition, arms, arg != 0, instances, stores);
+            }
+
+            _ => panic!("FIR: unsupported opcode {op}"),
+        }
+    }
+
+    if !open.is_empty() {
+        panic!("FIR: OP_BEGIN without OP_END");
+    }
+    if branch.is_some() {
+        panic!("FIR: OP_BRANCH without OP_BRANCH_END");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Branches
+// ---------------------------------------------------------------------------
+
+// Set up the branch in its holder and mount the selected arm. The condition subscription
+// only queues the holder, the arms are switched by `update_branches`.
+fn mount_branch(
+    holder: InstanceKey,
+    condition: StoreKey,
+    arms: SmallVec<[BranchArm; 2]>,
+    keep: bool,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) {
+    let queue = instances.branch_queue();
+    let cb: StoreCallback = Rc::new(move |_store, _sub, _rt: &mut StoreEffects| {
+        queue.borrow_mut().push(holder);
+    });
+    let sub = stores.subscribe(condition, cb);
+
+    let inst = instances.instance_mut(holder);
+    inst.subscriptions.push(sub);
+    inst.branch = Some(Branch {
+        condition,
+        mounted: arms.iter().map(|_| None).collect(),
+        arms,
+        keep,
+        selected: None,
+    });
+
+    switch_branch(holder, instances, stores);
+}
+
+/// Mount the arms selected by the branch conditions that changed since the last call.
+/// Call it after draining the store notifications.
+pub fn update_branches(instances: &mut dyn InstanceRuntime, stores: &mut StoreEffects) {
+    let queue = instances.branch_queue();
+    loop {
+        let Some(holder) = queue.borrow_mut().pop() else { break };
+        // an enclosing branch may have unmounted the holder in the meantime
+        if instances.contains(holder) {
+            switch_branch(holder, instances, stores);
+        }
+    }
+}
+
+// Unmount the previously selected arm, freeing it or hiding it when the branch keeps
+// its arms, then mount the selected one, showing the kept instance if there is one.
+fn switch_branch(holder: InstanceKey, instances: &mut dyn InstanceRuntime, stores: &mut StoreEffects) {
+    let Some(mut branch) = instances.instance_mut(holder).branch.take() else {
+        panic!("attempt to switch an instance that is not a branch");
+    };
+
+    let selected = arm_index(stores.get_value(branch.condition), branch.arms.len());
+
+    if selected != branch.selected {
+        if let Some(old) = branch.selected && let Some(inst) = branch.mounted[old] {
+            if branch.keep {
+                instances.instance_mut(inst).hidden = true;
+            } else {
+                instances.free_instance(inst, stores);
+                branch.mounted[old] = None;
+            }
+        }
+
+        if let Some(new) = selected {
+            match branch.mounted[new] {
+                Some(inst) => instances.instance_mut(inst).hidden = false,
+                None => {
+                    let arm = &branch.arms[new];
+                    branch.mounted[new] = Some(instantiate(arm.ir, &arm.args, Some(holder), instances, stores));
+                }
+            }
+        }
+
+        branch.selected = selected;
+    }
+
+    instances.instance_mut(holder).branch = Some(branch);
+}
+
+// The arm a condition value selects: a `bool` selects the first (`true`) or the second
+// arm, a `u32` the arm with that index. An index past the last arm selects none.
+fn arm_index(value: &dyn Any, count: usize) -> Option<usize> {
+    let index = if let Some(value) = value.downcast_ref::<bool>() {
+        if *value { 0 } else { 1 }
+    } else if let Some(value) = value.downcast_ref::<u32>() {
+        *value as usize
+    } else {
+        panic!("branch condition must be a `bool` or `u32` store");
+    };
+    (index < count).then_some(index)
+}
+
+/// An event handler bound to the stores of an instance. The linker passes it to the
+/// child fragment in a const store, see `call_handler`.
+#[derive(Clone)]
+pub struct BoundHandler {
+    handler: HandlerFn,
+    stores: SmallVec<[StoreKey; 4]>,
+}
+
+impl BoundHandler {
+    pub fn call(&self, rt: &mut StoreEffects) {
+        (self.handler)(&self.stores, rt);
+    }
+}
+
+/// Call the handler held by the const store `key`, event dispatch uses this.
+pub fn call_handler(key: StoreKey, rt: &mut StoreEffects) {
+    let Some(handler) = rt.get_value(key).downcast_ref::<BoundHandler>() else {
+        panic!("attempt to call a store that is not an event handler");
+    };
+    handler.clone().call(rt);
+}
+
+// Bind the handler to the stores of the instance, the const store holding it is owned by the instance.
+fn bind_handler(
+    desc: &EventHandlerDesc,
+    key: InstanceKey,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) -> StoreKey {
+    let bound = BoundHandler {
+        handler: desc.handler,
+        stores: desc.uses.iter().map(|index| store_at(instances, key, *index as u32)).collect(),
+    };
+    let store = stores.alloc_store(Box::new(ConstBoxed::new(Box::new(bound))));
+    instances.instance_mut(key).internal_stores.push(store);
+    store
+}
+
+/// A slot fill bound to the stores of the passing instance. The linker passes it to the
+/// child fragment in a const store, `OP_SLOT` instantiates it.
+#[derive(Clone)]
+pub struct BoundSlot {
+    ir: &'static FragmentIR,
+    stores: SmallVec<[StoreKey; 4]>,
+}
+
+// Bind the slot fill to the stores of the instance, the const store holding it is owned by the instance.
+fn bind_slot(
+    desc: &SlotDesc,
+    key: InstanceKey,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) -> StoreKey {
+    let bound = BoundSlot {
+        ir: instances.instance(key).ir.dependencies[desc.fragment as usize],
+        stores: desc.uses.iter().map(|index| store_at(instances, key, *index as u32)).collect(),
+    };
+    let store = stores.alloc_store(Box::new(ConstBoxed::new(Box::new(bound))));
+    instances.instance_mut(key).internal_stores.push(store);
+    store
+}
+
+fn store_at(instances: &dyn InstanceRuntime, key: InstanceKey, index: u32) -> StoreKey {
+    match instances.instance(key).stores.get(index as usize) {
+        Some(store) => *store,
+        None => panic!("FIR: store index {index} out of range"),
+    }
+}
+
+// Create a store with one of the OP_CONST .. OP_WRITABLE opcodes, owned by the instance.
+fn create_store(
+    op: u8,
+    arg: u32,
+    key: InstanceKey,
+    instances: &mut dyn InstanceRuntime,
+    stores: &mut StoreEffects,
+) -> StoreKey {
+    let ir = instances.instance(key).ir;
+
+    let store = match op {
+        OP_CONST => stores.alloc_store(Box::new(ConstBoxed::new((ir.resources[arg as usize])()))),
+        OP_READABLE | OP_WRITABLE => {
+            let value = (ir.resources[(arg & 0xffff) as usize])();
+            let eq = match arg >> 16 {
+                0 => EqStrategy::Always,
+                eq => ir.eq_strategies[eq as usize - 1],
+            };
+            stores.alloc_store(Box::new(EmittingStore::new(value, eq)))
+        }
+        OP_DERIVED => {
+            let desc = &ir.derived_handlers[arg as usize];
+            let uses: Rc<[StoreKey]> = desc.uses.iter().map(|index| store_at(instances, key, *index as u32)).collect();
+            derived(desc, uses, stores)
+        }
+        _ => unreachable!(),
+    };
+
+    instances.instance_mut(key).internal_stores.push(store);
+    store
+}
+
+fn derived(desc: &'static DeriveDesc, uses: Rc<[StoreKey]>, stores: &mut StoreEffects) -> StoreKey {
+    let key: Rc<Cell<Option<StoreKey>>> = Rc::new(Cell::new(None));
+    let key_for_cb = key.clone();
+    let uses_for_cb = uses.clone();
+
+    let cb: StoreCallback = Rc::new(move |_store, _sub, rt: &mut StoreEffects| {
+        let value = derive(desc, &uses_for_cb, rt);
+        if let Some(k) = key_for_cb.get() {
+            rt.set_value(k, value);
+        }
+    });
+
+    let initial = derive(desc, &uses, stores);
+    let store = DerivedStore::new(initial, desc.eq, cb, uses.iter().copied(), stores);
+    let k = stores.alloc_store(Box::new(store));
+    key.set(Some(k));
+    k
+}
+
+fn derive(desc: &DeriveDesc, uses: &[StoreKey], stores: &StoreEffects) -> Box<dyn Any> {
+    let values: SmallVec<[&dyn Any; 4]> = uses.iter().map(|key| stores.get_value(*key)).collect();
+    (desc.derive)(&values)
+}
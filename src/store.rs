@@ -1,8 +1,18 @@
 use std::any::Any;
+use std::cell::Cell;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 use smallvec::SmallVec;
 use thunderdome::{Arena, Index};
 
+pub mod scope;
+pub mod time;
+
+use scope::{ScopeKey, ScopeStats, StoreScope, remove_key};
+use time::{Clock, SystemClock};
+
 pub type StoreKey = Index;
 pub type SubscriptionKey = Index;
 
@@ -14,11 +24,40 @@ pub type StoreCallback = Rc<dyn Fn(StoreKey, SubscriptionKey, &mut StoreEffects)
 
 pub struct StoreSubscription {
     store: StoreKey,
+    seq: u64, // creation sequence number, used by deterministic ordering
+    lane: Lane,
+    idle_queued: bool, // already in the idle queue, idle notifications are deduplicated until processed
+    scope: Option<ScopeKey>, // the scope current when the subscription was created
     callback: StoreCallback
 }
 
+/// Priority class of a subscription. Each lane has its own queue, so heavy work
+/// does not delay latency-sensitive updates such as the text caret.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lane {
+    /// Processed first by `drain_notifications`, cascades are drained before any render work.
+    Sync,
+    /// Processed by `drain_notifications` when the sync lane is empty.
+    Render,
+    /// Processed only by `drain_idle`, in time slices.
+    Idle,
+}
+
+pub type TimerKey = Index;
+
+pub type TimerCallback = Rc<dyn Fn(StoreKey, &mut StoreEffects)>;
+
+pub struct StoreTimer {
+    store: StoreKey,
+    deadline: Duration,
+    callback: TimerCallback
+}
+
 const GEN_LIMIT: u64 = 1000;
 
+// hard cap on all generations of a drain, scoped ones included
+const DRAIN_LIMIT: u64 = 100 * GEN_LIMIT;
+
 /// The `Store` trait defines a generic interface for a storage mechanism that can hold and manage
 /// dynamically typed data (`dyn Any`). It provides methods for reading, writing, and managing subscriptions.
 /// It also offers a utility method to retrieve a debug/type name for diagnostic or tooling purposes.
@@ -29,6 +68,14 @@ pub trait Store {
     /// Untyped write; macro-generated code performs checked downcast.
     fn set_any(&mut self, value: Box<dyn Any>, sink: &mut SubSink);
 
+    /// Untyped in-place update. The function returns true when it has changed the value;
+    /// only then are the subscribers notified. Stores that do not support in-place
+    /// updates panic.
+    fn update_any(&mut self, f: &mut dyn FnMut(&mut dyn Any) -> bool, sink: &mut SubSink) {
+        let _ = (f, sink);
+        panic!("attempt to update a store that does not support in-place updates: {}", self.debug_name())
+    }
+
     /// Register a listener. When returns with true, the store has been changed
     /// in this generation; therefore, the key should be added to the pending queue.
     fn subscribe(&mut self, key: SubscriptionKey, generation : StoreGeneration) -> bool;
@@ -55,53 +102,316 @@ pub trait StoreRuntime {
     /// Remove a store.
     fn free_store(&mut self, key: StoreKey);
 
-    /// Subscribe to a store.
-    fn subscribe(&mut self, key: StoreKey, cb: StoreCallback) -> SubscriptionKey;
+    /// Subscribe to a store in the sync lane.
+    fn subscribe(&mut self, key: StoreKey, cb: StoreCallback) -> SubscriptionKey {
+        self.subscribe_in(key, Lane::Sync, cb)
+    }
+
+    /// Subscribe to a store in the given lane.
+    fn subscribe_in(&mut self, key: StoreKey, lane: Lane, cb: StoreCallback) -> SubscriptionKey;
 
     /// Unsubscribe from a store.
     fn unsubscribe(&mut self, key: SubscriptionKey) -> bool;
 
-    /// Erased write. Store appends subs into the sink; we enqueue those notifications
+    /// Erased read.
+    fn get_value(&self, store_key: StoreKey) -> &dyn Any;
+
+    /// Erased write. Store appends subs into the sink which writes directly
     /// into `pending`.
     fn set_value(&mut self, store_key: StoreKey, value: Box<dyn Any>);
 
-    /// Drain the pending notifications and invoke callbacks.
+    /// Erased in-place update. Works like `set_value`, but the store notifies only
+    /// when `f` returns with true.
+    fn update_value(&mut self, store_key: StoreKey, f: &mut dyn FnMut(&mut dyn Any) -> bool);
+
+    /// Drain the pending notifications of the sync and render lanes and invoke callbacks.
     fn drain_notifications(&mut self);
+
+    /// The current generation. Changes made now are notified in the next one.
+    fn generation(&self) -> StoreGeneration;
+
+    /// Invoke the callbacks of the idle lane until the queue is empty or `budget` (measured
+    /// with the runtime clock) is spent. Pending sync and render work is drained first, and
+    /// the work caused by an idle callback right after the callback. Returns true if idle
+    /// work remains.
+    fn drain_idle(&mut self, budget: Duration) -> bool;
+
+    /// Current time of the runtime clock.
+    fn now(&self) -> Duration;
+
+    /// Call `cb` when the clock reaches `deadline`. The timer belongs to the store,
+    /// freeing the store cancels the timer.
+    fn schedule(&mut self, store_key: StoreKey, deadline: Duration, cb: TimerCallback) -> TimerKey;
+
+    /// Cancel a timer. Returns false if the timer has already fired or has been cancelled.
+    fn cancel(&mut self, key: TimerKey) -> bool;
+
+    /// The earliest deadline of the scheduled timers. The event loop of the adapter
+    /// should call `run_timers` (and then `drain_notifications`) when it is reached.
+    fn next_deadline(&self) -> Option<Duration>;
+
+    /// Call the callbacks of the timers whose deadline has been reached. Timers
+    /// scheduled by these callbacks are not called, even if they are due.
+    fn run_timers(&mut self);
+
+    /// Create a scope with its own generation limit, as a child of the current scope.
+    /// Stores and subscriptions allocated while the scope is current belong to it.
+    fn create_scope(&mut self, gen_limit: u64) -> ScopeKey;
+
+    /// Free the scope, its child scopes and everything allocated in them.
+    fn free_scope(&mut self, key: ScopeKey);
+
+    /// Set the current scope (`None` is the root), returns the previous one. Callbacks run
+    /// with the scope of their subscription, timer callbacks with the scope of their store.
+    fn set_scope(&mut self, key: Option<ScopeKey>) -> Option<ScopeKey>;
+
+    /// Statistics of the scope, `None` if it has been freed.
+    fn scope_stats(&self, key: ScopeKey) -> Option<ScopeStats>;
 }
 
 // ---------------------------------------------------------------------------
 // Runtime implementation
 // ---------------------------------------------------------------------------
 
+// A store, the scope it has been allocated in and its scheduled timers.
+struct StoreEntry {
+    store: Box<dyn Store>,
+    scope: Option<ScopeKey>,
+    timers: Vec<TimerKey>
+}
+
 pub struct StoreRuntimeImpl {
-    stores: Arena<Box<dyn Store>>,
+    stores: Arena<StoreEntry>,
     subscriptions: Arena<StoreSubscription>,
-    pending: Vec<SubscriptionKey>,
+    timers: Arena<StoreTimer>,
+    scopes: Arena<StoreScope>,
+    current_scope: Option<ScopeKey>,
+    drain_id: u64, // identifies the drain the per-scope generation counters belong to
+    clock: Box<dyn Clock>,
+    pending: Vec<SubscriptionKey>, // notifications not routed into lanes yet
+    sync: Vec<SubscriptionKey>,
+    render: Vec<SubscriptionKey>,
+    idle: Vec<SubscriptionKey>,
+    spare: Vec<SubscriptionKey>, // the buffer of the previous batch, reused to avoid allocation in drain
     generation : StoreGeneration,
+    next_seq: u64,
+    deterministic: bool,
     is_draining: bool
 }
 
+impl Default for StoreRuntimeImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StoreRuntimeImpl {
     pub fn new() -> Self {
+        Self::with_clock(Box::new(SystemClock::new()))
+    }
+
+    /// Create a runtime that uses `clock` for timers, tests use `ManualClock`.
+    pub fn with_clock(clock: Box<dyn Clock>) -> Self {
         Self {
             stores: Arena::new(),
             subscriptions: Arena::new(),
+            timers: Arena::new(),
+            scopes: Arena::new(),
+            current_scope: None,
+            drain_id: 0,
+            clock,
             pending: Vec::new(),
+            sync: Vec::new(),
+            render: Vec::new(),
+            idle: Vec::new(),
+            spare: Vec::new(),
             generation : 0,
+            next_seq: 0,
+            deterministic: false,
             is_draining: false
         }
     }
+
+    /// When set, the callbacks of each generation are called in the order the subscriptions
+    /// were created, so replayed sessions produce identical traces. Off by default, as the
+    /// design does not need ordering and sorting each batch has a cost.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Number of allocated stores.
+    pub fn store_count(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Number of live subscriptions.
+    pub fn subscription_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    /// Number of scheduled timers.
+    pub fn timer_count(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Number of live scopes.
+    pub fn scope_count(&self) -> usize {
+        self.scopes.len()
+    }
+
+    // Sort the keys by subscription creation sequence, keys of removed subscriptions
+    // are skipped during processing anyway.
+    fn sort_batch(&self, batch: &mut [SubscriptionKey]) {
+        batch.sort_unstable_by_key(|key| self.subscriptions.get(*key).map(|sub| sub.seq));
+    }
+}
+
+impl StoreRuntimeImpl {
+    /// True if there are idle notifications waiting for `drain_idle`.
+    pub fn has_idle_work(&self) -> bool {
+        !self.idle.is_empty()
+    }
+
+    // Move the pending notifications into the queues of their lanes.
+    fn route_pending(&mut self) {
+        for key in self.pending.drain(..) {
+            // unsubscribed since queued
+            let Some(sub) = self.subscriptions.get_mut(key) else { continue };
+            match sub.lane {
+                Lane::Sync => self.sync.push(key),
+                Lane::Render => self.render.push(key),
+                Lane::Idle => {
+                    if !sub.idle_queued {
+                        sub.idle_queued = true;
+                        self.idle.push(key);
+                    }
+                }
+            }
+        }
+    }
+
+    // Drain the sync and render lanes, sync first: each generation processes the whole
+    // sync queue if it is not empty, the render queue otherwise.
+    fn drain_lanes(&mut self) {
+        self.drain_id += 1;
+
+        // generations that have called unscoped callbacks, scopes count their own
+        let mut root_generations = 0;
+        let mut generations = 0;
+
+        loop {
+            self.route_pending();
+
+            let lane = if !self.sync.is_empty() {
+                Lane::Sync
+            } else if !self.render.is_empty() {
+                Lane::Render
+            } else {
+                break;
+            };
+
+            // sanity check: if we don't finish the drain in GEN_LIMIT generations, something is wrong
+            if root_generations > GEN_LIMIT {
+                panic!("store runtime: single drain generation limit has been exceeded");
+            }
+            // scope limits may be arbitrarily large, a cycle in a scope still has to stop
+            if generations > DRAIN_LIMIT {
+                panic!("store runtime: hard drain generation limit has been exceeded");
+            }
+            generations += 1;
+
+            // Take the current batch; allow callbacks to enqueue more work for *next* generation
+            let spare = std::mem::take(&mut self.spare);
+            let queue = if lane == Lane::Sync { &mut self.sync } else { &mut self.render };
+            let mut batch = std::mem::replace(queue, spare);
+
+            if self.deterministic { self.sort_batch(&mut batch); }
+
+            // Increase generation, so callbacks can add other work for *next* generation
+            // We are confined to a single-threaded runtime, so this is safe.
+            self.generation += 1;
+
+            let mut root_called = false;
+
+            for &key in &batch {
+                let Some(sub) = self.subscriptions.get(key) else { continue };
+                let (store, scope, callback) = (sub.store, sub.scope, sub.callback.clone());
+
+                match scope {
+                    None => root_called = true,
+                    // a scope that has exceeded its limit is cut off for the rest of the drain
+                    Some(scope) => if !self.admit(scope) { continue },
+                }
+
+                let previous = std::mem::replace(&mut self.current_scope, scope);
+                callback(store, key, self);
+                self.current_scope = previous;
+            }
+
+            if root_called { root_generations += 1; }
+
+            batch.clear();
+            self.spare = batch;
+        }
+    }
+
+    // Count the current generation for the scope, false if the notification has to be dropped.
+    fn admit(&mut self, key: ScopeKey) -> bool {
+        match self.scopes.get_mut(key) {
+            Some(scope) => scope.admit(self.drain_id, self.generation),
+            None => true,
+        }
+    }
+
+    // Remove the timer from the runtime and from the timers of its store.
+    fn remove_timer(&mut self, key: TimerKey) -> Option<StoreTimer> {
+        let timer = self.timers.remove(key)?;
+        if let Some(entry) = self.stores.get_mut(timer.store) {
+            remove_key(&mut entry.timers, key);
+        }
+        Some(timer)
+    }
+
+    // The current scope for an allocation, the scope must still be alive.
+    fn live_scope(&self) -> Option<ScopeKey> {
+        if let Some(key) = self.current_scope && !self.scopes.contains(key) {
+            panic!("attempt to allocate in a freed scope");
+        }
+        self.current_scope
+    }
+
+    fn apply_sink(&mut self, sink: SubSink) {
+        // the sink has been writing into the pending queue, give it back to the runtime
+        self.pending = sink.local;
+
+        // writes forwarded by lens stores to their parents
+        for write in sink.forwarded {
+            let mut value = Some(write.value);
+            self.update_value(write.parent, &mut |parent| {
+                if let Some(value) = value.take() { (write.set)(parent, value); }
+                true
+            });
+        }
+    }
 }
 
 impl StoreRuntime for StoreRuntimeImpl {
 
     #[inline]
     fn alloc_store(&mut self, s: Box<dyn Store>) -> StoreKey {
-        self.stores.insert(s)
+        let scope = self.live_scope();
+        let key = self.stores.insert(StoreEntry { store: s, scope, timers: Vec::new() });
+        if let Some(scope) = scope {
+            self.scopes[scope].stores.push(key);
+        }
+        key
     }
 
     fn free_store(&mut self, key: StoreKey) {
-        if let Some(store) = self.stores.remove(key) {
+        if let Some(StoreEntry { store, scope, timers }) = self.stores.remove(key) {
+            if let Some(scope) = scope.and_then(|scope| self.scopes.get_mut(scope)) {
+                remove_key(&mut scope.stores, key);
+            }
             // remove subscriptions this store has to other stores
             for sub in store.dependencies().unwrap_or_default() {
                 self.unsubscribe(*sub);
@@ -110,13 +420,23 @@ impl StoreRuntime for StoreRuntimeImpl {
             for sub in store.subscriptions().unwrap_or_default() {
                 self.unsubscribe(*sub);
             }
+            // remove timers of this store
+            for timer in timers {
+                self.timers.remove(timer);
+            }
         }
     }
 
-    fn subscribe(&mut self, key: StoreKey, cb: StoreCallback) -> SubscriptionKey {
-        if let Some(store) = self.stores.get_mut(key) {
-            let sub = self.subscriptions.insert(StoreSubscription { store: key, callback: cb });
-            let current = store.subscribe(sub, self.generation);
+    fn subscribe_in(&mut self, key: StoreKey, lane: Lane, cb: StoreCallback) -> SubscriptionKey {
+        let scope = self.live_scope();
+        if let Some(entry) = self.stores.get_mut(key) {
+            let seq = self.next_seq;
+            self.next_seq += 1;
+            let sub = self.subscriptions.insert(StoreSubscription { store: key, seq, lane, idle_queued: false, scope, callback: cb });
+            if let Some(scope) = scope {
+                self.scopes[scope].subscriptions.push(sub);
+            }
+            let current = entry.store.subscribe(sub, self.generation);
             if current { self.pending.push(sub); } // the store has changed in this generation, so we must enqueue the notification
             sub
         } else {
@@ -126,8 +446,11 @@ impl StoreRuntime for StoreRuntimeImpl {
 
     fn unsubscribe(&mut self, key: SubscriptionKey) -> bool {
         if let Some(sub) = self.subscriptions.remove(key) {
-            if let Some(store) = self.stores.get_mut(sub.store) {
-                store.unsubscribe(key);
+            if let Some(scope) = sub.scope.and_then(|scope| self.scopes.get_mut(scope)) {
+                remove_key(&mut scope.subscriptions, key);
+            }
+            if let Some(entry) = self.stores.get_mut(sub.store) {
+                entry.store.unsubscribe(key);
                 true
             } else {
                 false
@@ -137,19 +460,32 @@ impl StoreRuntime for StoreRuntimeImpl {
         }
     }
 
-    fn set_value(&mut self, store_key: StoreKey, value: Box<dyn Any>) {
-        // local staging buffer for *this call*; avoids borrowing runtime during store logic
-        let mut sink = SubSink::new(self.generation);
+    fn get_value(&self, store_key: StoreKey) -> &dyn Any {
+        if let Some(entry) = self.stores.get(store_key) {
+            entry.store.get_any()
+        } else {
+            panic!("attempt to read non-existent store");
+        }
+    }
 
-        if let Some(store) = self.stores.get_mut(store_key) {
-            store.set_any(value, &mut sink);
+    fn set_value(&mut self, store_key: StoreKey, value: Box<dyn Any>) {
+        if let Some(entry) = self.stores.get_mut(store_key) {
+            // the sink takes over the pending queue for *this call*; avoids borrowing runtime during store logic
+            let mut sink = SubSink::new(self.generation, std::mem::take(&mut self.pending));
+            entry.store.set_any(value, &mut sink);
+            self.apply_sink(sink);
         } else {
             panic!("attempt to write non-existent store");
         }
+    }
 
-        if !sink.is_empty() {
-            // append staged entries to the runtime's pending queue
-            self.pending.extend(sink.local);
+    fn update_value(&mut self, store_key: StoreKey, f: &mut dyn FnMut(&mut dyn Any) -> bool) {
+        if let Some(entry) = self.stores.get_mut(store_key) {
+            let mut sink = SubSink::new(self.generation, std::mem::take(&mut self.pending));
+            entry.store.update_any(f, &mut sink);
+            self.apply_sink(sink);
+        } else {
+            panic!("attempt to update non-existent store");
         }
     }
 
@@ -159,30 +495,139 @@ impl StoreRuntime for StoreRuntimeImpl {
         assert!(!self.is_draining);
         self.is_draining = true;
 
-        let start_generation = self.generation;
+        self.drain_lanes();
 
-        while !self.pending.is_empty() {
-            // sanity check: if we don't finish the drain in GEN_LIMIT generations, something is wrong
-            if self.generation > start_generation + GEN_LIMIT {
-                panic!("store runtime: single drain generation limit has been exceeded");
-            }
+        self.is_draining = false;
+    }
 
-            // Take the current batch; allow callbacks to enqueue more work for *next* generation
-            let batch = std::mem::take(&mut self.pending);
+    fn generation(&self) -> StoreGeneration {
+        self.generation
+    }
+
+    fn drain_idle(&mut self, budget: Duration) -> bool {
+
+        assert!(!self.is_draining);
+        self.is_draining = true;
+
+        let deadline = self.clock.now() + budget;
+
+        // sync and render work queued before the call goes first, it also routes the idle work
+        self.drain_lanes();
+
+        let mut batch = std::mem::take(&mut self.idle);
+        let mut processed = 0;
+
+        if self.deterministic { self.sort_batch(&mut batch); }
+
+        for &key in &batch {
+            if self.clock.now() >= deadline { break; }
+            processed += 1;
+
+            let Some(sub) = self.subscriptions.get_mut(key) else { continue };
+            sub.idle_queued = false;
+            let (store, scope, callback) = (sub.store, sub.scope, sub.callback.clone());
 
-            // Increase generation, so callbacks can add other work for *next* generation
-            // We are confined to a single-threaded runtime, so this is safe.
             self.generation += 1;
+            let previous = std::mem::replace(&mut self.current_scope, scope);
+            callback(store, key, self);
+            self.current_scope = previous;
 
-            for key in batch {
-                if let Some(sub) = self.subscriptions.get_mut(key) {
-                    let callback = sub.callback.clone();
-                    callback(sub.store, key, self);
-                }
-            }            
+            // the caret must not wait for the rest of the idle work
+            self.drain_lanes();
         }
 
+        // unprocessed work goes before the work queued by the processed callbacks
+        batch.drain(..processed);
+        batch.append(&mut self.idle);
+        self.idle = batch;
+
         self.is_draining = false;
+
+        !self.idle.is_empty()
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn schedule(&mut self, store_key: StoreKey, deadline: Duration, cb: TimerCallback) -> TimerKey {
+        if !self.stores.contains(store_key) {
+            panic!("attempt to schedule timer for non-existent store");
+        }
+        let key = self.timers.insert(StoreTimer { store: store_key, deadline, callback: cb });
+        self.stores[store_key].timers.push(key);
+        key
+    }
+
+    fn cancel(&mut self, key: TimerKey) -> bool {
+        self.remove_timer(key).is_some()
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.timers.iter().map(|(_, timer)| timer.deadline).min()
+    }
+
+    fn run_timers(&mut self) {
+        assert!(!self.is_draining);
+
+        let now = self.clock.now();
+
+        let mut due: SmallVec<[(Duration, TimerKey); 8]> = self.timers.iter()
+            .filter(|(_, timer)| timer.deadline <= now)
+            .map(|(key, timer)| (timer.deadline, key))
+            .collect();
+
+        // earlier deadlines first, so the order of values follows the clock
+        due.sort_by_key(|(deadline, _)| *deadline);
+
+        for (_, key) in due {
+            // a callback may have cancelled a timer that is due
+            if let Some(timer) = self.remove_timer(key) {
+                let scope = self.stores.get(timer.store).and_then(|entry| entry.scope);
+                let previous = std::mem::replace(&mut self.current_scope, scope);
+                (timer.callback)(timer.store, self);
+                self.current_scope = previous;
+            }
+        }
+    }
+
+    fn create_scope(&mut self, gen_limit: u64) -> ScopeKey {
+        let parent = self.live_scope();
+        let key = self.scopes.insert(StoreScope::new(parent, gen_limit));
+        if let Some(parent) = parent {
+            self.scopes[parent].children.push(key);
+        }
+        key
+    }
+
+    fn free_scope(&mut self, key: ScopeKey) {
+        let Some(scope) = self.scopes.remove(key) else { return };
+
+        for child in scope.children {
+            self.free_scope(child);
+        }
+        // the scope is not in the arena anymore, so these do not touch its lists
+        for sub in scope.subscriptions {
+            self.unsubscribe(sub);
+        }
+        for store in scope.stores {
+            self.free_store(store);
+        }
+
+        if let Some(parent) = scope.parent.and_then(|parent| self.scopes.get_mut(parent)) {
+            parent.children.retain(|child| *child != key);
+        }
+    }
+
+    fn set_scope(&mut self, key: Option<ScopeKey>) -> Option<ScopeKey> {
+        if let Some(key) = key && !self.scopes.contains(key) {
+            panic!("attempt to enter non-existent scope");
+        }
+        std::mem::replace(&mut self.current_scope, key)
+    }
+
+    fn scope_stats(&self, key: ScopeKey) -> Option<ScopeStats> {
+        self.scopes.get(key).map(StoreScope::stats)
     }
 }
 
@@ -191,25 +636,35 @@ impl StoreRuntime for StoreRuntimeImpl {
 // ---------------------------------------------------------------------------
 
 // A concrete sink the runtime uses during set_any: enqueues (store, sub) pairs.
+// `local` is the pending queue of the runtime, taken over for the duration of the call.
 pub struct SubSink {
     generation: u64,
-    local: SmallVec<[SubscriptionKey; 8]>
+    local: Vec<SubscriptionKey>,
+    forwarded: Vec<ForwardedWrite>
+}
+
+// A write a lens store forwards to its parent, the runtime applies it after set_any returns.
+struct ForwardedWrite {
+    parent: StoreKey,
+    set: LensSetFn,
+    value: Box<dyn Any>
 }
 
 impl SubSink {
 
-    fn new(generation : u64) -> Self {
-        Self { generation, local: SmallVec::new() }
+    fn new(generation : u64, local: Vec<SubscriptionKey>) -> Self {
+        Self { generation, local, forwarded: Vec::new() }
     }
 
     #[inline]
-    fn push(&mut self, subs: SmallVec<[SubscriptionKey; 8]>) {
-        for k in subs { self.local.push(k); }
+    fn push(&mut self, subs: &[SubscriptionKey]) {
+        self.local.extend_from_slice(subs);
     }
+
     #[inline]
-    fn len(&self) -> usize { self.local.len() }
-    #[inline]
-    fn is_empty(&self) -> bool { self.len() == 0 }
+    fn forward(&mut self, parent: StoreKey, set: LensSetFn, value: Box<dyn Any>) {
+        self.forwarded.push(ForwardedWrite { parent, set, value });
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -234,6 +689,49 @@ impl<T: 'static> Store for ConstErased<T> {
         panic!("attempt to write const store, this is a framework error (or you've been naughty)")
     }
 
+    fn update_any(&mut self, _: &mut dyn FnMut(&mut dyn Any) -> bool, _: &mut SubSink) {
+        panic!("attempt to update const store, this is a framework error (or you've been naughty)")
+    }
+
+    fn subscribe(&mut self, _: SubscriptionKey, _ : StoreGeneration) -> bool {
+        false // const stores never change
+    }
+
+    fn unsubscribe(&mut self, _: SubscriptionKey) {
+
+    }
+
+    fn subscriptions(&self) -> Option<&[SubscriptionKey]> {
+        None
+    }
+
+    fn dependencies(&self) -> Option<&[SubscriptionKey]> {
+        None
+    }
+}
+
+// The linker creates const stores from erased resource values.
+pub struct ConstBoxed(Box<dyn Any>);
+
+impl ConstBoxed {
+    pub fn new(value: Box<dyn Any>) -> Self {
+        Self(value)
+    }
+}
+
+impl Store for ConstBoxed {
+    fn get_any(&self) -> &dyn Any {
+        &*self.0
+    }
+
+    fn set_any(&mut self, _: Box<dyn Any>, _: &mut SubSink) {
+        panic!("attempt to write const store, this is a framework error (or you've been naughty)")
+    }
+
+    fn update_any(&mut self, _: &mut dyn FnMut(&mut dyn Any) -> bool, _: &mut SubSink) {
+        panic!("attempt to update const store, this is a framework error (or you've been naughty)")
+    }
+
     fn subscribe(&mut self, _: SubscriptionKey, _ : StoreGeneration) -> bool {
         false // const stores never change
     }
@@ -259,33 +757,118 @@ impl<T: 'static> Store for ConstErased<T> {
 // values before notifying subscribers. The code generation macro decides when
 // to add this function to the store creation.
 // TODO think about when and how to add EqFn, pay attention to monomorphism and code size
-type EqFn = fn(old: &dyn Any, new: &dyn Any) -> bool;
+pub type EqFn = fn(old: &dyn Any, new: &dyn Any) -> bool;
+
+// Signature for the fingerprint function used by the EmittingStore with the
+// `EqStrategy::Fingerprint` strategy. The store keeps the fingerprint of the current
+// value, so only the new value is hashed on write. `None` means "cannot fingerprint",
+// the store notifies in that case.
+pub type FingerprintFn = fn(value: &dyn Any) -> Option<u64>;
+
+/// Decides if a write into an emitting store is a change that has to be notified.
+///
+/// The code generation macro picks the strategy per store declaration (`.. eq { ... }`
+/// in the DSL), stores without a declared strategy use deep comparison when the
+/// type supports `PartialEq`.
+#[derive(Clone, Copy)]
+pub enum EqStrategy {
+    /// Every write notifies the subscribers.
+    Always,
+    /// Compare the old and the new value with the function. Used for deep
+    /// comparison (`mk_eq_fn`), pointer identity (`mk_ptr_eq_fn`) and
+    /// user-supplied functions.
+    Compare(EqFn),
+    /// Compare the fingerprint of the new value with the fingerprint of
+    /// the current one (`mk_fingerprint_fn`).
+    Fingerprint(FingerprintFn),
+}
 
-pub fn mk_eq_fn<T: 'static + PartialEq>() -> EqFn {
+impl From<Option<EqFn>> for EqStrategy {
+    fn from(eq_fn: Option<EqFn>) -> Self {
+        match eq_fn {
+            Some(eq_fn) => EqStrategy::Compare(eq_fn),
+            None => EqStrategy::Always,
+        }
+    }
+}
+
+/// Deep comparison with `PartialEq`. For `Rc<T>` and `Arc<T>` this compares the
+/// pointed values, use `mk_ptr_eq_fn` to compare identities instead.
+pub const fn mk_eq_fn<T: 'static + PartialEq>() -> EqFn {
     |old, new| {
         let (Some(o), Some(n)) = (old.downcast_ref::<T>(), new.downcast_ref::<T>()) else { return false; };
         o == n
     }
 }
 
+/// Shared pointers that can be compared by identity.
+pub trait PtrIdentity: 'static {
+    fn ptr_identical(a: &Self, b: &Self) -> bool;
+}
+
+impl<T: ?Sized + 'static> PtrIdentity for Rc<T> {
+    #[inline]
+    fn ptr_identical(a: &Self, b: &Self) -> bool { Rc::ptr_eq(a, b) }
+}
+
+impl<T: ?Sized + 'static> PtrIdentity for Arc<T> {
+    #[inline]
+    fn ptr_identical(a: &Self, b: &Self) -> bool { Arc::ptr_eq(a, b) }
+}
+
+/// Pointer identity comparison (`Rc::ptr_eq` / `Arc::ptr_eq`). Cheap for large
+/// shared values, but a new allocation with the same content is a change.
+pub const fn mk_ptr_eq_fn<P: PtrIdentity>() -> EqFn {
+    |old, new| {
+        let (Some(o), Some(n)) = (old.downcast_ref::<P>(), new.downcast_ref::<P>()) else { return false; };
+        P::ptr_identical(o, n)
+    }
+}
+
+/// Hash based fingerprint. Hash collisions result in missed notifications, so this
+/// is a trade-off for large values where a deep comparison is too expensive.
+pub const fn mk_fingerprint_fn<T: 'static + Hash>() -> FingerprintFn {
+    |value| {
+        let v = value.downcast_ref::<T>()?;
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
+
 pub struct EmittingStore {
     value: Box<dyn Any>,
-    eq_fn: Option<EqFn>,           // None => AlwaysNotify
-    last_set_gen: StoreGeneration, // the store runtime generation when the last set_any was called
+    eq: EqStrategy,
+    fingerprint: Option<u64>,      // fingerprint of `value` when `eq` is `EqStrategy::Fingerprint`
+    last_set_gen: StoreGeneration, // the store runtime generation when the last set_any/update_any changed the value
     subs: SmallVec<[SubscriptionKey; 8]>
 }
 
 impl EmittingStore {
-    pub fn new(value: Box<dyn Any>, eq_fn: Option<EqFn>) -> Self {
+    pub fn new(value: Box<dyn Any>, eq: impl Into<EqStrategy>) -> Self {
+        let eq = eq.into();
+        let fingerprint = match eq {
+            EqStrategy::Fingerprint(fp_fn) => fp_fn(&*value),
+            _ => None
+        };
         // First real set_any marks it to the live gen.
         // Subscribe will only return true if a real write happened in the current gen.
         Self {
             value,
-            eq_fn,
+            eq,
+            fingerprint,
             last_set_gen: u64::MAX,
             subs: SmallVec::new()
         }
     }
+
+    /// Push the subscriptions into the sink, once per generation.
+    fn changed(&mut self, sink: &mut SubSink) {
+        if self.last_set_gen != sink.generation {
+            sink.push(&self.subs); // copies the keys, so further changes don't affect the sink
+            self.last_set_gen = sink.generation;
+        }
+    }
 }
 
 impl Store for EmittingStore {
@@ -295,19 +878,35 @@ impl Store for EmittingStore {
 
     fn set_any(&mut self, value: Box<dyn Any>, sink: &mut SubSink) {
         // if the value is equal to the old value, we don't need to notify
-        // TODO think about reference types (Box, Rc, Arc) and how to compare them
-        if let Some(eq_fn) = self.eq_fn {
-            if eq_fn(&*self.value, &*value) { return; }
+        match self.eq {
+            EqStrategy::Always => {}
+            EqStrategy::Compare(eq_fn) => {
+                if eq_fn(&*self.value, &*value) { return; }
+            }
+            EqStrategy::Fingerprint(fp_fn) => {
+                let fingerprint = fp_fn(&*value);
+                if fingerprint.is_some() && fingerprint == self.fingerprint { return; }
+                self.fingerprint = fingerprint;
+            }
         }
 
         self.value = value;
-        if self.last_set_gen != sink.generation {
-            sink.push(self.subs.clone()); // make clone so further changes don't affect the sink
-            self.last_set_gen = sink.generation;
+        self.changed(sink);
+    }
+
+    fn update_any(&mut self, f: &mut dyn FnMut(&mut dyn Any) -> bool, sink: &mut SubSink) {
+        // the function decides if there is a change, eq is not used for the decision
+        if !f(&mut *self.value) { return; }
+
+        if let EqStrategy::Fingerprint(fp_fn) = self.eq {
+            self.fingerprint = fp_fn(&*self.value);
         }
+
+        self.changed(sink);
     }
 
     fn subscribe(&mut self, key: SubscriptionKey, generation: StoreGeneration) -> bool {
+        // subscribing twice with the same key is a no-op
         if !self.subs.contains(&key) {
             self.subs.push(key);
         }
@@ -342,7 +941,7 @@ pub struct DerivedStore {
 impl DerivedStore {
     pub fn new(
         initial: Box<dyn Any>,
-        eq_fn: Option<EqFn>,
+        eq: impl Into<EqStrategy>,
         callback: StoreCallback,
         deps: impl IntoIterator<Item = StoreKey>,
         runtime: &mut StoreEffects,
@@ -355,7 +954,7 @@ impl DerivedStore {
         }
 
         DerivedStore {
-            base: EmittingStore::new(initial, eq_fn),
+            base: EmittingStore::new(initial, eq),
             deps: deps_vec,
         }
     }
@@ -375,6 +974,117 @@ impl Store for DerivedStore {
         self.base.set_any(value, sink);
     }
 
+    fn update_any(&mut self, f: &mut dyn FnMut(&mut dyn Any) -> bool, sink: &mut SubSink) {
+        self.base.update_any(f, sink);
+    }
+
+    fn subscribe(&mut self, key: SubscriptionKey, generation: StoreGeneration) -> bool {
+        self.base.subscribe(key, generation)
+    }
+
+    fn unsubscribe(&mut self, key: SubscriptionKey) {
+        self.base.unsubscribe(key);
+    }
+
+    fn subscriptions(&self) -> Option<&[SubscriptionKey]> {
+        self.base.subscriptions()
+    }
+
+    fn dependencies(&self) -> Option<&[SubscriptionKey]> {
+        Some(&self.deps)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Lens
+// ---------------------------------------------------------------------------
+
+// Projects a field of the parent value. Macro-generated, typically clones the field.
+pub type LensGetFn = fn(parent: &dyn Any) -> Box<dyn Any>;
+
+// Writes the field of the parent value. Macro-generated, performs checked downcasts.
+pub type LensSetFn = fn(parent: &mut dyn Any, value: Box<dyn Any>);
+
+// Value used by the lens subscription to refresh the projection without forwarding
+// the write to the parent.
+struct LensRefresh(Box<dyn Any>);
+
+/// A two-way store that projects a field of a parent store.
+///
+/// - Reads return the projection cached when the parent changed last time.
+/// - Writes are forwarded to the parent through the setter (as an in-place update),
+///   the lens is refreshed when the parent notifies, so reads between the write and
+///   the drain return the previous value.
+/// - Subscribers of the lens are notified only when the projected field has changed
+///   according to the equality strategy of the lens.
+///
+/// The parent has to support in-place updates (emitting and derived stores do).
+pub struct LensStore {
+    base: EmittingStore,
+    parent: StoreKey,
+    set: LensSetFn,
+    // The subscription to the parent, used for cleanup
+    deps: SmallVec<[SubscriptionKey; 1]>,
+}
+
+impl LensStore {
+    /// Allocate a lens on `parent` and subscribe it to the parent. Returns the key of the lens.
+    pub fn alloc(
+        parent: StoreKey,
+        get: LensGetFn,
+        set: LensSetFn,
+        eq: impl Into<EqStrategy>,
+        runtime: &mut StoreEffects,
+    ) -> StoreKey {
+
+        let initial = get(runtime.get_value(parent));
+
+        // the callback needs the key of the lens which is known only after allocation
+        let lens_key: Rc<Cell<Option<StoreKey>>> = Rc::new(Cell::new(None));
+        let lens_key_for_cb = lens_key.clone();
+
+        let callback: StoreCallback = Rc::new(move |parent, _sub, rt: &mut StoreEffects| {
+            if let Some(key) = lens_key_for_cb.get() {
+                let projection = get(rt.get_value(parent));
+                rt.set_value(key, Box::new(LensRefresh(projection)));
+            }
+        });
+
+        let mut deps = SmallVec::new();
+        deps.push(runtime.subscribe(parent, callback));
+
+        let key = runtime.alloc_store(Box::new(LensStore {
+            base: EmittingStore::new(initial, eq),
+            parent,
+            set,
+            deps,
+        }));
+
+        lens_key.set(Some(key));
+
+        key
+    }
+}
+
+impl Store for LensStore {
+    fn get_any(&self) -> &dyn Any {
+        self.base.get_any()
+    }
+
+    fn set_any(&mut self, value: Box<dyn Any>, sink: &mut SubSink) {
+        let value = match value.downcast::<LensRefresh>() {
+            // the parent has changed, base decides if the projection has changed as well
+            Ok(refresh) => return self.base.set_any(refresh.0, sink),
+            Err(value) => value
+        };
+
+        // write of the same field value, no need to touch the parent
+        if let EqStrategy::Compare(eq_fn) = self.base.eq
+            && eq_fn(self.base.get_any(), &*value) { return; }
+
+        sink.forward(self.parent, self.set, value);
+    }
+
     fn subscribe(&mut self, key: SubscriptionKey, generation: StoreGeneration) -> bool {
         self.base.subscribe(key, generation)
     }
@@ -390,4 +1100,4 @@ impl Store for DerivedStore {
     fn dependencies(&self) -> Option<&[SubscriptionKey]> {
         Some(&self.deps)
     }
-}
\ No newline at end of file
+}
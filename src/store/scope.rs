@@ -0,0 +1,93 @@
+use smallvec::SmallVec;
+use thunderdome::Index;
+
+use super::{StoreGeneration, StoreKey, SubscriptionKey};
+
+pub type ScopeKey = Index;
+
+/// Statistics of a store scope, see `StoreRuntime::scope_stats`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ScopeStats {
+    /// Live stores allocated in the scope.
+    pub stores: usize,
+    /// Live subscriptions created in the scope.
+    pub subscriptions: usize,
+    /// Callbacks of the scope invoked so far.
+    pub notifications: u64,
+    /// Notifications dropped because the scope has exceeded its generation limit.
+    pub dropped: u64,
+    /// Number of drains in which the scope has exceeded its generation limit.
+    pub limit_exceeded: u64,
+}
+
+/// A child namespace of the store runtime. Tracks the stores and subscriptions allocated
+/// while the scope is current, so they can be freed in one call.
+pub struct StoreScope {
+    pub(super) parent: Option<ScopeKey>,
+    pub(super) children: SmallVec<[ScopeKey; 4]>,
+    pub(super) stores: Vec<StoreKey>,
+    pub(super) subscriptions: Vec<SubscriptionKey>,
+    pub(super) gen_limit: u64,
+    // generation counting of the current drain
+    pub(super) drain_id: u64,
+    pub(super) drain_generations: u64,
+    pub(super) counted_generation: Option<StoreGeneration>,
+    pub(super) stats: ScopeStats,
+}
+
+impl StoreScope {
+    pub(super) fn new(parent: Option<ScopeKey>, gen_limit: u64) -> Self {
+        Self {
+            parent,
+            children: SmallVec::new(),
+            stores: Vec::new(),
+            subscriptions: Vec::new(),
+            gen_limit,
+            drain_id: 0,
+            drain_generations: 0,
+            counted_generation: None,
+            stats: ScopeStats::default(),
+        }
+    }
+
+    /// Count `generation` for the scope (once per generation). Returns false if the scope
+    /// has exceeded its generation limit in the drain, the notification has to be dropped.
+    pub(super) fn admit(&mut self, drain_id: u64, generation: StoreGeneration) -> bool {
+        if self.drain_id != drain_id {
+            self.drain_id = drain_id;
+            self.drain_generations = 0;
+            self.counted_generation = None;
+        }
+
+        if self.counted_generation != Some(generation) {
+            self.counted_generation = Some(generation);
+            self.drain_generations += 1;
+            if self.drain_generations - 1 == self.gen_limit {
+                self.stats.limit_exceeded += 1;
+            }
+        }
+
+        if self.drain_generations > self.gen_limit {
+            self.stats.dropped += 1;
+            false
+        } else {
+            self.stats.notifications += 1;
+            true
+        }
+    }
+
+    pub(super) fn stats(&self) -> ScopeStats {
+        ScopeStats {
+            stores: self.stores.len(),
+            subscriptions: self.subscriptions.len(),
+            ..self.stats
+        }
+    }
+}
+
+// no need to keep order, everything must work with any order by design
+pub(super) fn remove_key(keys: &mut Vec<Index>, key: Index) {
+    if let Some(i) = keys.iter().position(|&k| k == key) {
+        keys.swap_remove(i);
+    }
+}
@@ -0,0 +1,246 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::{DerivedStore, EqStrategy, StoreCallback, StoreEffects, StoreKey, TimerCallback, TimerKey};
+
+// ---------------------------------------------------------------------------
+// Clock
+// ---------------------------------------------------------------------------
+
+/// The time source of the store runtime. Time is measured from an arbitrary
+/// origin chosen by the clock, only differences are meaningful.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// Wall clock, the origin is the creation of the clock.
+pub struct SystemClock {
+    origin: Instant
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { origin: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Clock that moves only when told to, for deterministic tests and headless use.
+/// Clones share the same time, so a test can keep one and give the other to the runtime.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now.set(now);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Time-based derived stores
+// ---------------------------------------------------------------------------
+
+// Copies the value of the source store into the time-based store. Macro-generated.
+pub type CloneFn = fn(value: &dyn Any) -> Box<dyn Any>;
+
+pub fn mk_clone_fn<T: 'static + Clone>() -> CloneFn {
+    |value| {
+        let Some(v) = value.downcast_ref::<T>() else { panic!("clone of a value with unexpected type") };
+        Box::new(v.clone())
+    }
+}
+
+/// Emits the value of `source` when it has not changed for `period`.
+pub fn debounce(
+    source: StoreKey,
+    period: Duration,
+    clone: CloneFn,
+    eq: impl Into<EqStrategy>,
+    runtime: &mut StoreEffects,
+) -> StoreKey {
+    let pending: Rc<Cell<Option<TimerKey>>> = Rc::new(Cell::new(None));
+
+    alloc_timed(source, clone, eq, runtime, move |out, rt| {
+        if let Some(timer) = pending.take() {
+            rt.cancel(timer);
+        }
+
+        let pending_for_timer = pending.clone();
+        let on_timer: TimerCallback = Rc::new(move |out, rt: &mut StoreEffects| {
+            pending_for_timer.set(None);
+            emit(source, out, clone, rt);
+        });
+
+        let deadline = rt.now() + period;
+        pending.set(Some(rt.schedule(out, deadline, on_timer)));
+    })
+}
+
+/// Emits the value of `source` at most once in every `period`. The first change
+/// is emitted immediately, changes within the period are collapsed into one
+/// emission at the end of the period.
+pub fn throttle(
+    source: StoreKey,
+    period: Duration,
+    clone: CloneFn,
+    eq: impl Into<EqStrategy>,
+    runtime: &mut StoreEffects,
+) -> StoreKey {
+    let last_emit: Rc<Cell<Option<Duration>>> = Rc::new(Cell::new(None));
+    let trailing_scheduled = Rc::new(Cell::new(false));
+
+    alloc_timed(source, clone, eq, runtime, move |out, rt| {
+        // the trailing emission picks up the latest value anyway
+        if trailing_scheduled.get() { return; }
+
+        let now = rt.now();
+
+        match last_emit.get() {
+            Some(last) if now < last + period => {
+                let last_emit_for_timer = last_emit.clone();
+                let trailing_for_timer = trailing_scheduled.clone();
+                let deadline = last + period;
+
+                let on_timer: TimerCallback = Rc::new(move |out, rt: &mut StoreEffects| {
+                    trailing_for_timer.set(false);
+                    last_emit_for_timer.set(Some(deadline));
+                    emit(source, out, clone, rt);
+                });
+
+                trailing_scheduled.set(true);
+                rt.schedule(out, deadline, on_timer);
+            }
+            _ => {
+                last_emit.set(Some(now));
+                emit(source, out, clone, rt);
+            }
+        }
+    })
+}
+
+/// Emits the value of `source` at every `period` if it has changed since the last emission.
+pub fn sample(
+    source: StoreKey,
+    period: Duration,
+    clone: CloneFn,
+    eq: impl Into<EqStrategy>,
+    runtime: &mut StoreEffects,
+) -> StoreKey {
+    let changed = Rc::new(Cell::new(false));
+    let changed_for_timer = changed.clone();
+
+    let out = alloc_timed(source, clone, eq, runtime, move |_out, _rt| {
+        changed.set(true);
+    });
+
+    let deadline = runtime.now() + period;
+    schedule_sample(out, source, deadline, period, clone, changed_for_timer, runtime);
+
+    out
+}
+
+// Sampling reschedules itself from the previous deadline, so late timer runs do not cause drift.
+fn schedule_sample(
+    out: StoreKey,
+    source: StoreKey,
+    deadline: Duration,
+    period: Duration,
+    clone: CloneFn,
+    changed: Rc<Cell<bool>>,
+    runtime: &mut StoreEffects,
+) {
+    let on_timer: TimerCallback = Rc::new(move |out, rt: &mut StoreEffects| {
+        if changed.replace(false) {
+            emit(source, out, clone, rt);
+        }
+        schedule_sample(out, source, deadline + period, period, clone, changed.clone(), rt);
+    });
+
+    runtime.schedule(out, deadline, on_timer);
+}
+
+/// Emits every value of `source` `period` later than the source has changed.
+pub fn delay(
+    source: StoreKey,
+    period: Duration,
+    clone: CloneFn,
+    eq: impl Into<EqStrategy>,
+    runtime: &mut StoreEffects,
+) -> StoreKey {
+    alloc_timed(source, clone, eq, runtime, move |out, rt| {
+        // copy now, the source may change again before the timer fires
+        let value = RefCell::new(Some(clone(rt.get_value(source))));
+
+        let on_timer: TimerCallback = Rc::new(move |out, rt: &mut StoreEffects| {
+            if let Some(value) = value.take() {
+                rt.set_value(out, value);
+            }
+        });
+
+        let deadline = rt.now() + period;
+        rt.schedule(out, deadline, on_timer);
+    })
+}
+
+// Allocates the derived store of a time-based operator. `on_change` is called with
+// the key of the derived store whenever the source notifies.
+fn alloc_timed(
+    source: StoreKey,
+    clone: CloneFn,
+    eq: impl Into<EqStrategy>,
+    runtime: &mut StoreEffects,
+    on_change: impl Fn(StoreKey, &mut StoreEffects) + 'static,
+) -> StoreKey {
+    let initial = clone(runtime.get_value(source));
+
+    // the callback needs the key of the derived store which is known only after allocation
+    let out_key: Rc<Cell<Option<StoreKey>>> = Rc::new(Cell::new(None));
+    let out_key_for_cb = out_key.clone();
+
+    let callback: StoreCallback = Rc::new(move |_source, _sub, rt: &mut StoreEffects| {
+        if let Some(out) = out_key_for_cb.get() {
+            on_change(out, rt);
+        }
+    });
+
+    let derived = DerivedStore::new(initial, eq, callback, [source], runtime);
+    let key = runtime.alloc_store(Box::new(derived));
+
+    out_key.set(Some(key));
+
+    key
+}
+
+fn emit(source: StoreKey, out: StoreKey, clone: CloneFn, runtime: &mut StoreEffects) {
+    let value = clone(runtime.get_value(source));
+    runtime.set_value(out, value);
+}
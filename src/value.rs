@@ -0,0 +1,32 @@
+// value.rs
+
+// ---------------------------------------------------------------------------
+// Values shared by the compiler and the ART
+// ---------------------------------------------------------------------------
+
+// The compiler parses instruction arguments into these, the ART stores them in the render
+// data; neither layer depends on the other for them.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axis { Width, Height }
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum FillStrategy { #[default] Constrain, ConstrainReverse, ResizeToMax }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HAlign { Start, Center, End }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VAlign { Top, Center, Baseline, Bottom }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RelativeH { Before, Start, Center, End, After }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RelativeV { Above, Start, Center, End, Below }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Spacing { Around, Between }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScrollDirection { Horizontal, Vertical, Both }
@@ -0,0 +1,319 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use fluxum::art::instruction::*;
+use fluxum::art::layout::{LayoutEngine, Size};
+use fluxum::art::render_data::*;
+use fluxum::art::{Art, NodeKey, NodeKind};
+
+fn add(art: &mut Art, kind: NodeKind, parent: Option<NodeKey>, instructions: &[Instruction]) -> NodeKey {
+    let node = art.create(kind, parent);
+    art.apply(node, instructions);
+    node
+}
+
+fn fixed(value: Dip) -> Dimension {
+    Dimension { value: DimValue::Fixed(value), min: None, max: None }
+}
+
+fn dim(value: DimValue) -> Dimension {
+    Dimension { value, min: None, max: None }
+}
+
+fn size(width: Dip, height: Dip) -> [Instruction; 2] {
+    [Instruction::Width(fixed(width)), Instruction::Height(fixed(height))]
+}
+
+fn fill() -> [Instruction; 2] {
+    [Instruction::Width(dim(DimValue::Container)), Instruction::Height(dim(DimValue::Container))]
+}
+
+fn align(horizontal: Option<HAlign>, vertical: Option<VAlign>) -> Alignment {
+    Alignment { horizontal, vertical }
+}
+
+// The bounds of the nodes as JSON, one node per line.
+fn dump(art: &Art) -> String {
+    let mut out = String::from("[\n");
+    for (i, &root) in art.roots().iter().enumerate() {
+        if i > 0 {
+            out.push_str(",\n");
+        }
+        dump_node(art, root, 1, &mut out);
+    }
+    out.push_str("\n]\n");
+    out
+}
+
+fn dump_node(art: &Art, key: NodeKey, depth: usize, out: &mut String) {
+    let node = art.node(key);
+    let bounds = node.data.derived.bounds;
+    let indent = "  ".repeat(depth);
+    write!(out, "{indent}{{ \"kind\": \"{:?}\", \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}", node.kind, bounds.x, bounds.y, bounds.width, bounds.height).unwrap();
+    if node.children.is_empty() {
+        out.push_str(" }");
+        return;
+    }
+    out.push_str(", \"children\": [\n");
+    for (i, &child) in node.children.iter().enumerate() {
+        if i > 0 {
+            out.push_str(",\n");
+        }
+        dump_node(art, child, depth + 1, out);
+    }
+    write!(out, "\n{indent}] }}").unwrap();
+}
+
+// Compare with `tests/snapshots/<name>.json`, `UPDATE_SNAPSHOTS=1` rewrites the file.
+fn assert_layout(name: &str, art: &mut Art, viewport: Size) {
+    LayoutEngine::new().layout(art, viewport);
+    let actual = dump(art);
+
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots", &format!("{name}.json")].iter().collect();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {}", path.display()));
+    assert_eq!(actual, expected, "layout {name} differs");
+}
+
+#[test]
+fn column_places_children_below_each_other() {
+    let mut art = Art::new();
+    let column = add(&mut art, NodeKind::Column, None, &[
+        fill().as_slice(),
+        &[Instruction::Padding(Sides::all(10.0)), Instruction::Gap { width: None, height: Some(5.0) }],
+    ].concat());
+
+    // 50 x 20, the margin is counted into the size
+    add(&mut art, NodeKind::Box, Some(column), &[size(50.0, 20.0).as_slice(), &[Instruction::Margin(Sides::all(2.0))]].concat());
+    // the rest of the height, the full width
+    add(&mut art, NodeKind::Box, Some(column), &[Instruction::Width(dim(DimValue::Container)), Instruction::Height(dim(DimValue::Expand))]);
+    add(&mut art, NodeKind::Box, Some(column), &[size(30.0, 10.0).as_slice(), &[Instruction::AlignSelf(align(Some(HAlign::End), None))]].concat());
+
+    assert_layout("layout_column", &mut art, Size::new(200.0, 100.0));
+}
+
+#[test]
+fn fill_strategy_decides_who_gets_the_space() {
+    let mut art = Art::new();
+    let holder = add(&mut art, NodeKind::Column, None, &[]);
+    for strategy in [FillStrategy::Constrain, FillStrategy::ConstrainReverse] {
+        let row = add(&mut art, NodeKind::Row, Some(holder), &[Instruction::Width(fixed(120.0)), Instruction::FillStrategy(strategy)]);
+        add(&mut art, NodeKind::Box, Some(row), &[Instruction::Width(dim(DimValue::Container)), Instruction::Height(fixed(10.0))]);
+        add(&mut art, NodeKind::Box, Some(row), &size(40.0, 10.0));
+    }
+
+    // all the children get the width of the widest one
+    let column = add(&mut art, NodeKind::Column, Some(holder), &[Instruction::FillStrategy(FillStrategy::ResizeToMax)]);
+    add(&mut art, NodeKind::Box, Some(column), &size(40.0, 10.0));
+    add(&mut art, NodeKind::Box, Some(column), &size(80.0, 10.0));
+    add(&mut art, NodeKind::Text, Some(column), &[Instruction::Padding(Sides::all(5.0))]);
+
+    assert_layout("layout_fill_strategy", &mut art, Size::new(300.0, 300.0));
+}
+
+#[test]
+fn row_spacing_and_alignment() {
+    let mut art = Art::new();
+    let holder = add(&mut art, NodeKind::Column, None, &fill());
+
+    for spacing in [Spacing::Between, Spacing::Around] {
+        let row = add(&mut art, NodeKind::Row, Some(holder), &[Instruction::Width(dim(DimValue::Container)), Instruction::Spacing(spacing)]);
+        for width in [10.0, 20.0, 40.0] {
+            add(&mut art, NodeKind::Box, Some(row), &size(width, width));
+        }
+    }
+
+    let row = add(&mut art, NodeKind::Row, Some(holder), &[
+        Instruction::Width(dim(DimValue::Container)),
+        Instruction::Height(fixed(40.0)),
+        Instruction::Gap { width: Some(4.0), height: None },
+        Instruction::AlignItems(align(Some(HAlign::Center), Some(VAlign::Center))),
+    ]);
+    add(&mut art, NodeKind::Box, Some(row), &size(10.0, 10.0));
+    add(&mut art, NodeKind::Box, Some(row), &[size(10.0, 10.0).as_slice(), &[Instruction::AlignSelf(align(None, Some(VAlign::Bottom)))]].concat());
+
+    assert_layout("layout_row", &mut art, Size::new(100.0, 200.0));
+}
+
+#[test]
+fn box_positions_or_aligns_its_children() {
+    let mut art = Art::new();
+    let outer = add(&mut art, NodeKind::Box, None, &[
+        size(100.0, 80.0).as_slice(),
+        &[
+            Instruction::Border { sides: Sides::all(1.0), color: None },
+            Instruction::Margin(Sides::all(4.0)),
+            Instruction::AlignItems(align(Some(HAlign::Center), Some(VAlign::Center))),
+        ],
+    ].concat());
+
+    add(&mut art, NodeKind::Box, Some(outer), &[size(20.0, 20.0).as_slice(), &[Instruction::Position(Point { top: 5.0, left: 50.0 })]].concat());
+    add(&mut art, NodeKind::Box, Some(outer), &size(30.0, 10.0));
+    add(&mut art, NodeKind::Box, Some(outer), &[size(10.0, 10.0).as_slice(), &[Instruction::AlignSelf(align(Some(HAlign::End), Some(VAlign::Bottom)))]].concat());
+
+    // content sized: the farthest corner of the children
+    let content = add(&mut art, NodeKind::Box, None, &[Instruction::Padding(Sides::all(3.0))]);
+    add(&mut art, NodeKind::Box, Some(content), &[size(20.0, 20.0).as_slice(), &[Instruction::Position(Point { top: 10.0, left: 15.0 })]].concat());
+
+    assert_layout("layout_box", &mut art, Size::new(500.0, 500.0));
+}
+
+#[test]
+fn dimensions_are_clamped_and_scroll_is_unlimited() {
+    let mut art = Art::new();
+    let row = add(&mut art, NodeKind::Row, None, &[
+        Instruction::Width(Dimension { value: DimValue::Container, min: None, max: Some(150.0) }),
+        Instruction::Height(fixed(50.0)),
+        Instruction::Scroll(ScrollDirection::Horizontal),
+    ]);
+    add(&mut art, NodeKind::Box, Some(row), &[
+        Instruction::Width(dim(DimValue::Container)),
+        Instruction::Height(Dimension { value: DimValue::Container, min: None, max: Some(30.0) }),
+    ]);
+    add(&mut art, NodeKind::Box, Some(row), &size(100.0, 10.0));
+    add(&mut art, NodeKind::Box, Some(row), &size(100.0, 10.0));
+    add(&mut art, NodeKind::Box, Some(row), &[Instruction::Width(Dimension { value: DimValue::Fixed(10.0), min: Some(25.0), max: None })]);
+
+    assert_layout("layout_clamp_scroll", &mut art, Size::new(400.0, 400.0));
+}
+
+#[test]
+fn only_changed_bounds_are_patched() {
+    let mut art = Art::new();
+    let column = add(&mut art, NodeKind::Column, None, &[]);
+    let first = add(&mut art, NodeKind::Box, Some(column), &size(10.0, 10.0));
+    let second = add(&mut art, NodeKind::Box, Some(column), &size(10.0, 10.0));
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, Size::new(100.0, 100.0));
+    art.take_patches();
+
+    art.apply(first, &size(10.0, 20.0));
+    engine.layout(&mut art, Size::new(100.0, 100.0));
+
+    let moved: Vec<NodeKey> = art.take_patches().into_iter().filter_map(|patch| match patch {
+        fluxum::art::Patch::SetBounds { node, .. } => Some(node),
+        _ => None,
+    }).collect();
+    assert_eq!(moved, vec![column, first, second]);
+}
+
+fn bounds(art: &Art, nodes: &[NodeKey]) -> Vec<(Dip, Dip, Dip, Dip)> {
+    nodes.iter().map(|&node| {
+        let rect = art.node(node).data.derived.bounds;
+        (rect.x, rect.y, rect.width, rect.height)
+    }).collect()
+}
+
+#[test]
+fn flow_box_wraps_its_children() {
+    let mut art = Art::new();
+    let flow = add(&mut art, NodeKind::FlowBox, None, &[
+        Instruction::Width(fixed(100.0)),
+        Instruction::Padding(Sides::all(5.0)),
+        Instruction::Gap { width: Some(10.0), height: Some(4.0) },
+        Instruction::AlignItems(align(Some(HAlign::Center), Some(VAlign::Bottom))),
+    ]);
+    let children: Vec<NodeKey> = [(30.0, 10.0), (40.0, 20.0), (30.0, 10.0), (50.0, 10.0)].into_iter()
+        .map(|(width, height)| add(&mut art, NodeKind::Box, Some(flow), &size(width, height)))
+        .collect();
+
+    LayoutEngine::new().layout(&mut art, Size::new(500.0, 500.0));
+
+    // content box 90 wide: 30 + 10 + 40 fits, the third child does not (80 + 10 + 30)
+    // row 1: 80 wide, 20 high, centered at 5 + 5; row 2: 30 + 10 + 50 = 90, at 5 + 20 + 4
+    assert_eq!(bounds(&art, &children), vec![
+        (10.0, 15.0, 30.0, 10.0),
+        (50.0, 5.0, 40.0, 20.0),
+        (5.0, 29.0, 30.0, 10.0),
+        (45.0, 29.0, 50.0, 10.0),
+    ]);
+    assert_eq!(bounds(&art, &[flow]), vec![(0.0, 0.0, 100.0, 44.0)]);
+}
+
+#[test]
+fn grid_sizes_tracks_and_places_children() {
+    let mut art = Art::new();
+    let grid = add(&mut art, NodeKind::Grid, None, &[
+        Instruction::Width(fixed(240.0)),
+        Instruction::Height(fixed(200.0)),
+        Instruction::Gap { width: Some(10.0), height: Some(10.0) },
+        Instruction::GridTemplate {
+            columns: Some([Track::Fixed(50.0), Track::Content, Track::Fraction(1.0), Track::Fraction(2.0)].into()),
+            rows: Some([Track::Content, Track::Fraction(1.0)].into()),
+        },
+    ]);
+
+    let cell = |row, column, row_span, column_span| Instruction::GridCell(GridCell { row, column, row_span, column_span });
+
+    // spans the two fraction columns of the first row
+    let header = add(&mut art, NodeKind::Box, Some(grid), &[cell(0, 2, 1, 2), Instruction::Width(dim(DimValue::Container)), Instruction::Height(fixed(30.0))]);
+    // automatic: the first free cells, (0, 0) and (0, 1)
+    let first = add(&mut art, NodeKind::Box, Some(grid), &fill());
+    let second = add(&mut art, NodeKind::Box, Some(grid), &size(40.0, 20.0));
+    // spans all the columns of the second row, centered
+    let footer = add(&mut art, NodeKind::Box, Some(grid), &[
+        size(100.0, 20.0).as_slice(),
+        &[cell(1, 0, 1, 4), Instruction::AlignSelf(align(Some(HAlign::Center), Some(VAlign::Center)))],
+    ].concat());
+    // a third row sized to its content
+    let last = add(&mut art, NodeKind::Box, Some(grid), &[cell(2, 3, 1, 1), Instruction::Width(dim(DimValue::Container)), Instruction::Height(fixed(15.0))]);
+
+    LayoutEngine::new().layout(&mut art, Size::new(500.0, 500.0));
+
+    // columns: 50, 40 (content), 1fr = 40, 2fr = 80 (240 - 30 gaps - 90 = 120)
+    // rows: 30 (content), 15 (content), 1fr = 135 (200 - 20 gaps - 45)
+    assert_eq!(bounds(&art, &[header, first, second, footer, last]), vec![
+        (110.0, 0.0, 130.0, 30.0),
+        (0.0, 0.0, 50.0, 30.0),
+        (60.0, 0.0, 40.0, 20.0),
+        (70.0, 97.5, 100.0, 20.0),
+        (160.0, 185.0, 80.0, 15.0),
+    ]);
+}
+
+#[test]
+fn spanning_children_grow_content_tracks() {
+    let mut art = Art::new();
+    let grid = add(&mut art, NodeKind::Grid, None, &[Instruction::Gap { width: Some(10.0), height: Some(10.0) }]);
+
+    let cell = |row, column, row_span, column_span| Instruction::GridCell(GridCell { row, column, row_span, column_span });
+    let single = add(&mut art, NodeKind::Box, Some(grid), &[size(30.0, 10.0).as_slice(), &[cell(0, 0, 1, 1)]].concat());
+    // the only child of the second column, it spans it
+    let wide = add(&mut art, NodeKind::Box, Some(grid), &[size(100.0, 20.0).as_slice(), &[cell(1, 0, 1, 2)]].concat());
+    let tall = add(&mut art, NodeKind::Box, Some(grid), &[size(10.0, 80.0).as_slice(), &[cell(0, 2, 2, 1)]].concat());
+
+    LayoutEngine::new().layout(&mut art, Size::new(500.0, 500.0));
+
+    // columns: 30 + 30, 0 + 30 (100 - 40 shared), 10; rows: 10 + 20, 20 + 20 (80 - 40 shared)
+    assert_eq!(bounds(&art, &[single, wide, tall]), vec![
+        (0.0, 0.0, 30.0, 10.0),
+        (0.0, 40.0, 100.0, 20.0),
+        (110.0, 0.0, 10.0, 80.0),
+    ]);
+    assert_eq!(bounds(&art, &[grid]), vec![(0.0, 0.0, 120.0, 80.0)]);
+}
+
+#[test]
+fn content_sized_grid_sizes_fractions_to_their_content() {
+    let mut art = Art::new();
+    let grid = add(&mut art, NodeKind::Grid, None, &[
+        Instruction::GridTemplate { columns: Some([Track::Fraction(1.0), Track::Fraction(1.0)].into()), rows: None },
+        Instruction::Scroll(ScrollDirection::Horizontal),
+    ]);
+    let children: Vec<NodeKey> = [(20.0, 10.0), (30.0, 10.0), (25.0, 15.0)].into_iter()
+        .map(|(width, height)| add(&mut art, NodeKind::Box, Some(grid), &size(width, height)))
+        .collect();
+
+    LayoutEngine::new().layout(&mut art, Size::new(500.0, 500.0));
+
+    assert_eq!(bounds(&art, &children), vec![
+        (0.0, 0.0, 20.0, 10.0),
+        (25.0, 0.0, 30.0, 10.0),
+        (0.0, 10.0, 25.0, 15.0),
+    ]);
+    assert_eq!(bounds(&art, &[grid]), vec![(0.0, 0.0, 55.0, 25.0)]);
+}
@@ -0,0 +1,233 @@
+use fluxum::art::instruction::*;
+use fluxum::art::layout::{LayoutEngine, Size};
+use fluxum::art::render_data::*;
+use fluxum::art::{Art, NodeKey, NodeKind, Patch};
+
+fn add(art: &mut Art, kind: NodeKind, parent: Option<NodeKey>, instructions: &[Instruction]) -> NodeKey {
+    let node = art.create(kind, parent);
+    art.apply(node, instructions);
+    node
+}
+
+fn fixed(value: Dip) -> Dimension {
+    Dimension { value: DimValue::Fixed(value), min: None, max: None }
+}
+
+fn size(width: Dip, height: Dip) -> [Instruction; 2] {
+    [Instruction::Width(fixed(width)), Instruction::Height(fixed(height))]
+}
+
+fn moved(art: &mut Art) -> Vec<NodeKey> {
+    art.take_patches().into_iter().filter_map(|patch| match patch {
+        Patch::SetBounds { node, .. } => Some(node),
+        _ => None,
+    }).collect()
+}
+
+// A full layout by a new engine changes nothing.
+fn assert_full_layout_agrees(art: &mut Art, viewport: Size) {
+    art.take_patches();
+    LayoutEngine::new().layout(art, viewport);
+    assert_eq!(moved(art), Vec::new(), "the incremental layout differs from the full one");
+}
+
+// A scrolled column of 1000 rows of 3 cells.
+fn table(art: &mut Art) -> (NodeKey, Vec<NodeKey>) {
+    let column = add(art, NodeKind::Column, None, &[
+        size(400.0, 600.0).as_slice(),
+        &[Instruction::Scroll(ScrollDirection::Vertical), Instruction::Gap { width: None, height: Some(2.0) }],
+    ].concat());
+    let rows = (0..1000).map(|_| {
+        let row = add(art, NodeKind::Row, Some(column), &[Instruction::Padding(Sides::all(4.0))]);
+        for _ in 0..3 {
+            add(art, NodeKind::Text, Some(row), &size(50.0, 20.0));
+        }
+        row
+    }).collect();
+    (column, rows)
+}
+
+#[test]
+fn a_change_is_laid_out_up_to_the_nearest_fixed_size_ancestor() {
+    let mut art = Art::new();
+    let (column, rows) = table(&mut art);
+    let viewport = Size::new(800.0, 600.0);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, viewport);
+    assert_eq!(engine.measured(), 4001);
+    art.take_patches();
+
+    // the cell, the next one which has less space, the row and the column: the other rows
+    // are measured in the same space
+    let row = rows[500];
+    let cells = art.node(row).children.clone();
+    art.apply(cells[1], &size(60.0, 20.0));
+    engine.layout(&mut art, viewport);
+    assert_eq!(engine.measured(), 4);
+    assert_eq!(moved(&mut art), vec![row, cells[1], cells[2]]);
+    assert_eq!(art.node(column).data.derived.bounds.height, 600.0);
+
+    // a taller cell moves the rows below
+    art.apply(cells[0], &size(50.0, 30.0));
+    engine.layout(&mut art, viewport);
+    assert_eq!(engine.measured(), 3);
+    assert_eq!(moved(&mut art).len(), 1 + 1 + 499);
+
+    // nothing changed
+    engine.layout(&mut art, viewport);
+    assert_eq!(engine.measured(), 0);
+    assert!(art.take_patches().is_empty());
+
+    assert_full_layout_agrees(&mut art, viewport);
+}
+
+#[test]
+fn content_sized_ancestors_are_laid_out_again() {
+    let mut art = Art::new();
+    let root = add(&mut art, NodeKind::Column, None, &[]);
+    let row = add(&mut art, NodeKind::Row, Some(root), &[]);
+    let cell = add(&mut art, NodeKind::Box, Some(row), &size(10.0, 10.0));
+    let sibling = add(&mut art, NodeKind::Box, Some(root), &size(10.0, 10.0));
+    let viewport = Size::new(100.0, 100.0);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, viewport);
+    art.take_patches();
+
+    // up to the root, the sibling has less space
+    art.apply(cell, &size(20.0, 20.0));
+    engine.layout(&mut art, viewport);
+    assert_eq!(engine.measured(), 4);
+    assert_eq!(moved(&mut art), vec![root, row, cell, sibling]);
+
+    assert_full_layout_agrees(&mut art, viewport);
+}
+
+#[test]
+fn measurements_are_cached_by_constraints() {
+    let mut art = Art::new();
+    let (column, _) = table(&mut art);
+    art.apply(column, &[Instruction::Width(Dimension { value: DimValue::Container, min: None, max: None }), Instruction::Height(fixed(600.0))]);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    engine.layout(&mut art, Size::new(500.0, 600.0));
+    assert_eq!(engine.measured(), 4001);
+    art.take_patches();
+
+    // measured in this space before, the bounds are set again
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    assert_eq!(engine.measured(), 0);
+    assert_eq!(moved(&mut art), vec![column]);
+
+    assert_full_layout_agrees(&mut art, Size::new(800.0, 600.0));
+}
+
+#[test]
+fn structural_changes_are_laid_out() {
+    let mut art = Art::new();
+    let (column, rows) = table(&mut art);
+    let viewport = Size::new(800.0, 600.0);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, viewport);
+    art.take_patches();
+
+    art.remove(rows[998]);
+    let inserted = art.insert(NodeKind::Text, Some(column), 0);
+    art.apply(inserted, &size(100.0, 10.0));
+    art.move_to(rows[10], Some(rows[11]), 0);
+    engine.layout(&mut art, viewport);
+
+    // the column, the inserted node, the moved row and the row it is in with their cells
+    assert_eq!(engine.measured(), 10);
+    let moved = moved(&mut art);
+    assert!(moved.contains(&inserted) && moved.contains(&rows[10]) && moved.contains(&rows[999]));
+    assert_eq!(art.node(rows[0]).data.derived.bounds.y, 12.0);
+
+    assert_full_layout_agrees(&mut art, viewport);
+}
+
+fn container() -> Dimension {
+    Dimension { value: DimValue::Container, min: None, max: None }
+}
+
+#[test]
+fn resizing_the_viewport_more_often_than_cached_measurements() {
+    // children are measured twice for each measurement of the row, their measurements are
+    // evicted first
+    let mut art = Art::new();
+    let row = add(&mut art, NodeKind::Row, None, &[Instruction::Width(container()), Instruction::FillStrategy(FillStrategy::ResizeToMax)]);
+    let column = add(&mut art, NodeKind::Column, Some(row), &[Instruction::Width(Dimension { value: DimValue::Expand, min: None, max: None })]);
+    add(&mut art, NodeKind::Box, Some(column), &size(10.0, 30.0));
+    add(&mut art, NodeKind::Box, Some(row), &size(20.0, 10.0));
+
+    let mut engine = LayoutEngine::new();
+    for width in [100.0, 200.0, 300.0, 400.0, 100.0] {
+        engine.layout(&mut art, Size::new(width, 100.0));
+    }
+    assert_full_layout_agrees(&mut art, Size::new(100.0, 100.0));
+}
+
+#[test]
+fn resizing_the_viewport_after_a_change_below_a_boundary() {
+    let mut art = Art::new();
+    let root = add(&mut art, NodeKind::Column, None, &[Instruction::Width(container())]);
+    let boundary = add(&mut art, NodeKind::Box, Some(root), &[Instruction::Width(container()), Instruction::Height(fixed(50.0))]);
+    let column = add(&mut art, NodeKind::Column, Some(boundary), &[]);
+    let cell = add(&mut art, NodeKind::Box, Some(column), &size(10.0, 10.0));
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, Size::new(100.0, 100.0));
+    engine.layout(&mut art, Size::new(200.0, 100.0));
+    art.apply(cell, &size(20.0, 20.0));
+    engine.layout(&mut art, Size::new(200.0, 100.0));
+    engine.layout(&mut art, Size::new(100.0, 100.0));
+
+    assert_eq!(art.node(boundary).data.derived.bounds.width, 100.0);
+    assert_full_layout_agrees(&mut art, Size::new(100.0, 100.0));
+}
+
+#[test]
+fn a_changed_baseline_is_laid_out_above_the_boundary() {
+    let mut art = Art::new();
+    let row = add(&mut art, NodeKind::Row, None, &[
+        Instruction::AlignItems(Alignment { horizontal: None, vertical: Some(VAlign::Baseline) }),
+    ]);
+    let boundary = add(&mut art, NodeKind::Box, Some(row), &size(100.0, 100.0));
+    let inner = add(&mut art, NodeKind::Text, Some(boundary), &[]);
+    art.set_text(inner, "inner");
+    let sibling = add(&mut art, NodeKind::Text, Some(row), &[]);
+    art.set_text(sibling, "sibling");
+    let viewport = Size::new(800.0, 600.0);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, viewport);
+    assert_eq!(art.node(sibling).data.derived.bounds.y, 0.0);
+
+    // same size, lower baseline
+    art.apply(inner, &[Instruction::Font { name: None, size: Some(44.0), weight: None, color: None }]);
+    engine.layout(&mut art, viewport);
+    assert_eq!(art.node(sibling).data.derived.bounds.y, 24.5);
+
+    assert_full_layout_agrees(&mut art, viewport);
+}
+
+#[test]
+fn children_resized_to_max_are_not_boundaries() {
+    // the child is measured without stretch first, its content sets the width of the column
+    let mut art = Art::new();
+    let column = add(&mut art, NodeKind::Column, None, &[Instruction::FillStrategy(FillStrategy::ResizeToMax)]);
+    let stretched = add(&mut art, NodeKind::Box, Some(column), &[Instruction::Height(fixed(10.0))]);
+    let cell = add(&mut art, NodeKind::Box, Some(stretched), &size(20.0, 10.0));
+    let viewport = Size::new(100.0, 100.0);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, viewport);
+    art.apply(cell, &size(40.0, 10.0));
+    engine.layout(&mut art, viewport);
+
+    assert_eq!(art.node(column).data.derived.bounds.width, 40.0);
+    assert_full_layout_agrees(&mut art, viewport);
+}
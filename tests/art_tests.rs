@@ -0,0 +1,127 @@
+use fluxum::art::instruction::*;
+use fluxum::art::render_data::*;
+use fluxum::art::{Art, NodeKind, Patch};
+
+fn padding(top: Option<Dip>, right: Option<Dip>, bottom: Option<Dip>, left: Option<Dip>) -> Instruction {
+    Instruction::Padding(Sides { top, right, bottom, left })
+}
+
+fn blue() -> Color {
+    Color::rgb(0x0000ff, 1.0)
+}
+
+#[test]
+fn only_the_changed_fields_are_dirty() {
+    let mut art = Art::new();
+    let node = art.create(NodeKind::Box, None);
+    art.clear_dirty(node);
+    art.take_patches();
+
+    let mask = art.apply(node, &[Instruction::Padding(Sides::all(8.0)), Instruction::Margin(Sides::all(0.0))]);
+    assert_eq!(mask, DIRTY_PADDING);
+    assert_eq!(art.node(node).data.dirty_mask, DIRTY_PADDING);
+    assert!(art.take_patches().is_empty(), "padding is not painted");
+
+    let mask = art.apply(node, &[Instruction::Padding(Sides::all(8.0)), Instruction::Background(Background::Color(blue()))]);
+    assert_eq!(mask, DIRTY_BACKGROUND);
+    assert_eq!(art.node(node).data.dirty_mask, DIRTY_PADDING | DIRTY_BACKGROUND);
+
+    let patches = art.take_patches();
+    let [Patch::SetPaint { node: painted, mask, paint }] = patches.as_slice() else { panic!("expected one paint patch: {patches:?}") };
+    assert_eq!(*painted, node);
+    assert_eq!(*mask, DIRTY_BACKGROUND);
+    assert_eq!(paint.background, Background::Color(blue()));
+}
+
+#[test]
+fn applying_the_same_instructions_again_changes_nothing() {
+    let mut art = Art::new();
+    let node = art.create(NodeKind::Text, None);
+    let chain = [Instruction::Color(blue()), Instruction::Underline, Instruction::CornerRadius(Sides::all(4.0))];
+
+    assert_eq!(art.apply(node, &chain), DIRTY_COLOR | DIRTY_UNDERLINE | DIRTY_CORNER_RADIUS);
+    art.take_patches();
+
+    assert_eq!(art.apply(node, &chain), 0);
+    assert!(art.take_patches().is_empty());
+}
+
+#[test]
+fn later_instructions_override_the_sides_they_specify() {
+    let data = supplied_data(&[
+        Instruction::Padding(Sides::all(16.0)),
+        padding(Some(4.0), None, None, None),
+        Instruction::Border { sides: Sides::all(1.0), color: None },
+        Instruction::Font { name: None, size: Some(20.0), weight: None, color: None },
+    ]);
+
+    assert_eq!(data.padding, Surrounding { top: 4.0, right: 16.0, bottom: 16.0, left: 16.0 });
+    assert_eq!(data.border_color, Color::BLACK);
+    assert_eq!(data.font.size, 20.0);
+    assert_eq!(data.font.weight, 400);
+    assert_eq!(data.surrounding(), Surrounding { top: 5.0, right: 17.0, bottom: 17.0, left: 17.0 });
+}
+
+#[test]
+fn a_removed_instruction_resets_its_field() {
+    let mut art = Art::new();
+    let node = art.create(NodeKind::Box, None);
+    art.apply(node, &[Instruction::PointerEvents(false), Instruction::Width(Dimension { value: DimValue::Fixed(100.0), min: None, max: None })]);
+    art.clear_dirty(node);
+    art.take_patches();
+
+    let mask = art.apply(node, &[Instruction::Width(Dimension { value: DimValue::Fixed(100.0), min: None, max: None })]);
+    assert_eq!(mask, DIRTY_POINTER_EVENTS);
+    assert!(art.node(node).data.supplied.pointer_events);
+}
+
+#[test]
+fn structural_changes_are_patches() {
+    let mut art = Art::new();
+    let column = art.create(NodeKind::Column, None);
+    let first = art.create(NodeKind::Text, Some(column));
+    let row = art.create(NodeKind::Row, Some(column));
+    let inner = art.create(NodeKind::Text, Some(row));
+
+    assert_eq!(art.node(first).data.dirty_mask, LAYOUT_MASK | PAINT_MASK);
+    assert_eq!(art.take_patches(), vec![
+        Patch::Create { node: column, kind: NodeKind::Column, parent: None, index: 0 },
+        Patch::Create { node: first, kind: NodeKind::Text, parent: Some(column), index: 0 },
+        Patch::Create { node: row, kind: NodeKind::Row, parent: Some(column), index: 1 },
+        Patch::Create { node: inner, kind: NodeKind::Text, parent: Some(row), index: 0 },
+    ]);
+
+    art.move_to(first, Some(column), 1);
+    assert_eq!(art.node(column).children, vec![row, first]);
+
+    art.remove(row);
+    assert_eq!(art.node(column).children, vec![first]);
+    assert!(!art.contains(inner));
+    assert_eq!(art.len(), 2);
+
+    assert_eq!(art.take_patches(), vec![
+        Patch::Move { node: first, parent: Some(column), index: 1 },
+        Patch::Remove { node: row },
+    ]);
+}
+
+#[test]
+fn text_and_bounds_are_patched_when_they_change() {
+    let mut art = Art::new();
+    let node = art.create(NodeKind::Text, None);
+    art.clear_dirty(node);
+    art.take_patches();
+
+    art.set_text(node, "Hello");
+    art.set_text(node, "Hello");
+    assert_eq!(art.node(node).data.dirty_mask, DIRTY_TEXT);
+
+    let bounds = Rect { x: 0.0, y: 0.0, width: 40.0, height: 16.0 };
+    art.set_bounds(node, bounds);
+    art.set_bounds(node, bounds);
+
+    let patches = art.take_patches();
+    assert_eq!(patches.len(), 2);
+    assert!(matches!(&patches[0], Patch::SetPaint { mask: DIRTY_TEXT, paint, .. } if paint.text.as_deref() == Some("Hello")));
+    assert_eq!(patches[1], Patch::SetBounds { node, bounds });
+}
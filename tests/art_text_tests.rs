@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use fluxum::art::instruction::*;
+use fluxum::art::layout::{LayoutEngine, Size};
+use fluxum::art::render_data::*;
+use fluxum::art::text::*;
+use fluxum::art::{Art, NodeKey, NodeKind};
+
+fn add(art: &mut Art, kind: NodeKind, parent: Option<NodeKey>, instructions: &[Instruction]) -> NodeKey {
+    let node = art.create(kind, parent);
+    art.apply(node, instructions);
+    node
+}
+
+fn text(art: &mut Art, parent: NodeKey, text: &str, size: f32) -> NodeKey {
+    let node = add(art, NodeKind::Text, Some(parent), &[Instruction::Font { name: None, size: Some(size), weight: None, color: None }]);
+    art.set_text(node, text);
+    node
+}
+
+fn fixed(value: Dip) -> Dimension {
+    Dimension { value: DimValue::Fixed(value), min: None, max: None }
+}
+
+fn style(size: f32) -> TextStyle<'static> {
+    TextStyle { font: "", size, weight: 400, small_caps: false, letter_spacing: 0.0, line_height: None }
+}
+
+fn lines<'a>(text: &'a str, layout: &TextLayout) -> Vec<&'a str> {
+    layout.lines.iter().map(|line| &text[line.text.clone()]).collect()
+}
+
+fn bounds(art: &Art, node: NodeKey) -> Rect {
+    art.node(node).data.derived.bounds
+}
+
+// ---------------------------------------------------------------------------
+// Monospace measurer
+// ---------------------------------------------------------------------------
+
+#[test]
+fn monospace_text_is_wrapped_at_whitespace() {
+    let text = "hello brave new world";
+    let layout = MonospaceMeasurer::default().measure(text, &style(16.0), Some(100.0));
+
+    assert_eq!(lines(text, &layout), ["hello brave", "new world"]);
+    assert_eq!(layout.lines.iter().map(|line| line.bounds.width).collect::<Vec<_>>(), [88.0, 72.0]);
+    assert_eq!(layout.lines.iter().map(|line| line.baseline).collect::<Vec<_>>(), [14.0, 34.0]);
+    assert_eq!((layout.width, layout.height), (88.0, 40.0));
+    assert_eq!(layout.baseline(), Some(14.0));
+
+    // without a wrap width, a single line
+    let layout = MonospaceMeasurer::default().measure(text, &style(16.0), None);
+    assert_eq!(lines(text, &layout), [text]);
+    assert_eq!(layout.width, 168.0);
+}
+
+#[test]
+fn monospace_line_breaks() {
+    let measurer = MonospaceMeasurer::default();
+
+    // a word wider than the line is broken between characters
+    let text = "abcdefghij xy";
+    assert_eq!(lines(text, &measurer.measure(text, &style(10.0), Some(20.0))), ["abcd", "efgh", "ij", "xy"]);
+
+    // new lines are kept, empty ones too
+    let text = "one\n\ntwo";
+    let layout = measurer.measure(text, &style(10.0), None);
+    assert_eq!(lines(text, &layout), ["one", "", "two"]);
+    assert_eq!(layout.height, 37.5);
+
+    assert_eq!(measurer.measure("", &style(10.0), None), TextLayout::default());
+}
+
+#[test]
+fn monospace_letter_spacing_and_line_height() {
+    let style = TextStyle { letter_spacing: 2.0, line_height: Some(30.0), ..style(16.0) };
+    let layout = MonospaceMeasurer::default().measure("abc def", &style, Some(40.0));
+
+    assert_eq!(lines("abc def", &layout), ["abc", "def"]);
+    assert_eq!(layout.width, 30.0);
+    assert_eq!(layout.height, 60.0);
+    // the leading is shared above and below the glyphs
+    assert_eq!(layout.baseline(), Some(19.0));
+}
+
+// ---------------------------------------------------------------------------
+// Layout
+// ---------------------------------------------------------------------------
+
+#[test]
+fn text_nodes_are_sized_to_their_text() {
+    let mut art = Art::new();
+    let column = add(&mut art, NodeKind::Column, None, &[Instruction::Width(fixed(100.0))]);
+    let wrapped = text(&mut art, column, "hello brave new world", 16.0);
+    let single = text(&mut art, column, "hello brave new world", 16.0);
+    art.apply(single, &[Instruction::TextWrap(false)]);
+
+    let mut engine = LayoutEngine::new();
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+
+    assert_eq!(bounds(&art, wrapped), Rect { x: 0.0, y: 0.0, width: 88.0, height: 40.0 });
+    assert_eq!(bounds(&art, single), Rect { x: 0.0, y: 40.0, width: 168.0, height: 20.0 });
+    assert_eq!(art.node(wrapped).data.derived.text.as_ref().unwrap().lines.len(), 2);
+
+    // a new text is measured again
+    art.set_text(wrapped, "hello");
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    assert_eq!(bounds(&art, wrapped).height, 20.0);
+    assert_eq!(bounds(&art, single).y, 20.0);
+}
+
+#[test]
+fn baseline_alignment_in_a_row() {
+    let mut art = Art::new();
+    let row = add(&mut art, NodeKind::Row, None, &[
+        Instruction::AlignItems(Alignment { horizontal: None, vertical: Some(VAlign::Baseline) }),
+    ]);
+    let small = text(&mut art, row, "ab", 16.0);
+    let large = text(&mut art, row, "ab", 32.0);
+    let block = add(&mut art, NodeKind::Box, Some(row), &[Instruction::Width(fixed(10.0)), Instruction::Height(fixed(10.0))]);
+
+    LayoutEngine::new().layout(&mut art, Size::new(800.0, 600.0));
+
+    // the baselines are at 14 and 28 in the texts, a box without text sits on it
+    assert_eq!(bounds(&art, small).y, 14.0);
+    assert_eq!(bounds(&art, large).y, 0.0);
+    assert_eq!(bounds(&art, block).y, 18.0);
+    assert_eq!(bounds(&art, row).height, 40.0);
+}
+
+#[test]
+fn baseline_alignment_in_a_flow_box() {
+    let mut art = Art::new();
+    let flow = add(&mut art, NodeKind::FlowBox, None, &[
+        Instruction::Width(fixed(50.0)),
+        Instruction::AlignItems(Alignment { horizontal: None, vertical: Some(VAlign::Baseline) }),
+    ]);
+    let small = text(&mut art, flow, "ab", 16.0);
+    let large = text(&mut art, flow, "ab", 32.0);
+    let next = text(&mut art, flow, "abcd", 16.0);
+
+    LayoutEngine::new().layout(&mut art, Size::new(800.0, 600.0));
+
+    assert_eq!(bounds(&art, small), Rect { x: 0.0, y: 14.0, width: 16.0, height: 20.0 });
+    assert_eq!(bounds(&art, large), Rect { x: 16.0, y: 0.0, width: 32.0, height: 40.0 });
+    assert_eq!(bounds(&art, next), Rect { x: 0.0, y: 40.0, width: 32.0, height: 20.0 });
+}
+
+// Measures with the monospace metrics, recording the size and the small caps of each style.
+struct Recording(Rc<RefCell<Vec<(Dip, bool)>>>);
+
+impl TextMeasurer for Recording {
+    fn measure(&self, text: &str, style: &TextStyle, wrap_width: Option<Dip>) -> TextLayout {
+        self.0.borrow_mut().push((style.size, style.small_caps));
+        MonospaceMeasurer::default().measure(text, style, wrap_width)
+    }
+}
+
+#[test]
+fn font_sizes_are_scaled_and_small_caps_reach_the_measurer() {
+    let mut art = Art::new();
+    let column = add(&mut art, NodeKind::Column, None, &[]);
+    let node = text(&mut art, column, "ab", 16.0);
+
+    let styles = Rc::new(RefCell::new(Vec::new()));
+    let mut engine = LayoutEngine::with_measurer(Recording(styles.clone()));
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    assert_eq!(bounds(&art, node), Rect { x: 0.0, y: 0.0, width: 16.0, height: 20.0 });
+
+    // 16 SP at 1.5 DIP per SP
+    engine.set_font_scale(1.5);
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    assert_eq!(bounds(&art, node), Rect { x: 0.0, y: 0.0, width: 24.0, height: 30.0 });
+    assert_eq!(styles.borrow().last(), Some(&(24.0, false)));
+
+    art.apply(node, &[Instruction::SmallCaps]);
+    engine.layout(&mut art, Size::new(800.0, 600.0));
+    assert_eq!(styles.borrow().last(), Some(&(24.0, true)));
+}
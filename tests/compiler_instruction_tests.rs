@@ -0,0 +1,144 @@
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::Ident;
+
+use fluxum::compiler::ast::{Modifier, ModifierArg};
+use fluxum::compiler::instruction::*;
+
+// `padding { 8 top: 2 } .. fill`, values are single tokens
+fn chain(src: &str) -> Vec<Modifier> {
+    src.split(" .. ").map(|item| {
+        let (name, args) = match item.split_once(" { ") {
+            Some((name, args)) => (name, args.trim_end_matches(" }")),
+            None => (item, ""),
+        };
+        let mut parsed = Vec::new();
+        let mut arg_name = None;
+        for token in args.split_whitespace() {
+            // positional values may be separated by commas, `border { Gray, 1 }`
+            let token = token.trim_end_matches(',');
+            match token.strip_suffix(':') {
+                Some(n) => arg_name = Some(Ident::new(n, Span::call_site())),
+                None => parsed.push(ModifierArg { name: arg_name.take(), value: syn::parse_str(token).unwrap() }),
+            }
+        }
+        Modifier { name: Ident::new(name, Span::call_site()), args: parsed }
+    }).collect()
+}
+
+fn parse(src: &str) -> Vec<Instruction> {
+    match parse_instructions(&chain(src)) {
+        Ok(instructions) => instructions,
+        Err(error) => panic!("unexpected error: {error}"),
+    }
+}
+
+fn errors(src: &str) -> Vec<String> {
+    match parse_instructions(&chain(src)) {
+        Ok(_) => panic!("parsing should fail"),
+        Err(error) => error.into_iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+fn value(expr: &Option<syn::Expr>) -> String {
+    expr.as_ref().map_or(String::from("-"), |expr| expr.to_token_stream().to_string())
+}
+
+#[test]
+fn surrounding_shorthands_expand_to_sides() {
+    let instructions = parse("padding { 8 top: 2 horizontal: 4 } .. border { vertical: 1 color: red }");
+
+    let Instruction::Surrounding { kind: SurroundingKind::Padding, sides, color: None } = &instructions[0] else { panic!() };
+    assert_eq!([&sides.top, &sides.right, &sides.bottom, &sides.left].map(value), ["2", "4", "8", "4"]);
+
+    let Instruction::Surrounding { kind: SurroundingKind::Border, sides, color } = &instructions[1] else { panic!() };
+    assert_eq!([&sides.top, &sides.right, &sides.bottom, &sides.left].map(value), ["1", "-", "1", "-"]);
+    assert_eq!(value(color), "red");
+}
+
+#[test]
+fn border_takes_a_color_and_a_width() {
+    let instructions = parse("border { Gray, 1 } .. border { 2 } .. border { red, 1 top: 3 }");
+
+    let Instruction::Surrounding { kind: SurroundingKind::Border, sides, color } = &instructions[0] else { panic!() };
+    assert_eq!([&sides.top, &sides.right, &sides.bottom, &sides.left].map(value), ["1", "1", "1", "1"]);
+    assert_eq!(value(color), "Gray");
+
+    let Instruction::Surrounding { sides, color: None, .. } = &instructions[1] else { panic!() };
+    assert_eq!(value(&sides.left), "2");
+
+    let Instruction::Surrounding { sides, color, .. } = &instructions[2] else { panic!() };
+    assert_eq!([&sides.top, &sides.right].map(value), ["3", "1"]);
+    assert_eq!(value(color), "red");
+
+    // the color once, and only for borders
+    assert_eq!(errors("border { Gray, 1 color: red }"), ["`border` has a color value and a `color` argument"]);
+    assert_eq!(errors("padding { Gray, 1 }"), ["`padding` takes one value"]);
+    assert_eq!(errors("border { Gray, 1, 2 }"), ["`border` takes 2 values"]);
+}
+
+#[test]
+fn shorthands_expand() {
+    let instructions = parse("fill .. fit_content .. align_items_center .. align_self_end_baseline .. constrain_reverse .. gap { 4 }");
+
+    let dims: Vec<_> = instructions.iter().filter_map(|instruction| match instruction {
+        Instruction::Dimension { axis, value, .. } => Some((*axis, matches!(value, DimValue::Content))),
+        _ => None,
+    }).collect();
+    assert_eq!(dims, vec![(Axis::Width, false), (Axis::Height, false), (Axis::Width, true), (Axis::Height, true)]);
+
+    assert!(matches!(instructions[4], Instruction::Align {
+        target: AlignTarget::Items, horizontal: Some(HAlign::Center), vertical: Some(VAlign::Center),
+    }));
+    assert!(matches!(instructions[5], Instruction::Align {
+        target: AlignTarget::SelfNode, horizontal: Some(HAlign::End), vertical: Some(VAlign::Baseline),
+    }));
+    assert!(matches!(instructions[6], Instruction::FillStrategy(FillStrategy::ConstrainReverse)));
+    let Instruction::Gap { width, height } = &instructions[7] else { panic!() };
+    assert_eq!((value(width), value(height)), ("4".into(), "4".into()));
+}
+
+#[test]
+fn invalid_instructions_are_reported_together() {
+    assert_eq!(errors("paddin { 16 } .. padding { lft: 4 } .. width { 10 top: 1 } .. font { 12 } .. align_self_middle .. text_wrap { maybe } .. underline { 1 }"), vec![
+        "unknown instruction `paddin`",
+        "`padding` has no argument `lft`, expected `top`, `right`, `bottom`, `left`, `horizontal` or `vertical`",
+        "`padding` needs at least one side",
+        "`width` has no argument `top`, expected `min` or `max`",
+        "`font` takes only named arguments",
+        "`font` needs at least one of `name`, `size`, `weight`, `color`",
+        "unknown instruction `align_self_middle`, expected `<horizontal>_<vertical>` after the target",
+        "expected `none` or `wrap` for `text_wrap`",
+        "`underline` takes no arguments",
+    ]);
+}
+
+#[test]
+fn scroll_conflicts_with_content_size_on_the_same_axis() {
+    assert_eq!(errors("scroll { horizontal } .. fit_content"), vec![
+        "`scroll { horizontal }` conflicts with `width { content }` on the same node",
+    ]);
+    assert_eq!(errors("height { content } .. scroll { both }"), vec![
+        "`scroll { both }` conflicts with `height { content }` on the same node",
+    ]);
+
+    parse("scroll { vertical } .. width { content }");
+}
+
+#[test]
+fn grid_tracks_are_dips_content_or_fractions() {
+    let instructions = parse("grid_template { columns: [120,content,1fr,2.5fr] } .. grid_cell { row: 1 column: 0 column_span: 2 }");
+
+    let Instruction::GridTemplate { columns: Some(columns), rows: None } = &instructions[0] else { panic!() };
+    assert!(matches!(columns.as_slice(), [Track::Fixed(_), Track::Content, Track::Fraction(1.0), Track::Fraction(2.5)]));
+
+    let Instruction::GridCell { row, column, row_span, column_span } = &instructions[1] else { panic!() };
+    assert_eq!([value(&Some(row.clone())), value(&Some(column.clone())), value(row_span), value(column_span)], ["1", "0", "-", "2"]);
+
+    assert_eq!(errors("grid_template { columns: 3 rows: [0fr] } .. grid_template .. grid_cell { row: 1 }"), vec![
+        "`columns` needs a list of tracks, `[120, content, 1fr]`",
+        "a fraction needs a positive weight",
+        "`grid_template` needs `columns`, `rows` or both",
+        "`grid_cell` needs `column`",
+    ]);
+}
@@ -0,0 +1,143 @@
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::Ident;
+
+use fluxum::compiler::ast::*;
+use fluxum::compiler::interpolate::interpolate;
+use fluxum::compiler::lower::lower_fragment;
+use fluxum::fir::*;
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+fn lit(text: &str) -> syn::Expr {
+    let lit = syn::LitStr::new(text, Span::call_site());
+    syn::parse_quote!(#lit)
+}
+
+const STORES: [&str; 3] = ["label", "count", "price"];
+
+fn spec(text: &str) -> Option<DerivedSpec> {
+    interpolate(&lit(text), &|name| STORES.iter().any(|s| name == s)).unwrap()
+}
+
+fn error(text: &str) -> String {
+    match interpolate(&lit(text), &|name| STORES.iter().any(|s| name == s)) {
+        Ok(_) => panic!("interpolation should fail"),
+        Err(error) => error.to_string(),
+    }
+}
+
+fn body(spec: &DerivedSpec) -> String {
+    spec.body.to_token_stream().to_string()
+}
+
+fn expected(tokens: proc_macro2::TokenStream) -> String {
+    tokens.to_string()
+}
+
+#[test]
+fn plain_literals_are_left_alone() {
+    assert!(spec("Click me").is_none());
+    assert!(spec("costs $5 {not a placeholder}").is_none());
+}
+
+#[test]
+fn placeholders_without_stores_are_formatted_as_constants() {
+    let sum = spec("${1 + 1} items").unwrap();
+    assert!(sum.uses.is_empty());
+    assert_eq!(body(&sum), expected(quote::quote!(::std::format!("{} items", 1 + 1))));
+
+    let escaped = spec("$${x} {y}").unwrap();
+    assert!(escaped.uses.is_empty());
+    assert_eq!(body(&escaped), expected(quote::quote!(::std::format!("${{x}} {{y}}"))));
+}
+
+#[test]
+fn names_become_format_arguments() {
+    let spec = spec("${label}: ${count}").unwrap();
+    assert_eq!(spec.uses, vec![ident("label"), ident("count")]);
+    assert_eq!(body(&spec), expected(quote::quote!(::std::format!("{}: {}", label, count))));
+}
+
+#[test]
+fn expressions_and_format_specs() {
+    let spec = spec("{${count * 2}} total ${price:.2} of ${std::f64::consts::PI:>8.3} $${raw}").unwrap();
+    assert_eq!(spec.uses, vec![ident("count"), ident("price")]);
+    assert_eq!(
+        body(&spec),
+        expected(quote::quote!(::std::format!(
+            "{{{}}} total {:.2} of {:>8.3} ${{raw}}",
+            count * 2, price, std::f64::consts::PI
+        ))),
+    );
+}
+
+#[test]
+fn only_format_specs_are_split_off() {
+    let spec = spec("${count:?} ${label:*^10} ${price:+#08.2e} ${count:>1$} ${|a: i32| a + count}").unwrap();
+    assert_eq!(spec.uses, vec![ident("count"), ident("label"), ident("price")]);
+    assert_eq!(
+        body(&spec),
+        expected(quote::quote!(::std::format!(
+            "{:?} {:*^10} {:+#08.2e} {:>1$} {}",
+            count, label, price, count, |a: i32| a + count
+        ))),
+    );
+}
+
+#[test]
+fn unknown_names_and_bad_placeholders_are_errors() {
+    assert_eq!(error("${missing}"), "cannot find store `missing` in this fragment");
+    assert_eq!(error("${label"), "unterminated `${` in string literal");
+    assert!(error("${label +}").starts_with("invalid expression in `${label +}`"));
+}
+
+#[test]
+fn interpolated_literals_lower_to_derived_stores() {
+    let decl = FragmentDecl {
+        name: ident("Counter"),
+        params: vec![Param {
+            name: ident("label"),
+            kind: StoreKind::Readable,
+            ty: syn::parse_str("String").unwrap(),
+            span: Span::call_site(),
+        }],
+        slots: Vec::new(),
+        body: Block {
+            items: vec![
+                BuildStmt::Store(StoreDecl {
+                    kind: StoreKind::Writable,
+                    name: ident("title"),
+                    ty: None,
+                    init: StoreInit::Literal(lit("Counter of ${label}")),
+                    eq: None,
+                    span: Span::call_site(),
+                }),
+                BuildStmt::Node(NodeDecl {
+                    name: ident("text"),
+                    args: vec![NodeArg::Const(lit("${title}!")), NodeArg::Const(lit("$${title}"))],
+                    children: None,
+                    slots: Vec::new(),
+                    chain: Vec::new(),
+                    span: Span::call_site(),
+                }),
+            ],
+            span: Span::call_site(),
+        },
+        span: Span::call_site(),
+    };
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    let mut ops = Vec::new();
+    for (opcode, arg) in [(OP_VERSION, FIR_VERSION), (OP_DERIVED, 0), (OP_BEGIN, 0), (OP_ARG_DERIVED, 1), (OP_ARG_CONST, 0), (OP_END, 0)] {
+        encode_op(&mut ops, opcode, arg);
+    }
+    assert_eq!(fir.ops, ops);
+    assert_eq!(fir.derived_handlers[0].uses, vec![0]);
+    assert_eq!(fir.derived_handlers[1].uses, vec![1]);
+    // the escaped literal is formatted once
+    assert_eq!(fir.resources.len(), 1);
+}
@@ -0,0 +1,603 @@
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::Ident;
+
+use fluxum::compiler::ast::*;
+use fluxum::compiler::instruction::Instruction;
+use fluxum::compiler::lower::{ChainPart, DeriveBody, LoweredDerive, LoweredResource, lower_fragment};
+use fluxum::fir::*;
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+fn expr(src: &str) -> syn::Expr {
+    syn::parse_str(src).unwrap()
+}
+
+fn path(src: &str) -> syn::Path {
+    syn::parse_str(src).unwrap()
+}
+
+fn block(items: Vec<BuildStmt>) -> Block {
+    Block { items, span: Span::call_site() }
+}
+
+fn fragment(params: &[&str], items: Vec<BuildStmt>) -> FragmentDecl {
+    FragmentDecl {
+        name: ident("App"),
+        params: params.iter().map(|name| Param {
+            name: ident(name),
+            kind: StoreKind::Readable,
+            ty: syn::parse_str("String").unwrap(),
+            span: Span::call_site(),
+        }).collect(),
+        slots: Vec::new(),
+        body: block(items),
+        span: Span::call_site(),
+    }
+}
+
+fn store(kind: StoreKind, name: &str, value: &str) -> BuildStmt {
+    BuildStmt::Store(StoreDecl {
+        kind,
+        name: ident(name),
+        ty: None,
+        init: StoreInit::Literal(expr(value)),
+        eq: None,
+        span: Span::call_site(),
+    })
+}
+
+fn node(name: &str, args: Vec<NodeArg>, children: Option<Vec<BuildStmt>>) -> BuildStmt {
+    BuildStmt::Node(NodeDecl {
+        name: ident(name),
+        args,
+        children: children.map(block),
+        slots: Vec::new(),
+        chain: Vec::new(),
+        span: Span::call_site(),
+    })
+}
+
+fn provide(key: &str, store: &str) -> BuildStmt {
+    BuildStmt::Provide(ProvideStmt { key: path(key), store: ident(store), span: Span::call_site() })
+}
+
+fn use_context(name: &str, key: &str) -> BuildStmt {
+    BuildStmt::Use(UseStmt { name: ident(name), key: path(key), span: Span::call_site() })
+}
+
+fn ops(list: &[(u8, u32)]) -> Vec<u8> {
+    let mut ops = Vec::new();
+    for &(opcode, arg) in list {
+        encode_op(&mut ops, opcode, arg);
+    }
+    ops
+}
+
+fn error(decl: &FragmentDecl) -> String {
+    match lower_fragment(decl) {
+        Ok(_) => panic!("lowering should fail"),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn op_encoding_round_trip() {
+    let list = [(OP_BEGIN, 3), (OP_ARG_PASS, 0x1234), (OP_PROVIDE, provide_arg(2, 1)), (OP_END, 0xffff_ffff)];
+    let ops = ops(&list);
+    assert_eq!(&ops[..2], &[OP_BEGIN | ARG_LEN_1, 3]);
+    assert_eq!(ops[2], OP_ARG_PASS | ARG_LEN_2);
+    assert_eq!(OpReader::new(&ops).collect::<Vec<_>>(), list);
+}
+
+#[test]
+fn lowers_stores_and_nodes() {
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Writable, "count", "0"),
+        node("column", vec![], Some(vec![
+            node("text", vec![NodeArg::Pass(ident("label"))], None),
+            node("text", vec![NodeArg::Const(expr("\"Click me\""))], None),
+        ])),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, store_arg(0, 1)),
+        (OP_BEGIN, 0),
+        (OP_BEGIN, 1), (OP_ARG_PASS, 0), (OP_END, 0),
+        (OP_BEGIN, 1), (OP_ARG_CONST, 1), (OP_END, 0),
+        (OP_END, 0),
+    ]));
+    assert_eq!((fir.ext_store_count, fir.own_store_count, fir.node_count), (1, 1, 3));
+    assert_eq!(fir.dependencies, vec![ident("column"), ident("text")]);
+    assert_eq!(fir.resources.len(), 2);
+}
+
+fn compared(kind: StoreKind, name: &str, ty: Option<&str>, value: &str, eq: Option<EqSpec>) -> BuildStmt {
+    BuildStmt::Store(StoreDecl {
+        kind,
+        name: ident(name),
+        ty: ty.map(|ty| syn::parse_str(ty).unwrap()),
+        init: StoreInit::Literal(expr(value)),
+        eq,
+        span: Span::call_site(),
+    })
+}
+
+#[test]
+fn lowers_eq_strategies() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "count", "0"),
+        compared(StoreKind::Writable, "rows", Some("Rc<[Row]>"), "load_rows()", Some(EqSpec::Ptr)),
+        compared(StoreKind::Readable, "selected", Some("Row"), "first_row()", Some(EqSpec::Fn(path("same_row_id")))),
+        compared(StoreKind::Writable, "flag", None, "false", Some(EqSpec::Always)),
+        store(StoreKind::Writable, "items", "Vec::new()"),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    // literals are compared, other values notify on every write
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, store_arg(0, 1)),
+        (OP_WRITABLE, store_arg(1, 2)),
+        (OP_READABLE, store_arg(2, 3)),
+        (OP_WRITABLE, 3),
+        (OP_WRITABLE, 4),
+    ]));
+    let strategies: Vec<String> = fir.eq_strategies.iter().map(tokens).collect();
+    assert_eq!(strategies[..2], [
+        tokens(&quote::quote!(::fluxum::store::EqStrategy::Compare(::fluxum::store::mk_eq_fn::<i32>()))),
+        tokens(&quote::quote!(::fluxum::store::EqStrategy::Compare(::fluxum::store::mk_ptr_eq_fn::<Rc<[Row]> >()))),
+    ]);
+    assert!(strategies[2].contains("same_row_id (old , new)"), "{}", strategies[2]);
+}
+
+#[test]
+fn eq_strategy_errors() {
+    let untyped = fragment(&[], vec![compared(StoreKind::Writable, "rows", None, "load_rows()", Some(EqSpec::Fingerprint))]);
+    assert_eq!(error(&untyped), "cannot infer the type of store `rows` for its `eq` strategy, declare it as `store rows: Type = ...`");
+
+    let constant = fragment(&[], vec![compared(StoreKind::Const, "step", None, "1", Some(EqSpec::Deep))]);
+    assert_eq!(error(&constant), "only readable and writable stores have an `eq` strategy");
+}
+
+#[test]
+fn lowers_provide_and_use() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Readable, "theme", "Theme::dark()"),
+        provide("Theme", "theme"),
+        node("Panel", vec![], Some(vec![
+            use_context("locale", "i18n::Locale"),
+            provide("i18n::Locale", "locale"),
+            node("text", vec![NodeArg::Pass(ident("locale"))], None),
+        ])),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_READABLE, 0),
+        (OP_PROVIDE, provide_arg(0, 0)),
+        (OP_BEGIN, 0),
+        (OP_USE, 1),
+        (OP_PROVIDE, provide_arg(1, 1)),
+        (OP_BEGIN, 1), (OP_ARG_PASS, 1), (OP_END, 0),
+        (OP_END, 0),
+    ]));
+    assert_eq!(fir.contexts.len(), 2);
+    assert_eq!(fir.own_store_count, 1);
+}
+
+#[test]
+fn used_names_are_block_scoped() {
+    let decl = fragment(&[], vec![
+        node("Panel", vec![], Some(vec![use_context("theme", "Theme")])),
+        node("text", vec![NodeArg::Pass(ident("theme"))], None),
+    ]);
+
+    assert_eq!(error(&decl), "cannot find store `theme` in this fragment");
+}
+
+#[test]
+fn provide_of_unknown_store_is_an_error() {
+    let decl = fragment(&[], vec![provide("Theme", "theme")]);
+    assert_eq!(error(&decl), "cannot find store `theme` in this fragment");
+}
+
+// ---------------------------------------------------------------------------
+// Event handlers
+// ---------------------------------------------------------------------------
+
+fn on_click(body: &str) -> NodeArg {
+    NodeArg::EventHandler(EventHandler { name: ident("on_click"), body: BlockOrExpr::Expr(expr(body)) })
+}
+
+fn button(handler: NodeArg) -> BuildStmt {
+    node("button", vec![handler], None)
+}
+
+fn tokens(item: &impl ToTokens) -> String {
+    item.to_token_stream().to_string()
+}
+
+fn derive_body(derive: &LoweredDerive) -> String {
+    match &derive.body {
+        DeriveBody::Value(body) => tokens(body),
+        DeriveBody::Instructions(_) => panic!("expected a value"),
+    }
+}
+
+#[test]
+fn handler_reads_and_writes_stores() {
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Writable, "count", "0"),
+        button(on_click("count = count + label.len() as i32")),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops[fir.ops.len() - 4..], ops(&[(OP_ARG_EH, 0), (OP_END, 0)]));
+
+    let handler = &fir.events_handlers[0];
+    assert_eq!(handler.uses, vec![1, 0]);
+
+    let expected: syn::ItemFn = syn::parse_quote! {
+        fn eh_0(__stores: &[::fluxum::store::StoreKey], __rt: &mut ::fluxum::store::StoreEffects) {
+            {
+                let __value: ::std::boxed::Box<i32> = ::std::boxed::Box::new(
+                    ::std::clone::Clone::clone(__rt.get_value(__stores[0usize]).downcast_ref::<i32>().unwrap())
+                        + ::std::clone::Clone::clone(__rt.get_value(__stores[1usize]).downcast_ref::<String>().unwrap()).len() as i32
+                );
+                __rt.set_value(__stores[0usize], __value);
+            };
+        }
+    };
+    assert_eq!(tokens(&handler.item), tokens(&expected));
+}
+
+#[test]
+fn handler_compound_assignment_updates_in_place() {
+    let decl = fragment(&[], vec![
+        BuildStmt::Store(StoreDecl {
+            kind: StoreKind::Writable,
+            name: ident("total"),
+            ty: Some(syn::parse_str("u64").unwrap()),
+            init: StoreInit::Literal(expr("0")),
+            eq: None,
+            span: Span::call_site(),
+        }),
+        button(on_click("total += 2")),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+    let body = tokens(&fir.events_handlers[0].item);
+
+    assert!(body.contains("update_value"), "{body}");
+    assert!(body.contains("downcast_mut :: < u64 >"), "{body}");
+}
+
+#[test]
+fn handler_locals_shadow_stores() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Const, "step", "1"),
+        button(on_click("{ let step = 5; step }")),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+    assert!(fir.events_handlers[0].uses.is_empty());
+}
+
+#[test]
+fn handler_pattern_bindings_shadow_stores() {
+    let uses = |body: &str| {
+        let decl = fragment(&[], vec![store(StoreKind::Writable, "count", "0"), button(on_click(body))]);
+        lower_fragment(&decl).unwrap().events_handlers[0].uses.clone()
+    };
+
+    assert!(uses("match Some(1) { Some(count) => count, None => 0 }").is_empty());
+    assert!(uses("for count in 0..3 { let _ = count; }").is_empty());
+    assert!(uses("if let Some(count) = Some(1) { let _ = count; }").is_empty());
+    assert!(uses("while let Some(count) = None::<i32> { let _ = count; }").is_empty());
+
+    // outside of the binding the store is read
+    assert_eq!(uses("match Some(1) { Some(count) => count, None => count }"), vec![0]);
+    assert_eq!(uses("for i in 0..count { let _ = i; }"), vec![0]);
+    assert_eq!(uses("if let Some(count) = Some(1) { count } else { count }"), vec![0]);
+}
+
+#[test]
+fn handler_reads_clone_the_value() {
+    // a `String` cannot be moved out of the store
+    let decl = fragment(&["label"], vec![button(on_click("drop(label)"))]);
+
+    let fir = lower_fragment(&decl).unwrap();
+    let body = tokens(&fir.events_handlers[0].item);
+    assert!(body.contains("drop (:: std :: clone :: Clone :: clone (__rt . get_value (__stores [0usize]) . downcast_ref :: < String > () . unwrap ()))"), "{body}");
+}
+
+#[test]
+fn handler_field_shorthand_reads_the_store() {
+    let decl = fragment(&["label"], vec![button(on_click("{ let size = 1; drop(Tag { label, size }) }"))]);
+
+    let fir = lower_fragment(&decl).unwrap();
+    assert_eq!(fir.events_handlers[0].uses, vec![0]);
+    let body = tokens(&fir.events_handlers[0].item);
+    assert!(body.contains("Tag { label : :: std :: clone :: Clone :: clone (__rt . get_value (__stores [0usize])"), "{body}");
+    // a local keeps the shorthand
+    assert!(body.contains(", size }"), "{body}");
+}
+
+#[test]
+fn handler_macro_arguments_read_stores() {
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Writable, "count", "0"),
+        button(on_click("println!(\"{}: {}\", label, count + 1)")),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+    assert_eq!(fir.events_handlers[0].uses, vec![0, 1]);
+
+    let captured = fragment(&[], vec![store(StoreKind::Writable, "count", "0"), button(on_click("println!(\"{count}\")"))]);
+    assert_eq!(error(&captured), "a format string cannot capture store `count`, pass it as an argument");
+
+    let tokens = fragment(&[], vec![store(StoreKind::Writable, "count", "0"), button(on_click("vec![0; count]"))]);
+    assert_eq!(error(&tokens), "cannot use store `count` in this macro, read it into a `let` first");
+}
+
+#[test]
+fn handler_writes_to_non_writable_stores_are_errors() {
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Const, "step", "1"),
+        store(StoreKind::Readable, "limit", "10"),
+        button(on_click("{ step = 2; limit += 1; label = String::new() }")),
+    ]);
+
+    let messages: Vec<String> = match lower_fragment(&decl) {
+        Ok(_) => panic!("lowering should fail"),
+        Err(error) => error.into_iter().map(|e| e.to_string()).collect(),
+    };
+
+    assert_eq!(messages, vec![
+        "cannot assign to `step`, it is a const store",
+        "cannot assign to `limit`, it is a readable store",
+        "cannot assign to `label`, it is a readable store",
+    ]);
+}
+
+#[test]
+fn handler_needs_store_types() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "items", "Vec::new()"),
+        button(on_click("items = Vec::new()")),
+    ]);
+
+    assert_eq!(error(&decl), "cannot infer the type of store `items`, declare it as `store items: Type = ...`");
+}
+
+#[test]
+fn handler_field_assignment_is_an_error() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "point", "(0, 0)"),
+        button(on_click("point.0 = 1")),
+    ]);
+
+    assert_eq!(error(&decl), "cannot assign to a part of store `point`, assign the whole value or use a lens store");
+}
+
+fn if_stmt(keep: bool, cond: &str, then: Vec<BuildStmt>, else_arm: Option<ElseArm>) -> IfStmt {
+    IfStmt { keep, cond: expr(cond), then_block: block(then), else_arm }
+}
+
+#[test]
+fn if_chain_lowers_to_branch_with_arm_fragments() {
+    let chain = if_stmt(false, "count > 10", vec![node("text", vec![NodeArg::Pass(ident("label"))], None)], Some(
+        ElseArm::If(Box::new(if_stmt(false, "count < 0", vec![node("text", vec![NodeArg::Const(expr("\"negative\""))], None)], Some(
+            ElseArm::Block(block(vec![node("text", vec![NodeArg::Pass(ident("count"))], None)])),
+        )))),
+    ));
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Writable, "count", "0"),
+        BuildStmt::If(chain),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, store_arg(0, 1)),
+        (OP_DERIVED, 0),
+        (OP_BRANCH, 2),
+        (OP_ARM, 0), (OP_ARG_PASS, 0),
+        (OP_ARM, 1),
+        (OP_ARM, 2), (OP_ARG_PASS, 1),
+        (OP_BRANCH_END, 0),
+    ]));
+    assert_eq!(fir.own_store_count, 2);
+    assert_eq!(fir.dependencies, vec![ident("App_arm_0"), ident("App_arm_1"), ident("App_arm_2")]);
+
+    let condition = &fir.derived_handlers[0];
+    assert_eq!(condition.uses, vec![1]);
+    assert_eq!(
+        derive_body(condition),
+        quote::quote!(if count > 10 { 0u32 } else { if count < 0 { 1u32 } else { 2u32 } }).to_string(),
+    );
+
+    // the arms are fragments of their own, the captured stores are their parameters
+    let names: Vec<_> = fir.nested.iter().map(|arm| (arm.name.to_string(), arm.ext_store_count)).collect();
+    assert_eq!(names, vec![("App_arm_0".into(), 1), ("App_arm_1".into(), 0), ("App_arm_2".into(), 1)]);
+    assert_eq!(fir.nested[0].ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_BEGIN, 0), (OP_ARG_PASS, 0), (OP_END, 0),
+    ]));
+}
+
+#[test]
+fn if_on_bool_store_uses_the_store_as_condition() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "open", "false"),
+        BuildStmt::If(if_stmt(true, "open", vec![node("text", vec![NodeArg::Const(expr("\"details\""))], None)], None)),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, store_arg(0, 1)),
+        (OP_BRANCH, 0),
+        (OP_ARM, 0),
+        (OP_BRANCH_END, 1),
+    ]));
+    assert!(fir.derived_handlers.is_empty());
+}
+
+#[test]
+fn deeply_nested_blocks_capture_through_every_level() {
+    // lowering each level twice would take 2^40 passes
+    let mut items = vec![node("text", vec![NodeArg::Pass(ident("label"))], None)];
+    for _ in 0..40 {
+        items = vec![BuildStmt::If(if_stmt(false, "open", items, None))];
+    }
+    let mut body = vec![store(StoreKind::Writable, "open", "false")];
+    body.extend(items);
+
+    let fir = lower_fragment(&fragment(&["label"], body)).unwrap();
+
+    // every arm captures `open` for its own condition and `label` for the text
+    let mut arm = &fir.nested[0];
+    for _ in 1..40 {
+        assert_eq!(arm.ext_store_count, 2);
+        arm = &arm.nested[0];
+    }
+    assert_eq!(arm.ext_store_count, 1);
+    assert!(arm.nested.is_empty());
+}
+
+fn match_arm(pat: &str, guard: Option<&str>, items: Vec<BuildStmt>) -> MatchArm {
+    MatchArm { pat: syn::parse::Parser::parse_str(syn::Pat::parse_multi, pat).unwrap(), guard: guard.map(expr), body: block(items) }
+}
+
+fn match_stmt(scrutinee: &str, arms: Vec<MatchArm>) -> BuildStmt {
+    BuildStmt::Match(MatchStmt { keep: false, scrutinee: expr(scrutinee), arms, span: Span::call_site() })
+}
+
+#[test]
+fn match_lowers_to_branch_on_arm_index() {
+    let decl = fragment(&["label"], vec![
+        store(StoreKind::Writable, "mode", "Mode::List"),
+        store(StoreKind::Writable, "compact", "false"),
+        match_stmt("mode", vec![
+            match_arm("Mode::List", Some("compact"), vec![]),
+            match_arm("Mode::List | Mode::Grid", None, vec![node("text", vec![NodeArg::Pass(ident("label"))], None)]),
+            match_arm("Mode::Empty", None, vec![]),
+        ]),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, 0),
+        (OP_WRITABLE, store_arg(1, 1)),
+        (OP_DERIVED, 0),
+        (OP_BRANCH, 3),
+        (OP_ARM, 0),
+        (OP_ARM, 1), (OP_ARG_PASS, 0),
+        (OP_ARM, 2),
+        (OP_BRANCH_END, 0),
+    ]));
+
+    // no catch-all arm: rustc reports a non-exhaustive match
+    let condition = &fir.derived_handlers[0];
+    assert_eq!(condition.uses, vec![1, 2]);
+    assert_eq!(
+        derive_body(condition),
+        quote::quote!(match mode {
+            Mode::List if compact => 0u32,
+            Mode::List | Mode::Grid => 1u32,
+            Mode::Empty => 2u32,
+        }).to_string(),
+    );
+}
+
+#[test]
+fn match_pattern_bindings_are_not_supported() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "state", "Loading::Pending"),
+        match_stmt("state", vec![
+            match_arm("Loading::Pending | None", None, vec![]),
+            match_arm("Loading::Failed(message)", None, vec![]),
+        ]),
+    ]);
+
+    assert!(error(&decl).starts_with("`message` in a `match` pattern is a binding"));
+
+    // case does not tell bindings from constants, every bare identifier but `None` is rejected
+    for pattern in ["Failed", "MAX", "Some(_) | other"] {
+        let decl = fragment(&[], vec![
+            store(StoreKind::Writable, "state", "Loading::Pending"),
+            match_stmt("state", vec![match_arm(pattern, None, vec![])]),
+        ]);
+        assert!(error(&decl).contains("in a `match` pattern is a binding"), "{pattern}");
+    }
+}
+
+// `name { arg_name: value }` with a single argument
+fn styled(name: &str, args: Vec<NodeArg>, chain: &[(&str, Option<&str>, &str)]) -> BuildStmt {
+    let BuildStmt::Node(mut node) = node(name, args, None) else { unreachable!() };
+    node.chain = chain.iter().map(|(name, arg_name, value)| Modifier {
+        name: ident(name),
+        args: vec![ModifierArg { name: arg_name.map(ident), value: expr(value) }],
+    }).collect();
+    BuildStmt::Node(node)
+}
+
+#[test]
+fn instructions_using_stores_are_derived_stores() {
+    let decl = fragment(&[], vec![
+        store(StoreKind::Writable, "selected", "false"),
+        store(StoreKind::Writable, "family", "\"Mono\""),
+        styled("box", vec![], &[
+            ("background", Some("color"), "if selected { Blue } else { White }"),
+            ("padding", None, "8"),
+        ]),
+        styled("text", vec![NodeArg::Const(expr("\"a\""))], &[("font", Some("name"), "\"${family} Bold\"")]),
+        styled("column", vec![], &[("padding", None, "4")]),
+    ]);
+
+    let fir = lower_fragment(&decl).unwrap();
+
+    assert_eq!(fir.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_WRITABLE, store_arg(0, 1)),
+        (OP_WRITABLE, store_arg(1, 2)),
+        (OP_BEGIN, 0), (OP_ARG_DERIVED, 0), (OP_END, 0),
+        (OP_BEGIN, 1), (OP_ARG_CONST, 2), (OP_ARG_DERIVED, 1), (OP_END, 0),
+        (OP_BEGIN, 2), (OP_ARG_CONST, 3), (OP_END, 0),
+    ]));
+
+    let chain = |derive: &LoweredDerive| match &derive.body {
+        DeriveBody::Instructions(parts) => match parts.as_slice() {
+            [ChainPart::Inline(instructions)] => instructions.len(),
+            _ => panic!("expected the inline instructions"),
+        },
+        DeriveBody::Value(_) => panic!("expected an instruction chain"),
+    };
+    assert_eq!(fir.derived_handlers[0].uses, vec![0]);
+    assert_eq!(chain(&fir.derived_handlers[0]), 2);
+
+    // interpolated strings are formatted from the stores they use
+    assert_eq!(fir.derived_handlers[1].uses, vec![1]);
+    let DeriveBody::Instructions(parts) = &fir.derived_handlers[1].body else { panic!("expected an instruction chain") };
+    let [ChainPart::Inline(font)] = parts.as_slice() else { panic!("expected the inline instructions") };
+    let [Instruction::Font { name: Some(name), .. }] = font.as_slice() else { panic!("expected a font") };
+    assert_eq!(tokens(name), tokens(&expr("::std::format!(\"{} Bold\", family)")));
+
+    // constant chains stay resources
+    assert!(matches!(fir.resources[3], LoweredResource::Instructions(_)));
+}
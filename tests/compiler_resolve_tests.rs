@@ -0,0 +1,231 @@
+use proc_macro2::Span;
+use syn::Ident;
+
+use fluxum::compiler::ast::*;
+use fluxum::compiler::lower::lower_file;
+use fluxum::compiler::resolve::resolve_file;
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+fn expr(src: &str) -> syn::Expr {
+    syn::parse_str(src).unwrap()
+}
+
+fn block(items: Vec<BuildStmt>) -> Block {
+    Block { items, span: Span::call_site() }
+}
+
+fn param(kind: StoreKind, name: &str) -> Param {
+    Param { name: ident(name), kind, ty: syn::parse_str("i32").unwrap(), span: Span::call_site() }
+}
+
+fn fragment(name: &str, params: Vec<Param>, items: Vec<BuildStmt>) -> FragmentDecl {
+    FragmentDecl { name: ident(name), params, slots: Vec::new(), body: block(items), span: Span::call_site() }
+}
+
+fn store(kind: StoreKind, name: &str, value: &str) -> BuildStmt {
+    BuildStmt::Store(StoreDecl {
+        kind,
+        name: ident(name),
+        ty: None,
+        init: StoreInit::Literal(expr(value)),
+        eq: None,
+        span: Span::call_site(),
+    })
+}
+
+fn node(name: &str, args: Vec<NodeArg>, children: Option<Vec<BuildStmt>>) -> BuildStmt {
+    BuildStmt::Node(NodeDecl { name: ident(name), args, children: children.map(block), slots: Vec::new(), chain: Vec::new(), span: Span::call_site() })
+}
+
+fn pass(name: &str) -> NodeArg {
+    NodeArg::Pass(ident(name))
+}
+
+fn on_click(body: &str) -> NodeArg {
+    NodeArg::EventHandler(EventHandler { name: ident("on_click"), body: BlockOrExpr::Expr(expr(body)) })
+}
+
+fn file(fragments: Vec<FragmentDecl>) -> FragmentFile {
+    FragmentFile { styles: Vec::new(), fragments }
+}
+
+fn errors(file: &FragmentFile) -> Vec<String> {
+    match resolve_file(file) {
+        Ok(_) => panic!("resolution should fail"),
+        Err(error) => error.into_iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+fn warnings(file: &FragmentFile) -> Vec<String> {
+    match resolve_file(file) {
+        Ok(warnings) => warnings.into_iter().map(|w| w.message).collect(),
+        Err(error) => panic!("unexpected error: {error}"),
+    }
+}
+
+fn stepper() -> FragmentDecl {
+    fragment("Stepper", vec![param(StoreKind::Writable, "value")], vec![
+        node("button", vec![on_click("value += 1")], None),
+    ])
+}
+
+#[test]
+fn clean_file_has_no_diagnostics() {
+    let file = file(vec![
+        stepper(),
+        fragment("Counter", vec![param(StoreKind::Readable, "label")], vec![
+            store(StoreKind::Writable, "count", "0"),
+            node("Stepper", vec![pass("count")], None),
+            node("text", vec![NodeArg::Const(expr("\"${label}\""))], None),
+        ]),
+    ]);
+
+    assert!(warnings(&file).is_empty());
+    assert_eq!(lower_file(&file).unwrap().0.len(), 2);
+}
+
+#[test]
+fn all_errors_are_reported_at_once() {
+    let file = file(vec![
+        stepper(),
+        fragment("App", vec![param(StoreKind::Readable, "count")], vec![
+            store(StoreKind::Writable, "count", "0"),
+            store(StoreKind::Derived, "total", "0"),
+            node("text", vec![pass("missing")], None),
+            node("text", vec![NodeArg::Const(expr("\"${nowhere}\""))], None),
+            node("Stepper", vec![pass("total")], None),
+            node("Stepper", vec![NodeArg::Const(expr("1"))], None),
+            node("Stepper", vec![], None),
+        ]),
+        fragment("App", vec![], vec![]),
+    ]);
+
+    assert_eq!(errors(&file), vec![
+        "`count` is declared twice in this block",
+        "cannot find store `missing` in this fragment",
+        "cannot find store `nowhere` in this fragment",
+        "`total` is a derived store, but `Stepper` expects a writable store for `value`",
+        "the argument is a const store, but `Stepper` expects a writable store for `value`",
+        "`Stepper` takes 1 argument, 0 given",
+        "fragment `App` is declared twice",
+    ]);
+    assert!(lower_file(&file).is_err());
+}
+
+#[test]
+fn names_are_block_scoped() {
+    let file = file(vec![fragment("App", vec![], vec![
+        node("column", vec![], Some(vec![store(StoreKind::Writable, "inner", "0"), node("text", vec![pass("inner")], None)])),
+        node("text", vec![pass("inner")], None),
+    ])]);
+
+    assert_eq!(errors(&file), vec!["cannot find store `inner` in this fragment"]);
+}
+
+#[test]
+fn shadowing_and_unused_stores_are_warnings() {
+    let file = file(vec![fragment("App", vec![], vec![
+        store(StoreKind::Writable, "count", "0"),
+        store(StoreKind::Const, "unused", "1"),
+        node("column", vec![], Some(vec![
+            store(StoreKind::Writable, "count", "1"),
+            node("button", vec![on_click("count = count + 1")], None),
+        ])),
+        node("text", vec![pass("count")], None),
+    ])]);
+
+    assert_eq!(warnings(&file), vec![
+        "`count` shadows a writable store of an enclosing block",
+        "store `unused` is never used",
+    ]);
+}
+
+fn slot_param(name: &str) -> SlotParam {
+    SlotParam { name: ident(name), span: Span::call_site() }
+}
+
+fn place(name: &str) -> BuildStmt {
+    BuildStmt::Slot(SlotStmt { name: ident(name), span: Span::call_site() })
+}
+
+fn card() -> FragmentDecl {
+    let mut card = fragment("Card", vec![param(StoreKind::Readable, "title")], vec![
+        node("column", vec![], Some(vec![
+            place("header"),
+            node("text", vec![pass("title")], None),
+            place("children"),
+        ])),
+    ]);
+    card.slots = vec![slot_param("header"), slot_param("children")];
+    card
+}
+
+fn card_call(args: Vec<NodeArg>, children: Option<Vec<BuildStmt>>, fills: Vec<(&str, Vec<BuildStmt>)>) -> BuildStmt {
+    BuildStmt::Node(NodeDecl {
+        name: ident("Card"),
+        args,
+        children: children.map(block),
+        slots: fills.into_iter().map(|(name, items)| SlotFill { name: ident(name), body: block(items), span: Span::call_site() }).collect(),
+        chain: Vec::new(),
+        span: Span::call_site(),
+    })
+}
+
+#[test]
+fn slot_fills_lower_to_fragments_bound_by_the_caller() {
+    use fluxum::fir::*;
+
+    let file = file(vec![
+        card(),
+        fragment("App", vec![], vec![
+            store(StoreKind::Writable, "count", "0"),
+            card_call(
+                vec![pass("count")],
+                Some(vec![node("button", vec![on_click("count += 1")], None)]),
+                vec![("header", vec![node("text", vec![pass("count")], None)])],
+            ),
+        ]),
+    ]);
+
+    let (fragments, _) = lower_file(&file).unwrap();
+    let (card, app) = (&fragments[0], &fragments[1]);
+
+    let mut ops = Vec::new();
+    for (op, arg) in [(OP_VERSION, FIR_VERSION), (OP_WRITABLE, store_arg(0, 1)), (OP_BEGIN, 0), (OP_ARG_PASS, 0), (OP_ARG_SLOT, 0), (OP_ARG_SLOT, 1), (OP_END, 0)] {
+        encode_op(&mut ops, op, arg);
+    }
+    assert_eq!(app.ops, ops);
+    assert_eq!(app.dependencies, vec![ident("Card"), ident("App_slot_0"), ident("App_slot_1")]);
+    assert_eq!(app.slots.iter().map(|slot| (slot.fragment, slot.uses.clone())).collect::<Vec<_>>(), vec![(1, vec![0]), (2, vec![0])]);
+    assert_eq!(app.nested[1].events_handlers.len(), 1);
+
+    // the slots are external stores of the callee, after the parameters
+    let mut ops = Vec::new();
+    for (op, arg) in [(OP_VERSION, FIR_VERSION), (OP_BEGIN, 0), (OP_SLOT, 1), (OP_BEGIN, 1), (OP_ARG_PASS, 0), (OP_END, 0), (OP_SLOT, 2), (OP_END, 0)] {
+        encode_op(&mut ops, op, arg);
+    }
+    assert_eq!(card.ops, ops);
+    assert_eq!(card.ext_store_count, 3);
+}
+
+#[test]
+fn slot_misuse_is_reported() {
+    let file = file(vec![
+        card(),
+        fragment("App", vec![], vec![
+            card_call(vec![NodeArg::Const(expr("1"))], None, vec![("footer", vec![])]),
+            node("column", vec![], None),
+            card_call(vec![NodeArg::Const(expr("2"))], Some(vec![place("header")]), vec![("header", vec![])]),
+        ]),
+    ]);
+
+    assert_eq!(errors(&file), vec![
+        "`Card` has no slot `footer`",
+        "`Card` expects a block for slot `header`",
+        "`Card` expects a block for slot `children`",
+        "cannot find slot `header` in this fragment",
+    ]);
+}
@@ -0,0 +1,171 @@
+use proc_macro2::Span;
+use syn::Ident;
+
+use fluxum::compiler::ast::*;
+use fluxum::compiler::instruction::*;
+use fluxum::compiler::lower::{ChainPart, LoweredResource, lower_file, lower_styles};
+use fluxum::compiler::resolve::resolve_file;
+use fluxum::fir::*;
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+fn block(items: Vec<BuildStmt>) -> Block {
+    Block { items, span: Span::call_site() }
+}
+
+// `padding { 8 top: 2 } .. Card`, values are single tokens
+fn chain(src: &str) -> Vec<Modifier> {
+    src.split(" .. ").map(|item| {
+        let (name, args) = match item.split_once(" { ") {
+            Some((name, args)) => (name, args.trim_end_matches(" }")),
+            None => (item, ""),
+        };
+        let mut parsed = Vec::new();
+        let mut arg_name = None;
+        for token in args.split_whitespace() {
+            // positional values may be separated by commas, `border { Gray, 1 }`
+            let token = token.trim_end_matches(',');
+            match token.strip_suffix(':') {
+                Some(n) => arg_name = Some(ident(n)),
+                None => parsed.push(ModifierArg { name: arg_name.take(), value: syn::parse_str(token).unwrap() }),
+            }
+        }
+        Modifier { name: ident(name), args: parsed }
+    }).collect()
+}
+
+fn style(name: &str, src: &str) -> StyleDecl {
+    StyleDecl { name: ident(name), chain: chain(src), span: Span::call_site() }
+}
+
+fn node(name: &str, src: &str) -> BuildStmt {
+    BuildStmt::Node(NodeDecl {
+        name: ident(name),
+        args: Vec::new(),
+        children: None,
+        slots: Vec::new(),
+        chain: chain(src),
+        span: Span::call_site(),
+    })
+}
+
+fn file(styles: Vec<StyleDecl>, items: Vec<BuildStmt>) -> FragmentFile {
+    FragmentFile {
+        styles,
+        fragments: vec![FragmentDecl { name: ident("App"), params: Vec::new(), slots: Vec::new(), body: block(items), span: Span::call_site() }],
+    }
+}
+
+fn errors(file: &FragmentFile) -> Vec<String> {
+    match resolve_file(file) {
+        Ok(_) => panic!("resolution should fail"),
+        Err(error) => error.into_iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+fn ops(list: &[(u8, u32)]) -> Vec<u8> {
+    let mut ops = Vec::new();
+    for &(opcode, arg) in list {
+        encode_op(&mut ops, opcode, arg);
+    }
+    ops
+}
+
+fn card() -> StyleDecl {
+    style("Card", "padding { 16 } .. border { Gray, 1 } .. corner_radius { 8 }")
+}
+
+#[test]
+fn a_style_is_one_resource_and_one_store_for_all_its_uses() {
+    let file = file(vec![card()], vec![
+        node("column", "Card"),
+        node("row", "Card .. padding { top: 4 }"),
+        node("box", "Card"),
+        node("text", "underline"),
+    ]);
+
+    let (fragments, _) = lower_file(&file).unwrap();
+    let app = &fragments[0];
+
+    assert_eq!(app.ops, ops(&[
+        (OP_VERSION, FIR_VERSION),
+        (OP_CONST, 0),
+        (OP_BEGIN, 0), (OP_ARG_PASS, 0), (OP_END, 0),
+        (OP_BEGIN, 1), (OP_ARG_CONST, 1), (OP_END, 0),
+        (OP_BEGIN, 2), (OP_ARG_PASS, 0), (OP_END, 0),
+        (OP_BEGIN, 3), (OP_ARG_CONST, 2), (OP_END, 0),
+    ]));
+    assert_eq!(app.own_store_count, 1);
+
+    assert!(matches!(&app.resources[0], LoweredResource::Style(name) if name == "Card"));
+    let LoweredResource::Instructions(parts) = &app.resources[1] else { panic!("expected a chain") };
+    assert!(matches!(parts.as_slice(), [ChainPart::Style(0), ChainPart::Inline(inline)] if inline.len() == 1));
+    assert!(matches!(&app.resources[2], LoweredResource::Instructions(parts) if parts.len() == 1));
+}
+
+#[test]
+fn styles_compose_and_later_instructions_override() {
+    let file = file(vec![
+        style("Flat", "Primary .. border { 0 }"),
+        card(),
+        style("Primary", "Card .. background { color: Blue }"),
+    ], vec![node("box", "Flat")]);
+
+    let styles = lower_styles(&file).unwrap();
+
+    // the styles are listed after the ones they refer to
+    let names: Vec<String> = styles.iter().map(|style| style.name.to_string()).collect();
+    assert_eq!(names, ["Card", "Primary", "Flat"]);
+
+    let kinds: Vec<&str> = styles[2].instructions.iter().map(|instruction| match instruction {
+        Instruction::Surrounding { kind: SurroundingKind::Padding, .. } => "padding",
+        Instruction::Surrounding { kind: SurroundingKind::Border, .. } => "border",
+        Instruction::CornerRadius(_) => "corner_radius",
+        Instruction::Background(_) => "background",
+        _ => "other",
+    }).collect();
+    assert_eq!(kinds, ["padding", "border", "corner_radius", "background", "border"]);
+
+    // `border { Gray, 1 }` of the card, as written
+    let Instruction::Surrounding { sides, color: Some(color), .. } = &styles[0].instructions[1] else { panic!("expected the border") };
+    assert_eq!(quote::ToTokens::to_token_stream(color).to_string(), "Gray");
+    assert_eq!(sides.left.as_ref().map(|width| quote::ToTokens::to_token_stream(width).to_string()).as_deref(), Some("1"));
+}
+
+#[test]
+fn style_errors_are_reported_with_the_others() {
+    let file = file(vec![
+        card(),
+        style("Loop", "Other .. padding { 1 }"),
+        style("Other", "Loop"),
+        style("Card", "padding { 2 }"),
+        style("bad", "Missing"),
+        style("Broken", "padding { side: 2 }"),
+    ], vec![
+        node("box", "Card { 2 }"),
+        node("box", "Broken"),
+        node("row", "Broken .. margin { 4 }"),
+        node("column", "Cards"),
+    ]);
+
+    assert_eq!(errors(&file), vec![
+        "style `Loop` refers to itself",
+        "style `Card` is declared twice",
+        "style `bad` must start with an uppercase letter",
+        "cannot find style `Missing`",
+        "`padding` has no argument `side`, expected `top`, `right`, `bottom`, `left`, `horizontal` or `vertical`",
+        "`padding` needs at least one side",
+        "style `Card` takes no arguments",
+        "cannot find style `Cards`",
+    ]);
+}
+
+#[test]
+fn styles_apply_to_standard_fragments_only() {
+    let mut file = file(vec![card()], vec![node("Panel", "Card")]);
+    file.fragments.push(FragmentDecl { name: ident("Panel"), params: Vec::new(), slots: Vec::new(), body: block(Vec::new()), span: Span::call_site() });
+
+    assert_eq!(errors(&file), vec!["`Panel` is a fragment of this file, instructions apply to standard fragments"]);
+}
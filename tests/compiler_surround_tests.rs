@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use proc_macro2::Span;
+use syn::Ident;
+
+use fluxum::compiler::ast::*;
+use fluxum::compiler::dump::dump_file;
+use fluxum::compiler::surround::{CapabilityTable, surround_file};
+
+fn ident(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+fn expr(src: &str) -> syn::Expr {
+    syn::parse_str(src).unwrap()
+}
+
+fn block(items: Vec<BuildStmt>) -> Block {
+    Block { items, span: Span::call_site() }
+}
+
+fn fragment(name: &str, items: Vec<BuildStmt>) -> FragmentDecl {
+    FragmentDecl { name: ident(name), params: Vec::new(), slots: Vec::new(), body: block(items), span: Span::call_site() }
+}
+
+// `chain` is `name` or `name { arg }` items
+fn node(name: &str, args: Vec<NodeArg>, children: Option<Vec<BuildStmt>>, chain: &[(&str, Option<&str>)]) -> BuildStmt {
+    BuildStmt::Node(NodeDecl {
+        name: ident(name),
+        args,
+        children: children.map(block),
+        slots: Vec::new(),
+        chain: chain.iter().map(|(name, arg)| modifier(name, *arg)).collect(),
+        span: Span::call_site(),
+    })
+}
+
+fn modifier(name: &str, arg: Option<&str>) -> Modifier {
+    Modifier { name: ident(name), args: arg.into_iter().map(|arg| ModifierArg { name: None, value: expr(arg) }).collect() }
+}
+
+fn text(value: &str, chain: &[(&str, Option<&str>)]) -> BuildStmt {
+    node("text", vec![NodeArg::Const(expr(value))], None, chain)
+}
+
+// Compare with `tests/snapshots/<name>.snap`, `UPDATE_SNAPSHOTS=1` rewrites the file.
+fn assert_snapshot(name: &str, actual: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots", &format!("{name}.snap")].iter().collect();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {}", path.display()));
+    assert_eq!(actual, expected, "snapshot {name} differs");
+}
+
+fn before_after(file: &mut FragmentFile, table: &CapabilityTable) -> String {
+    let before = dump_file(file);
+    surround_file(file, table);
+    format!("--- before\n{before}--- after\n{}", dump_file(file))
+}
+
+#[test]
+fn basic_fragments_are_wrapped_into_a_box() {
+    let mut file = FragmentFile {
+        styles: Vec::new(),
+        fragments: vec![fragment("Page", vec![
+            node("column", vec![], Some(vec![
+                text("\"Hello\"", &[("padding", Some("8")), ("text_small", None), ("border", Some("Red"))]),
+                text("\"World\"", &[("text_small", None)]),
+            ]), &[("padding", Some("16"))]),
+        ])],
+    };
+
+    assert_snapshot("surround_basic", &before_after(&mut file, &CapabilityTable::standard()));
+}
+
+#[test]
+fn nested_blocks_are_rewritten() {
+    let mut card = NodeDecl {
+        name: ident("Card"),
+        args: Vec::new(),
+        children: Some(block(vec![text("\"body\"", &[("margin", Some("4"))])])),
+        slots: vec![SlotFill {
+            name: ident("header"),
+            body: block(vec![text("\"title\"", &[("padding", Some("2"))])]),
+            span: Span::call_site(),
+        }],
+        chain: Vec::new(),
+        span: Span::call_site(),
+    };
+    card.chain.push(modifier("border", Some("1")));
+
+    let mut file = FragmentFile {
+        styles: Vec::new(),
+        fragments: vec![fragment("Page", vec![
+            BuildStmt::If(IfStmt {
+                keep: false,
+                cond: expr("open"),
+                then_block: block(vec![BuildStmt::Node(card)]),
+                else_arm: Some(ElseArm::Block(block(vec![text("\"closed\"", &[("padding", Some("8"))])]))),
+            }),
+        ])],
+    };
+
+    assert_snapshot("surround_nested", &before_after(&mut file, &CapabilityTable::standard()));
+}
+
+#[test]
+fn capability_table_decides_what_is_wrapped() {
+    let mut file = FragmentFile {
+        styles: Vec::new(),
+        fragments: vec![fragment("Page", vec![
+            node("Card", vec![], None, &[("padding", Some("8"))]),
+            node("column", vec![], None, &[("padding", Some("8"))]),
+        ])],
+    };
+
+    let mut table = CapabilityTable::new();
+    table.accept_surrounding("Card");
+
+    assert_snapshot("surround_capabilities", &before_after(&mut file, &table));
+}
+
+#[test]
+fn styles_with_surrounding_are_split_on_wrapped_nodes() {
+    let mut file = FragmentFile {
+        styles: vec![
+            StyleDecl {
+                name: ident("Card"),
+                chain: vec![modifier("padding", Some("16")), modifier("corner_radius", Some("8"))],
+                span: Span::call_site(),
+            },
+            StyleDecl { name: ident("Small"), chain: vec![modifier("text_small", None)], span: Span::call_site() },
+        ],
+        fragments: vec![fragment("Page", vec![
+            node("column", vec![], Some(vec![
+                text("\"title\"", &[("Small", None), ("Card", None), ("border", Some("1"))]),
+            ]), &[("Card", None)]),
+        ])],
+    };
+
+    assert_snapshot("surround_styles", &before_after(&mut file, &CapabilityTable::standard()));
+}
@@ -0,0 +1,165 @@
+use std::any::Any;
+
+use fluxum::fir::*;
+use fluxum::instance::{InstanceKey, InstanceRuntime, InstanceRuntimeImpl};
+use fluxum::linker::{LinkError, link};
+use fluxum::store::{StoreRuntime, StoreRuntimeImpl};
+
+struct Theme;
+
+impl ContextKey for Theme {
+    type Value = &'static str;
+}
+
+fn dark() -> Box<dyn Any> { Box::new("dark") }
+fn light() -> Box<dyn Any> { Box::new("light") }
+
+static CONTEXTS: [ContextDesc; 1] = [ContextDesc::of::<Theme>()];
+
+const fn fragment(resources: &'static [ResourceFn], dependencies: &'static [&'static FragmentIR], ops: &'static [u8]) -> FragmentIR {
+    FragmentIR {
+        node_count: dependencies.len() as u16,
+        ext_store_count: 0,
+        own_store_count: 0,
+        resources,
+        dependencies,
+        events_handlers: &[],
+        derived_handlers: &[],
+        eq_strategies: &[],
+        contexts: &CONTEXTS,
+        slots: &[],
+        ops,
+    }
+}
+
+// uses the theme
+static LEAF: FragmentIR = fragment(&[], &[], &[OP_VERSION, 1, OP_USE, 0]);
+
+// provides nothing, just passes through
+static MIDDLE: FragmentIR = fragment(&[], &[&LEAF], &[OP_VERSION, 1, OP_BEGIN, 0, OP_END, 0]);
+
+// uses the outer theme, then provides its own to the descendants
+static OVERRIDE: FragmentIR = fragment(&[light], &[&LEAF], &[
+    OP_VERSION, 1,
+    OP_USE, 0,
+    OP_CONST, 0,
+    OP_PROVIDE, 1,
+    OP_BEGIN, 0, OP_END, 0,
+]);
+
+static APP: FragmentIR = fragment(&[dark], &[&MIDDLE, &OVERRIDE], &[
+    OP_VERSION, 1,
+    OP_CONST, 0,
+    OP_PROVIDE, 0,
+    OP_BEGIN, 0, OP_END, 0,
+    OP_BEGIN, 1, OP_END, 0,
+]);
+
+fn child(instances: &InstanceRuntimeImpl, key: InstanceKey, index: usize) -> InstanceKey {
+    instances.instance(key).children[index]
+}
+
+fn theme(instances: &InstanceRuntimeImpl, stores: &StoreRuntimeImpl, key: InstanceKey) -> &'static str {
+    let store = instances.instance(key).stores[0];
+    stores.get_value(store).downcast_ref::<&'static str>().unwrap()
+}
+
+#[test]
+fn descendants_use_the_nearest_provider() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let app = link(&APP, &[], None, &mut instances, &mut stores).unwrap();
+
+    let leaf = child(&instances, child(&instances, app, 0), 0);
+    assert_eq!(instances.instance(leaf).stores[0], instances.instance(app).stores[0]);
+    assert_eq!(theme(&instances, &stores, leaf), "dark");
+
+    // the override sees the outer theme, its descendants its own
+    let over = child(&instances, app, 1);
+    assert_eq!(theme(&instances, &stores, over), "dark");
+    assert_eq!(theme(&instances, &stores, child(&instances, over, 0)), "light");
+
+    assert_eq!(instances.context::<Theme>(app), Some(instances.instance(app).stores[0]));
+}
+
+#[test]
+fn used_context_stores_are_not_owned() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let app = link(&APP, &[], None, &mut instances, &mut stores).unwrap();
+    assert_eq!(stores.store_count(), 2);
+    assert_eq!(instances.instance_count(), 5);
+
+    let middle = child(&instances, app, 0);
+    instances.free_instance(middle, &mut stores);
+    assert_eq!(stores.store_count(), 2);
+    assert_eq!(instances.instance_count(), 3);
+
+    instances.free_instance(app, &mut stores);
+    assert_eq!(stores.store_count(), 0);
+    assert_eq!(instances.instance_count(), 0);
+}
+
+fn closed() -> Box<dyn Any> { Box::new(false) }
+
+// the arm using the theme is not mounted, the condition is false
+static HIDDEN: FragmentIR = fragment(&[closed], &[&LEAF], &[
+    OP_VERSION, 1,
+    OP_CONST, 0,
+    OP_BRANCH, 0, OP_ARM, 0, OP_BRANCH_END, 0,
+]);
+
+// provides the theme on its own instance, to the nodes the caller nests in it
+static PROVIDER: FragmentIR = fragment(&[dark], &[], &[OP_VERSION, 1, OP_CONST, 0, OP_PROVIDE, 0]);
+
+// `Provider { Leaf }`
+static NESTED: FragmentIR = fragment(&[], &[&PROVIDER, &LEAF], &[
+    OP_VERSION, 1,
+    OP_BEGIN, 0,
+    OP_BEGIN, 1, OP_END, 0,
+    OP_END, 0,
+]);
+
+// `Provider {} Leaf`, the leaf is not under the provider
+static SIBLINGS: FragmentIR = fragment(&[], &[&PROVIDER, &LEAF], &[
+    OP_VERSION, 1,
+    OP_BEGIN, 0, OP_END, 0,
+    OP_BEGIN, 1, OP_END, 0,
+]);
+
+#[test]
+fn children_nested_in_a_provider_component_use_its_context() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let app = link(&NESTED, &[], None, &mut instances, &mut stores).unwrap();
+    let leaf = child(&instances, child(&instances, app, 0), 0);
+    assert_eq!(theme(&instances, &stores, leaf), "dark");
+
+    let error = LinkError::ContextNotProvided(std::any::type_name::<Theme>());
+    assert_eq!(link(&SIBLINGS, &[], None, &mut instances, &mut stores), Err(error));
+}
+
+#[test]
+fn missing_context_is_a_link_error() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+    let error = LinkError::ContextNotProvided(std::any::type_name::<Theme>());
+    let missing = Err(error.clone());
+
+    // used directly, by a child, before the fragment provides its own, by an arm not mounted
+    for ir in [&LEAF, &MIDDLE, &OVERRIDE, &HIDDEN] {
+        assert_eq!(link(ir, &[], None, &mut instances, &mut stores), missing);
+    }
+    assert_eq!(error.to_string(), format!("context not provided: {}", std::any::type_name::<Theme>()));
+
+    // nothing has been created
+    assert_eq!(instances.instance_count(), 0);
+    assert_eq!(stores.store_count(), 0);
+
+    // under a provider the same fragments link
+    let app = link(&APP, &[], None, &mut instances, &mut stores).unwrap();
+    assert!(link(&HIDDEN, &[], Some(app), &mut instances, &mut stores).is_ok());
+}
@@ -0,0 +1,329 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use fluxum::fir::*;
+use fluxum::instance::{InstanceKey, InstanceRuntime, InstanceRuntimeImpl};
+use fluxum::linker::{call_handler, link, update_branches};
+use fluxum::store::{EqStrategy, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl, mk_eq_fn};
+
+fn zero() -> Box<dyn Any> { Box::new(0i32) }
+
+// what the compiler generates for `on_click { count = count + 1 }`
+fn eh_0(__stores: &[StoreKey], __rt: &mut StoreEffects) {
+    {
+        let __value: Box<i32> = Box::new(Clone::clone(__rt.get_value(__stores[0usize]).downcast_ref::<i32>().unwrap()) + 1);
+        __rt.set_value(__stores[0usize], __value);
+    };
+}
+
+static BUTTON: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 1,
+    own_store_count: 0,
+    resources: &[],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[OP_VERSION, 1],
+};
+
+static COUNTER: FragmentIR = FragmentIR {
+    node_count: 1,
+    ext_store_count: 0,
+    own_store_count: 1,
+    resources: &[zero],
+    dependencies: &[&BUTTON],
+    events_handlers: &[EventHandlerDesc { uses: &[0], handler: eh_0 }],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[
+        OP_VERSION, 1,
+        OP_WRITABLE, 0,
+        OP_BEGIN, 0, OP_ARG_EH, 0, OP_END, 0,
+    ],
+};
+
+#[test]
+fn event_handler_is_bound_to_the_stores_of_the_instance() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let counter = link(&COUNTER, &[], None, &mut instances, &mut stores).unwrap();
+    let count = instances.instance(counter).stores[0];
+    let button = instances.instance(counter).children[0];
+    let on_click = instances.instance(button).stores[0];
+
+    call_handler(on_click, &mut stores);
+    call_handler(on_click, &mut stores);
+    stores.drain_notifications();
+
+    assert_eq!(*stores.get_value(count).downcast_ref::<i32>().unwrap(), 2);
+
+    // the handler store is owned by the counter instance
+    instances.free_instance(counter, &mut stores);
+    assert_eq!(stores.store_count(), 0);
+}
+
+// `store count = 0` and `store total: i32 = 0 .. eq { always }`
+static COMPARED: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 0,
+    own_store_count: 2,
+    resources: &[zero],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[EqStrategy::Compare(mk_eq_fn::<i32>())],
+    contexts: &[],
+    slots: &[],
+    ops: &[
+        OP_VERSION, 1,
+        OP_WRITABLE | ARG_LEN_3, 0, 0, 1,
+        OP_WRITABLE, 0,
+    ],
+};
+
+#[test]
+fn stores_are_created_with_their_eq_strategy() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let key = link(&COMPARED, &[], None, &mut instances, &mut stores).unwrap();
+    let notified: Vec<Rc<Cell<u32>>> = instances.instance(key).stores.iter().map(|&store| {
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        let cb: StoreCallback = Rc::new(move |_, _, _: &mut StoreEffects| counter.set(counter.get() + 1));
+        stores.subscribe(store, cb);
+        count
+    }).collect();
+
+    // the same value: only the store without a comparison notifies
+    for &store in &instances.instance(key).stores {
+        stores.set_value(store, Box::new(0i32));
+    }
+    stores.drain_notifications();
+    assert_eq!((notified[0].get(), notified[1].get()), (0, 1));
+
+    stores.set_value(instances.instance(key).stores[0], Box::new(1i32));
+    stores.drain_notifications();
+    assert_eq!(notified[0].get(), 1);
+}
+
+// ---------------------------------------------------------------------------
+// Branches
+// ---------------------------------------------------------------------------
+
+fn first_arm() -> Box<dyn Any> { Box::new(0u32) }
+
+// an arm with a writable store of its own and the store of the page as parameter
+static ARM_A: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 1,
+    own_store_count: 1,
+    resources: &[zero],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[OP_VERSION, 1, OP_WRITABLE, 0],
+};
+
+static ARM_B: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 0,
+    own_store_count: 0,
+    resources: &[],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[OP_VERSION, 1],
+};
+
+// `if mode == 0 { A(mode) } else { B() }`, with the arm index as condition store
+static PAGE: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 0,
+    own_store_count: 1,
+    resources: &[first_arm],
+    dependencies: &[&ARM_A, &ARM_B],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[
+        OP_VERSION, 1,
+        OP_WRITABLE, 0,
+        OP_BRANCH, 0,
+        OP_ARM, 0, OP_ARG_PASS, 0,
+        OP_ARM, 1,
+        OP_BRANCH_END, 0,
+    ],
+};
+
+static KEEP_PAGE: FragmentIR = FragmentIR {
+    ops: &[
+        OP_VERSION, 1,
+        OP_WRITABLE, 0,
+        OP_BRANCH, 0,
+        OP_ARM, 0, OP_ARG_PASS, 0,
+        OP_ARM, 1,
+        OP_BRANCH_END, 1,
+    ],
+    ..PAGE
+};
+
+fn select(arm: u32, mode: StoreKey, instances: &mut InstanceRuntimeImpl, stores: &mut StoreRuntimeImpl) {
+    stores.set_value(mode, Box::new(arm));
+    stores.drain_notifications();
+    update_branches(instances, stores);
+}
+
+// (ir, hidden) of the children of the branch holder
+fn arms(instances: &InstanceRuntimeImpl, holder: InstanceKey) -> Vec<(*const FragmentIR, bool)> {
+    instances.instance(holder).children.iter()
+        .map(|child| (instances.instance(*child).ir as *const FragmentIR, instances.instance(*child).hidden))
+        .collect()
+}
+
+#[test]
+fn branch_mounts_the_selected_arm() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let page = link(&PAGE, &[], None, &mut instances, &mut stores).unwrap();
+    let mode = instances.instance(page).stores[0];
+    let holder = instances.instance(page).children[0];
+
+    let a: *const FragmentIR = &ARM_A;
+    let b: *const FragmentIR = &ARM_B;
+    assert_eq!(arms(&instances, holder), vec![(a, false)]);
+    let arm = instances.instance(holder).children[0];
+    assert_eq!(instances.instance(arm).stores[0], mode);
+    stores.set_value(instances.instance(arm).stores[1], Box::new(7i32));
+
+    select(1, mode, &mut instances, &mut stores);
+    assert_eq!(arms(&instances, holder), vec![(b, false)]);
+
+    // past the last arm nothing is mounted
+    select(2, mode, &mut instances, &mut stores);
+    assert!(arms(&instances, holder).is_empty());
+
+    // a remounted arm starts with fresh state
+    select(0, mode, &mut instances, &mut stores);
+    let arm = instances.instance(holder).children[0];
+    assert_eq!(*stores.get_value(instances.instance(arm).stores[1]).downcast_ref::<i32>().unwrap(), 0);
+    assert_eq!(stores.store_count(), 2);
+
+    instances.free_instance(page, &mut stores);
+    assert_eq!((stores.store_count(), stores.subscription_count(), instances.instance_count()), (0, 0, 0));
+}
+
+#[test]
+fn kept_branch_hides_the_arms_not_selected() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let page = link(&KEEP_PAGE, &[], None, &mut instances, &mut stores).unwrap();
+    let mode = instances.instance(page).stores[0];
+    let holder = instances.instance(page).children[0];
+    let arm = instances.instance(holder).children[0];
+    let own = instances.instance(arm).stores[1];
+    stores.set_value(own, Box::new(7i32));
+
+    let a: *const FragmentIR = &ARM_A;
+    let b: *const FragmentIR = &ARM_B;
+
+    select(1, mode, &mut instances, &mut stores);
+    assert_eq!(arms(&instances, holder), vec![(a, true), (b, false)]);
+
+    // the hidden instance comes back with its state
+    select(0, mode, &mut instances, &mut stores);
+    assert_eq!(arms(&instances, holder), vec![(a, false), (b, true)]);
+    assert_eq!(instances.instance(holder).children[0], arm);
+    assert_eq!(*stores.get_value(own).downcast_ref::<i32>().unwrap(), 7);
+
+    instances.free_instance(page, &mut stores);
+    assert_eq!((stores.store_count(), stores.subscription_count(), instances.instance_count()), (0, 0, 0));
+}
+
+// ---------------------------------------------------------------------------
+// Slots
+// ---------------------------------------------------------------------------
+
+static HEADER: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 1,
+    own_store_count: 0,
+    resources: &[],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[OP_VERSION, 1],
+};
+
+// `Card(slot header) { slot header }`
+static CARD: FragmentIR = FragmentIR {
+    node_count: 0,
+    ext_store_count: 1,
+    own_store_count: 0,
+    resources: &[],
+    dependencies: &[],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[],
+    ops: &[OP_VERSION, 1, OP_SLOT, 0],
+};
+
+// `store count = 0; Card() { slot header { HEADER(count) } }`
+static CARD_PAGE: FragmentIR = FragmentIR {
+    node_count: 1,
+    ext_store_count: 0,
+    own_store_count: 1,
+    resources: &[zero],
+    dependencies: &[&CARD, &HEADER],
+    events_handlers: &[],
+    derived_handlers: &[],
+    eq_strategies: &[],
+    contexts: &[],
+    slots: &[SlotDesc { fragment: 1, uses: &[0] }],
+    ops: &[
+        OP_VERSION, 1,
+        OP_WRITABLE, 0,
+        OP_BEGIN, 0, OP_ARG_SLOT, 0, OP_END, 0,
+    ],
+};
+
+#[test]
+fn slot_fill_is_instantiated_by_the_callee_with_the_stores_of_the_caller() {
+    let mut stores = StoreRuntimeImpl::new();
+    let mut instances = InstanceRuntimeImpl::new();
+
+    let page = link(&CARD_PAGE, &[], None, &mut instances, &mut stores).unwrap();
+    let count = instances.instance(page).stores[0];
+    let card = instances.instance(page).children[0];
+    let header = instances.instance(card).children[0];
+
+    let ir: *const FragmentIR = instances.instance(header).ir;
+    assert_eq!(ir, &HEADER as *const FragmentIR);
+    assert_eq!(instances.instance(header).stores[0], count);
+
+    instances.free_instance(page, &mut stores);
+    assert_eq!((stores.store_count(), instances.instance_count()), (0, 0));
+}
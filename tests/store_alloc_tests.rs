@@ -0,0 +1,126 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::rc::Rc;
+
+use fluxum::store::{
+    DerivedStore, EmittingStore, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl, mk_eq_fn,
+};
+
+// Counts allocations of the current thread, so parallel tests do not disturb each other.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations<R>(f: impl FnOnce() -> R) -> usize {
+    let before = ALLOCATIONS.with(|a| a.get());
+    f();
+    ALLOCATIONS.with(|a| a.get()) - before
+}
+
+fn emitting(rt: &mut StoreRuntimeImpl) -> StoreKey {
+    rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))))
+}
+
+fn derived(rt: &mut StoreRuntimeImpl, dep: StoreKey) -> StoreKey {
+    let key: Rc<Cell<Option<StoreKey>>> = Rc::new(Cell::new(None));
+    let key_for_cb = key.clone();
+
+    let cb: StoreCallback = Rc::new(move |store, _sub, rt: &mut StoreEffects| {
+        let value = *rt.get_value(store).downcast_ref::<i32>().unwrap();
+        if let Some(k) = key_for_cb.get() {
+            rt.set_value(k, Box::new(value));
+        }
+    });
+
+    let store = DerivedStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()), cb, [dep], rt);
+    let k = rt.alloc_store(Box::new(store));
+    key.set(Some(k));
+    k
+}
+
+// Write and drain a few times, so the buffers of the runtime reach their steady-state capacity
+fn warm_up(rt: &mut StoreRuntimeImpl, key: StoreKey) {
+    for i in 1..4 {
+        rt.set_value(key, Box::new(-i));
+        rt.drain_notifications();
+    }
+}
+
+#[test]
+fn set_value_allocates_only_the_value_box() {
+    let mut rt = StoreRuntimeImpl::new();
+    let key = emitting(&mut rt);
+
+    // more subscribers than the inline capacity of the store
+    for _ in 0..32 {
+        let cb: StoreCallback = Rc::new(|_, _, _: &mut StoreEffects| {});
+        rt.subscribe(key, cb);
+    }
+
+    warm_up(&mut rt, key);
+
+    let value = Box::new(1i32);
+    assert_eq!(allocations(|| rt.set_value(key, value)), 0);
+    assert_eq!(allocations(|| rt.drain_notifications()), 0);
+}
+
+#[test]
+fn update_value_does_not_allocate() {
+    let mut rt = StoreRuntimeImpl::new();
+    let key = emitting(&mut rt);
+    let cb: StoreCallback = Rc::new(|_, _, _: &mut StoreEffects| {});
+    rt.subscribe(key, cb);
+
+    warm_up(&mut rt, key);
+
+    let count = allocations(|| {
+        rt.update_value(key, &mut |v| {
+            *v.downcast_mut::<i32>().unwrap() += 1;
+            true
+        });
+        rt.drain_notifications();
+    });
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn derived_chain_allocates_one_box_per_store() {
+    let mut rt = StoreRuntimeImpl::new();
+    let source = emitting(&mut rt);
+    let mut last = source;
+    for _ in 0..16 {
+        last = derived(&mut rt, last);
+    }
+
+    warm_up(&mut rt, source);
+
+    let value = Box::new(1i32);
+    let count = allocations(|| {
+        rt.set_value(source, value);
+        rt.drain_notifications();
+    });
+
+    // the derive callbacks box the new value, nothing else allocates
+    assert_eq!(count, 16);
+}
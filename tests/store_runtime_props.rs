@@ -0,0 +1,228 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use fluxum::store::{
+    EmittingStore, StoreCallback, StoreEffects, StoreGeneration, StoreKey, StoreRuntime, StoreRuntimeImpl,
+    SubscriptionKey, mk_eq_fn,
+};
+use proptest::prelude::*;
+
+// ---------------------------------------------------------------------------
+// Operations
+// ---------------------------------------------------------------------------
+
+// Indices select from the live stores/subscriptions of the model (modulo their count),
+// operations without a target are skipped.
+
+#[derive(Clone, Debug)]
+enum Op {
+    Alloc,
+    Free(usize),
+    Subscribe(usize, Action),
+    Unsubscribe(usize),
+    Set(usize, i32),
+    Drain,
+}
+
+// What a callback does when it is called, executed from within the drain.
+// Writes go only to stores allocated after the store of the subscription, so the
+// generated store graphs are acyclic and the drain terminates.
+#[derive(Clone, Debug)]
+enum Action {
+    Nothing,
+    Set(usize, i32),
+    Subscribe(usize),
+    Unsubscribe(usize),
+    Free(usize),
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        3 => Just(Action::Nothing),
+        3 => (any::<usize>(), 0..3i32).prop_map(|(s, v)| Action::Set(s, v)),
+        1 => any::<usize>().prop_map(Action::Subscribe),
+        1 => any::<usize>().prop_map(Action::Unsubscribe),
+        1 => any::<usize>().prop_map(Action::Free),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        2 => Just(Op::Alloc),
+        1 => any::<usize>().prop_map(Op::Free),
+        3 => (any::<usize>(), action()).prop_map(|(s, a)| Op::Subscribe(s, a)),
+        1 => any::<usize>().prop_map(Op::Unsubscribe),
+        3 => (any::<usize>(), 0..3i32).prop_map(|(s, v)| Op::Set(s, v)),
+        2 => Just(Op::Drain),
+    ]
+}
+
+// ---------------------------------------------------------------------------
+// Reference model
+// ---------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Model {
+    stores: Vec<StoreKey>,
+    values: HashMap<StoreKey, i32>,
+    last_change: HashMap<StoreKey, StoreGeneration>,
+    subs: Vec<(SubscriptionKey, StoreKey)>,
+    // (generation of the batch, subscription) pairs the runtime has to call
+    expected: HashSet<(StoreGeneration, SubscriptionKey)>,
+    violations: Vec<String>,
+}
+
+type SharedModel = Rc<RefCell<Model>>;
+
+fn pick<T: Copy>(items: &[T], index: usize) -> Option<T> {
+    if items.is_empty() { None } else { Some(items[index % items.len()]) }
+}
+
+fn alloc(rt: &mut StoreEffects, model: &SharedModel) {
+    let key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let mut m = model.borrow_mut();
+    m.stores.push(key);
+    m.values.insert(key, 0);
+}
+
+fn free(rt: &mut StoreEffects, model: &SharedModel, index: usize) {
+    let Some(key) = pick(&model.borrow().stores, index) else { return };
+
+    rt.free_store(key);
+
+    let mut m = model.borrow_mut();
+    m.stores.retain(|s| *s != key);
+    m.values.remove(&key);
+    m.last_change.remove(&key);
+    let removed: Vec<SubscriptionKey> = m.subs.iter().filter(|(_, s)| *s == key).map(|(sub, _)| *sub).collect();
+    m.subs.retain(|(_, s)| *s != key);
+    m.expected.retain(|(_, sub)| !removed.contains(sub));
+}
+
+fn subscribe(rt: &mut StoreEffects, model: &SharedModel, index: usize, action: Action) {
+    let Some(store) = pick(&model.borrow().stores, index) else { return };
+
+    let model_for_cb = model.clone();
+    let cb: StoreCallback = Rc::new(move |store, sub, rt: &mut StoreEffects| {
+        callback(rt, &model_for_cb, store, sub, &action);
+    });
+
+    let sub = rt.subscribe(store, cb);
+
+    let mut m = model.borrow_mut();
+    m.subs.push((sub, store));
+    let generation = rt.generation();
+    if m.last_change.get(&store) == Some(&generation) {
+        m.expected.insert((generation + 1, sub));
+    }
+}
+
+fn unsubscribe(rt: &mut StoreEffects, model: &SharedModel, index: usize) {
+    let Some((sub, _)) = pick(&model.borrow().subs, index) else { return };
+
+    if !rt.unsubscribe(sub) {
+        model.borrow_mut().violations.push(format!("unsubscribe of live subscription {sub:?} returned false"));
+    }
+
+    let mut m = model.borrow_mut();
+    m.subs.retain(|(s, _)| *s != sub);
+    m.expected.retain(|(_, s)| *s != sub);
+}
+
+fn set(rt: &mut StoreEffects, model: &SharedModel, index: usize, value: i32) {
+    let Some(store) = pick(&model.borrow().stores, index) else { return };
+
+    rt.set_value(store, Box::new(value));
+
+    let mut m = model.borrow_mut();
+    if m.values.insert(store, value) == Some(value) { return; }
+
+    let generation = rt.generation();
+    m.last_change.insert(store, generation);
+    let subs: Vec<SubscriptionKey> = m.subs.iter().filter(|(_, s)| *s == store).map(|(sub, _)| *sub).collect();
+    for sub in subs {
+        m.expected.insert((generation + 1, sub));
+    }
+}
+
+fn callback(rt: &mut StoreEffects, model: &SharedModel, store: StoreKey, sub: SubscriptionKey, action: &Action) {
+    {
+        let mut m = model.borrow_mut();
+        let generation = rt.generation();
+
+        if !m.subs.contains(&(sub, store)) {
+            m.violations.push(format!("callback of dead subscription {sub:?}"));
+        } else if !m.expected.remove(&(generation, sub)) {
+            m.violations.push(format!("unexpected or repeated callback of {sub:?} in generation {generation}"));
+        }
+    }
+
+    match *action {
+        Action::Nothing => {}
+        Action::Set(index, value) => {
+            let later = {
+                let m = model.borrow();
+                let position = m.stores.iter().position(|s| *s == store).map_or(m.stores.len(), |p| p + 1);
+                m.stores[position..].to_vec()
+            };
+            if let Some(target) = pick(&later, index) {
+                let position = model.borrow().stores.iter().position(|s| *s == target).unwrap();
+                set(rt, model, position, value);
+            }
+        }
+        Action::Subscribe(index) => subscribe(rt, model, index, Action::Nothing),
+        Action::Unsubscribe(index) => unsubscribe(rt, model, index),
+        Action::Free(index) => free(rt, model, index),
+    }
+}
+
+fn drain(rt: &mut StoreRuntimeImpl, model: &SharedModel) {
+    rt.drain_notifications();
+
+    let mut m = model.borrow_mut();
+    if !m.expected.is_empty() {
+        let missed = format!("missed notifications after drain: {:?}", m.expected);
+        m.violations.push(missed);
+    }
+}
+
+fn run(ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut rt = StoreRuntimeImpl::new();
+    let model: SharedModel = Rc::new(RefCell::new(Model::default()));
+
+    for op in ops {
+        match op {
+            Op::Alloc => alloc(&mut rt, &model),
+            Op::Free(index) => free(&mut rt, &model, *index),
+            Op::Subscribe(index, action) => subscribe(&mut rt, &model, *index, action.clone()),
+            Op::Unsubscribe(index) => unsubscribe(&mut rt, &model, *index),
+            Op::Set(index, value) => set(&mut rt, &model, *index, *value),
+            Op::Drain => drain(&mut rt, &model),
+        }
+        prop_assert!(model.borrow().violations.is_empty(), "{:?}", model.borrow().violations);
+        prop_assert_eq!(rt.store_count(), model.borrow().stores.len());
+        prop_assert_eq!(rt.subscription_count(), model.borrow().subs.len());
+    }
+
+    drain(&mut rt, &model);
+    prop_assert!(model.borrow().violations.is_empty(), "{:?}", model.borrow().violations);
+
+    // freeing everything leaves the arenas empty
+    while !model.borrow().stores.is_empty() {
+        free(&mut rt, &model, 0);
+    }
+    prop_assert_eq!(rt.store_count(), 0);
+    prop_assert_eq!(rt.subscription_count(), 0);
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn runtime_matches_reference_model(ops in prop::collection::vec(op(), 1..80)) {
+        run(&ops)?;
+    }
+}
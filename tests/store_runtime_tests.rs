@@ -1,9 +1,11 @@
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::time::Duration;
 
+use fluxum::store::time::ManualClock;
 use fluxum::store::{
-    DerivedStore, EmittingStore, Store, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl,
-    SubscriptionKey, mk_eq_fn,
+    DerivedStore, EmittingStore, EqStrategy, Lane, LensStore, Store, StoreCallback, StoreEffects, StoreKey, StoreRuntime,
+    StoreRuntimeImpl, SubscriptionKey, mk_eq_fn, mk_fingerprint_fn, mk_ptr_eq_fn,
 };
 
 fn with_quiet_panic<F, R>(f: F) -> std::thread::Result<R>
@@ -75,6 +77,21 @@ fn subscribe_enqueues_if_changed_in_current_generation() {
     assert_eq!(*counter.borrow(), 1);
 }
 
+#[test]
+fn subscribing_twice_with_the_same_key_is_a_noop() {
+    let mut keys = thunderdome::Arena::new();
+    let (first, second): (SubscriptionKey, SubscriptionKey) = (keys.insert(()), keys.insert(()));
+
+    let mut store = EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()));
+    store.subscribe(first, 0);
+    store.subscribe(second, 0);
+    store.subscribe(first, 0);
+    assert_eq!(store.subscriptions().unwrap().len(), 2);
+
+    store.unsubscribe(first);
+    assert_eq!(store.subscriptions(), Some([second].as_slice()));
+}
+
 #[test]
 fn unsubscribe_prevents_further_notifications() {
     let mut rt = StoreRuntimeImpl::new();
@@ -365,3 +382,414 @@ fn multi_cycle_chain_a_to_b_to_c() {
     // Expect exactly one notification on C, processed after >1 cycles.
     assert_eq!(*c_counter.borrow(), 1);
 }
+
+#[test]
+fn ptr_eq_strategy_compares_identity_not_content() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let rows = Rc::new(vec![1, 2, 3]);
+    let store = EmittingStore::new(Box::new(rows.clone()), Some(mk_ptr_eq_fn::<Rc<Vec<i32>>>()));
+    let key = rt.alloc_store(Box::new(store));
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(key, cb);
+
+    // Same Rc → no notification
+    rt.set_value(key, Box::new(rows.clone()));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 0);
+
+    // Equal content in a new Rc → notification
+    rt.set_value(key, Box::new(Rc::new(vec![1, 2, 3])));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn deep_eq_strategy_compares_rc_content() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let store = EmittingStore::new(Box::new(Rc::new(vec![1, 2, 3])), Some(mk_eq_fn::<Rc<Vec<i32>>>()));
+    let key = rt.alloc_store(Box::new(store));
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(key, cb);
+
+    // Equal content in a new Rc → no notification
+    rt.set_value(key, Box::new(Rc::new(vec![1, 2, 3])));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 0);
+
+    rt.set_value(key, Box::new(Rc::new(vec![1, 2, 4])));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn fingerprint_strategy_notifies_only_on_hash_change() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let store = EmittingStore::new(
+        Box::new(vec![String::from("a")]),
+        EqStrategy::Fingerprint(mk_fingerprint_fn::<Vec<String>>()),
+    );
+    let key = rt.alloc_store(Box::new(store));
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(key, cb);
+
+    // Same content as the initial value → no notification
+    rt.set_value(key, Box::new(vec![String::from("a")]));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 0);
+
+    rt.set_value(key, Box::new(vec![String::from("b")]));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+
+    // The fingerprint follows the current value, so "b" again is not a change
+    rt.set_value(key, Box::new(vec![String::from("b")]));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn user_supplied_and_always_strategies() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    // Compare only the parity of the values
+    fn same_parity(old: &dyn std::any::Any, new: &dyn std::any::Any) -> bool {
+        let (Some(o), Some(n)) = (old.downcast_ref::<i32>(), new.downcast_ref::<i32>()) else { return false; };
+        o % 2 == n % 2
+    }
+
+    let parity_key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), EqStrategy::Compare(same_parity))));
+    let always_key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), None)));
+
+    let (parity_counter, parity_cb) = rc_counter();
+    let (always_counter, always_cb) = rc_counter();
+    let _ = rt.subscribe(parity_key, parity_cb);
+    let _ = rt.subscribe(always_key, always_cb);
+
+    rt.set_value(parity_key, Box::new(2i32));
+    rt.set_value(always_key, Box::new(0i32));
+    rt.drain_notifications();
+    assert_eq!(*parity_counter.borrow(), 0);
+    assert_eq!(*always_counter.borrow(), 1);
+
+    rt.set_value(parity_key, Box::new(3i32));
+    rt.drain_notifications();
+    assert_eq!(*parity_counter.borrow(), 1);
+}
+
+#[test]
+fn update_value_notifies_only_when_changed() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(Vec::<i32>::new()), None)));
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(key, cb);
+
+    // Push in place → one notification
+    rt.update_value(key, &mut |v| {
+        v.downcast_mut::<Vec<i32>>().unwrap().push(1);
+        true
+    });
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+
+    // Closure reports no change → no notification
+    rt.update_value(key, &mut |_| false);
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+
+    // Two updates in the same generation → one notification, both applied
+    rt.update_value(key, &mut |v| {
+        v.downcast_mut::<Vec<i32>>().unwrap().push(2);
+        true
+    });
+    rt.set_value(key, Box::new(vec![1, 2, 3]));
+    rt.update_value(key, &mut |v| {
+        v.downcast_mut::<Vec<i32>>().unwrap().push(4);
+        true
+    });
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 2);
+
+    // The store holds the updated value
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let seen_in_update = seen.clone();
+    rt.update_value(key, &mut |v| {
+        seen_in_update.borrow_mut().extend_from_slice(v.downcast_ref::<Vec<i32>>().unwrap());
+        false
+    });
+    assert_eq!(*seen.borrow(), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn update_value_on_derived_store_and_subscribe_in_same_generation() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let base_key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let (_dep_counter, dep_cb) = rc_counter();
+    let derived = DerivedStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()), dep_cb, [base_key], &mut rt);
+    let derived_key = rt.alloc_store(Box::new(derived));
+
+    // Update before subscribing: the subscription is enqueued as the store changed in this generation
+    rt.update_value(derived_key, &mut |v| {
+        *v.downcast_mut::<i32>().unwrap() += 1;
+        true
+    });
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(derived_key, cb);
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn update_value_panics_on_store_without_support() {
+    struct NoUpdateStore {
+        value: Box<dyn std::any::Any>,
+    }
+
+    impl Store for NoUpdateStore {
+        fn get_any(&self) -> &dyn std::any::Any { &*self.value }
+        fn set_any(&mut self, _: Box<dyn std::any::Any>, _: &mut fluxum::store::SubSink) {}
+        fn subscribe(&mut self, _: SubscriptionKey, _: u64) -> bool { false }
+        fn unsubscribe(&mut self, _: SubscriptionKey) {}
+        fn subscriptions(&self) -> Option<&[SubscriptionKey]> { None }
+        fn dependencies(&self) -> Option<&[SubscriptionKey]> { None }
+    }
+
+    let mut rt = StoreRuntimeImpl::new();
+    let key = rt.alloc_store(Box::new(NoUpdateStore { value: Box::new(0i32) }));
+    let result = with_quiet_panic(|| {
+        rt.update_value(key, &mut |_| true);
+    });
+    assert!(result.is_err());
+}
+
+#[derive(Clone, PartialEq)]
+struct Person {
+    name: String,
+    age: i32,
+}
+
+fn person_name_get(parent: &dyn std::any::Any) -> Box<dyn std::any::Any> {
+    Box::new(parent.downcast_ref::<Person>().unwrap().name.clone())
+}
+
+fn person_name_set(parent: &mut dyn std::any::Any, value: Box<dyn std::any::Any>) {
+    parent.downcast_mut::<Person>().unwrap().name = *value.downcast::<String>().unwrap();
+}
+
+fn person_store(rt: &mut StoreRuntimeImpl) -> StoreKey {
+    let person = Person { name: String::from("Ann"), age: 30 };
+    rt.alloc_store(Box::new(EmittingStore::new(Box::new(person), Some(mk_eq_fn::<Person>()))))
+}
+
+#[test]
+fn lens_reads_field_and_notifies_only_on_field_change() {
+    let mut rt = StoreRuntimeImpl::new();
+    let parent = person_store(&mut rt);
+
+    let lens = LensStore::alloc(parent, person_name_get, person_name_set, Some(mk_eq_fn::<String>()), &mut rt);
+    assert_eq!(rt.get_value(lens).downcast_ref::<String>().unwrap(), "Ann");
+
+    let (counter, cb) = rc_counter();
+    let _sub = rt.subscribe(lens, cb);
+
+    // Change another field of the parent → no lens notification
+    rt.set_value(parent, Box::new(Person { name: String::from("Ann"), age: 31 }));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 0);
+
+    // Change the projected field → lens notification
+    rt.set_value(parent, Box::new(Person { name: String::from("Bob"), age: 31 }));
+    rt.drain_notifications();
+    assert_eq!(*counter.borrow(), 1);
+    assert_eq!(rt.get_value(lens).downcast_ref::<String>().unwrap(), "Bob");
+}
+
+#[test]
+fn lens_write_updates_parent_field() {
+    let mut rt = StoreRuntimeImpl::new();
+    let parent = person_store(&mut rt);
+
+    let lens = LensStore::alloc(parent, person_name_get, person_name_set, Some(mk_eq_fn::<String>()), &mut rt);
+
+    let (parent_counter, parent_cb) = rc_counter();
+    let (lens_counter, lens_cb) = rc_counter();
+    let _ = rt.subscribe(parent, parent_cb);
+    let _ = rt.subscribe(lens, lens_cb);
+
+    rt.set_value(lens, Box::new(String::from("Cecil")));
+    rt.drain_notifications();
+
+    let person = rt.get_value(parent).downcast_ref::<Person>().unwrap();
+    assert_eq!(person.name, "Cecil");
+    assert_eq!(person.age, 30);
+    assert_eq!(rt.get_value(lens).downcast_ref::<String>().unwrap(), "Cecil");
+    assert_eq!(*parent_counter.borrow(), 1);
+    assert_eq!(*lens_counter.borrow(), 1);
+
+    // Writing the current field value is not forwarded
+    rt.set_value(lens, Box::new(String::from("Cecil")));
+    rt.drain_notifications();
+    assert_eq!(*parent_counter.borrow(), 1);
+    assert_eq!(*lens_counter.borrow(), 1);
+}
+
+#[test]
+fn freeing_lens_cleans_parent_subscription() {
+    let mut rt = StoreRuntimeImpl::new();
+    let parent = person_store(&mut rt);
+
+    let lens = LensStore::alloc(parent, person_name_get, person_name_set, Some(mk_eq_fn::<String>()), &mut rt);
+    rt.free_store(lens);
+
+    // The lens callback would panic on the freed lens key if it was still subscribed
+    rt.set_value(parent, Box::new(Person { name: String::from("Dan"), age: 1 }));
+    rt.drain_notifications();
+    assert_eq!(rt.get_value(parent).downcast_ref::<Person>().unwrap().name, "Dan");
+}
+
+// Callback that appends `name` to the shared log
+fn logger(log: &Rc<RefCell<Vec<&'static str>>>, name: &'static str) -> StoreCallback {
+    let log = log.clone();
+    Rc::new(move |_store, _sub, _rt: &mut StoreEffects| log.borrow_mut().push(name))
+}
+
+#[test]
+fn sync_lane_cascades_before_render_lane() {
+    let mut rt = StoreRuntimeImpl::new();
+    let a = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let caret = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    // render subscriber of `a` (a heavy recompute), sync subscriber of `a` moves the caret
+    let _ = rt.subscribe_in(a, Lane::Render, logger(&log, "render a"));
+    let log_for_sync = log.clone();
+    let move_caret: StoreCallback = Rc::new(move |_store, _sub, rt: &mut StoreEffects| {
+        log_for_sync.borrow_mut().push("sync a");
+        rt.set_value(caret, Box::new(1i32));
+    });
+    let _ = rt.subscribe(a, move_caret);
+    let _ = rt.subscribe_in(caret, Lane::Sync, logger(&log, "sync caret"));
+
+    rt.set_value(a, Box::new(1i32));
+    rt.drain_notifications();
+
+    assert_eq!(*log.borrow(), vec!["sync a", "sync caret", "render a"]);
+}
+
+#[test]
+fn idle_lane_runs_only_in_drain_idle_within_budget() {
+    let clock = ManualClock::new();
+    let mut rt = StoreRuntimeImpl::with_clock(Box::new(clock.clone()));
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut keys = Vec::new();
+
+    // each idle callback takes 10ms
+    for name in ["idle 1", "idle 2", "idle 3"] {
+        let key = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+        let log_cb = log.clone();
+        let clock_cb = clock.clone();
+        let cb: StoreCallback = Rc::new(move |_store, _sub, _rt: &mut StoreEffects| {
+            log_cb.borrow_mut().push(name);
+            clock_cb.advance(Duration::from_millis(10));
+        });
+        let _ = rt.subscribe_in(key, Lane::Idle, cb);
+        keys.push(key);
+    }
+
+    for key in &keys {
+        rt.set_value(*key, Box::new(1i32));
+    }
+
+    rt.drain_notifications();
+    assert!(log.borrow().is_empty());
+
+    assert!(rt.drain_idle(Duration::from_millis(15)));
+    assert_eq!(*log.borrow(), vec!["idle 1", "idle 2"]);
+    assert!(rt.has_idle_work());
+
+    assert!(!rt.drain_idle(Duration::from_millis(15)));
+    assert_eq!(*log.borrow(), vec!["idle 1", "idle 2", "idle 3"]);
+    assert!(!rt.has_idle_work());
+}
+
+#[test]
+fn idle_notifications_are_deduplicated_and_cascade_into_sync() {
+    let mut rt = StoreRuntimeImpl::new();
+    let a = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let b = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let log_for_idle = log.clone();
+    let idle_cb: StoreCallback = Rc::new(move |_store, _sub, rt: &mut StoreEffects| {
+        log_for_idle.borrow_mut().push("idle a");
+        rt.set_value(b, Box::new(1i32));
+    });
+    let _ = rt.subscribe_in(a, Lane::Idle, idle_cb);
+    let _ = rt.subscribe(b, logger(&log, "sync b"));
+
+    // changes in several frames before the idle work gets time
+    rt.set_value(a, Box::new(1i32));
+    rt.drain_notifications();
+    rt.set_value(a, Box::new(2i32));
+    rt.drain_notifications();
+
+    assert!(!rt.drain_idle(Duration::from_secs(1)));
+    assert_eq!(*log.borrow(), vec!["idle a", "sync b"]);
+}
+
+#[test]
+fn pending_sync_work_runs_before_idle_work() {
+    let mut rt = StoreRuntimeImpl::new();
+    let a = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let b = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let _ = rt.subscribe_in(a, Lane::Idle, logger(&log, "idle a"));
+    let _ = rt.subscribe(b, logger(&log, "sync b"));
+    let _ = rt.subscribe_in(b, Lane::Render, logger(&log, "render b"));
+
+    // both changed, nothing drained yet
+    rt.set_value(a, Box::new(1i32));
+    rt.set_value(b, Box::new(1i32));
+
+    assert!(!rt.drain_idle(Duration::from_secs(1)));
+    assert_eq!(*log.borrow(), vec!["sync b", "render b", "idle a"]);
+}
+
+#[test]
+fn deterministic_mode_orders_batch_by_subscription_creation() {
+    let mut rt = StoreRuntimeImpl::new();
+    rt.set_deterministic(true);
+
+    let a = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    let b = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    let _ = rt.subscribe(b, logger(&log, "b1"));
+    let _ = rt.subscribe(a, logger(&log, "a1"));
+    let a2 = rt.subscribe(a, logger(&log, "a2"));
+    let _ = rt.subscribe(a, logger(&log, "a3"));
+    let _ = rt.subscribe(a, logger(&log, "a4"));
+    let _ = rt.subscribe(b, logger(&log, "b2"));
+
+    // swap_remove in the store moves a4 into the place of a2
+    rt.unsubscribe(a2);
+
+    rt.set_value(a, Box::new(1i32));
+    rt.set_value(b, Box::new(1i32));
+    rt.drain_notifications();
+
+    assert_eq!(*log.borrow(), vec!["b1", "a1", "a3", "a4", "b2"]);
+}
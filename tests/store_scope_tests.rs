@@ -0,0 +1,185 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use fluxum::store::{
+    EmittingStore, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl, mk_eq_fn,
+};
+
+fn emitting(rt: &mut StoreEffects) -> StoreKey {
+    rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))))
+}
+
+fn noop() -> StoreCallback {
+    Rc::new(|_, _, _: &mut StoreEffects| {})
+}
+
+#[test]
+fn free_scope_frees_everything_allocated_in_it() {
+    let mut rt = StoreRuntimeImpl::new();
+    let root_store = emitting(&mut rt);
+
+    let scope = rt.create_scope(100);
+    rt.set_scope(Some(scope));
+    let a = emitting(&mut rt);
+    let _ = rt.subscribe(a, noop());
+    let _ = rt.subscribe(root_store, noop()); // scope subscription to a root store
+
+    let child = rt.create_scope(100);
+    rt.set_scope(Some(child));
+    let b = emitting(&mut rt);
+    let _ = rt.subscribe(b, noop());
+    rt.set_scope(None);
+
+    let stats = rt.scope_stats(scope).unwrap();
+    assert_eq!((stats.stores, stats.subscriptions), (1, 2));
+    assert_eq!(rt.store_count(), 3);
+    assert_eq!(rt.subscription_count(), 3);
+
+    rt.free_scope(scope);
+
+    assert_eq!(rt.store_count(), 1);
+    assert_eq!(rt.subscription_count(), 0);
+    assert_eq!(rt.scope_count(), 0);
+    assert!(rt.scope_stats(child).is_none());
+
+    // the root store still works
+    rt.set_value(root_store, Box::new(1));
+    rt.drain_notifications();
+}
+
+#[test]
+fn freeing_scope_members_individually_keeps_stats_exact() {
+    let mut rt = StoreRuntimeImpl::new();
+    let scope = rt.create_scope(100);
+    rt.set_scope(Some(scope));
+    let a = emitting(&mut rt);
+    let b = emitting(&mut rt);
+    let sub = rt.subscribe(a, noop());
+    let _ = rt.subscribe(b, noop());
+    rt.set_scope(None);
+
+    rt.unsubscribe(sub);
+    rt.free_store(b);
+
+    let stats = rt.scope_stats(scope).unwrap();
+    assert_eq!((stats.stores, stats.subscriptions), (1, 0));
+}
+
+#[test]
+fn callbacks_run_in_the_scope_of_their_subscription() {
+    let mut rt = StoreRuntimeImpl::new();
+    let source = emitting(&mut rt);
+
+    let scope = rt.create_scope(100);
+    rt.set_scope(Some(scope));
+    let cb: StoreCallback = Rc::new(|_, _, rt: &mut StoreEffects| {
+        // allocated while the callback runs, so it belongs to the scope
+        emitting(rt);
+    });
+    let _ = rt.subscribe(source, cb);
+    rt.set_scope(None);
+
+    rt.set_value(source, Box::new(1));
+    rt.drain_notifications();
+
+    assert_eq!(rt.scope_stats(scope).unwrap().stores, 1);
+    assert_eq!(rt.scope_stats(scope).unwrap().notifications, 1);
+
+    // the current scope is restored after the callback
+    emitting(&mut rt);
+    assert_eq!(rt.scope_stats(scope).unwrap().stores, 1);
+
+    rt.free_scope(scope);
+    assert_eq!(rt.store_count(), 2);
+    assert_eq!(rt.subscription_count(), 0);
+}
+
+// A store that triggers itself forever
+fn ping_pong(rt: &mut StoreEffects, calls: Rc<Cell<u32>>) -> StoreKey {
+    let key = emitting(rt);
+    let cb: StoreCallback = Rc::new(move |store, _sub, rt: &mut StoreEffects| {
+        calls.set(calls.get() + 1);
+        let value = *rt.get_value(store).downcast_ref::<i32>().unwrap();
+        rt.set_value(store, Box::new(value + 1));
+    });
+    let _ = rt.subscribe(key, cb);
+    key
+}
+
+#[test]
+fn scope_generation_limit_cuts_off_only_the_scope() {
+    let mut rt = StoreRuntimeImpl::new();
+
+    let scope = rt.create_scope(10);
+    rt.set_scope(Some(scope));
+    let calls = Rc::new(Cell::new(0));
+    let looping = ping_pong(&mut rt, calls.clone());
+    rt.set_scope(None);
+
+    let root = emitting(&mut rt);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let seen_cb = seen.clone();
+    let cb: StoreCallback = Rc::new(move |store, _sub, rt: &mut StoreEffects| {
+        seen_cb.borrow_mut().push(*rt.get_value(store).downcast_ref::<i32>().unwrap());
+    });
+    let _ = rt.subscribe(root, cb);
+
+    rt.set_value(looping, Box::new(1));
+    rt.set_value(root, Box::new(1));
+    rt.drain_notifications();
+
+    assert_eq!(calls.get(), 10);
+    assert_eq!(*seen.borrow(), vec![1]);
+
+    let stats = rt.scope_stats(scope).unwrap();
+    assert_eq!(stats.notifications, 10);
+    assert_eq!(stats.dropped, 1);
+    assert_eq!(stats.limit_exceeded, 1);
+
+    // the limit is per drain: the next drain runs the scope again
+    rt.set_value(looping, Box::new(-1));
+    rt.drain_notifications();
+    assert_eq!(calls.get(), 20);
+    assert_eq!(rt.scope_stats(scope).unwrap().limit_exceeded, 2);
+}
+
+#[test]
+#[should_panic(expected = "generation limit")]
+fn root_cycle_still_hits_the_global_limit() {
+    let mut rt = StoreRuntimeImpl::new();
+    let key = ping_pong(&mut rt, Rc::new(Cell::new(0)));
+    rt.set_value(key, Box::new(1));
+    rt.drain_notifications();
+}
+
+#[test]
+#[should_panic(expected = "hard drain generation limit")]
+fn scope_cycle_hits_the_hard_limit() {
+    let mut rt = StoreRuntimeImpl::new();
+    let scope = rt.create_scope(u64::MAX);
+    rt.set_scope(Some(scope));
+    let key = ping_pong(&mut rt, Rc::new(Cell::new(0)));
+    rt.set_scope(None);
+
+    rt.set_value(key, Box::new(1));
+    rt.drain_notifications();
+}
+
+#[test]
+#[should_panic(expected = "freed scope")]
+fn allocating_in_a_freed_scope_panics() {
+    let mut rt = StoreRuntimeImpl::new();
+    let source = emitting(&mut rt);
+
+    let scope = rt.create_scope(100);
+    rt.set_scope(Some(scope));
+    let cb: StoreCallback = Rc::new(move |_, _, rt: &mut StoreEffects| {
+        rt.free_scope(scope);
+        emitting(rt);
+    });
+    let _ = rt.subscribe(source, cb);
+    rt.set_scope(None);
+
+    rt.set_value(source, Box::new(1));
+    rt.drain_notifications();
+}
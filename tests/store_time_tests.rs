@@ -0,0 +1,188 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use fluxum::store::time::{ManualClock, debounce, delay, mk_clone_fn, sample, throttle};
+use fluxum::store::{
+    EmittingStore, StoreCallback, StoreEffects, StoreKey, StoreRuntime, StoreRuntimeImpl, mk_eq_fn,
+};
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn runtime() -> (StoreRuntimeImpl, ManualClock, StoreKey) {
+    let clock = ManualClock::new();
+    let mut rt = StoreRuntimeImpl::with_clock(Box::new(clock.clone()));
+    let source = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+    (rt, clock, source)
+}
+
+// Records the values of the store at each notification
+fn recorder(rt: &mut StoreRuntimeImpl, key: StoreKey) -> Rc<RefCell<Vec<i32>>> {
+    let values = Rc::new(RefCell::new(Vec::new()));
+    let values_cb = values.clone();
+    let cb: StoreCallback = Rc::new(move |store, _sub, rt: &mut StoreEffects| {
+        values_cb.borrow_mut().push(*rt.get_value(store).downcast_ref::<i32>().unwrap());
+    });
+    let _ = rt.subscribe(key, cb);
+    values
+}
+
+// One adapter frame: set the source (if any), run the due timers and drain
+fn frame(rt: &mut StoreRuntimeImpl, source: StoreKey, value: Option<i32>) {
+    if let Some(value) = value {
+        rt.set_value(source, Box::new(value));
+    }
+    rt.drain_notifications();
+    rt.run_timers();
+    rt.drain_notifications();
+}
+
+#[test]
+fn debounce_emits_after_quiet_period() {
+    let (mut rt, clock, source) = runtime();
+    let out = debounce(source, ms(100), mk_clone_fn::<i32>(), Some(mk_eq_fn::<i32>()), &mut rt);
+    let values = recorder(&mut rt, out);
+
+    frame(&mut rt, source, Some(1));
+    assert_eq!(rt.next_deadline(), Some(ms(100)));
+
+    clock.advance(ms(50));
+    frame(&mut rt, source, Some(2));
+    assert_eq!(rt.next_deadline(), Some(ms(150)));
+
+    clock.advance(ms(99));
+    frame(&mut rt, source, None);
+    assert!(values.borrow().is_empty());
+
+    clock.advance(ms(1));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![2]);
+    assert_eq!(rt.next_deadline(), None);
+}
+
+#[test]
+fn throttle_emits_leading_and_trailing() {
+    let (mut rt, clock, source) = runtime();
+    let out = throttle(source, ms(100), mk_clone_fn::<i32>(), Some(mk_eq_fn::<i32>()), &mut rt);
+    let values = recorder(&mut rt, out);
+
+    // leading edge
+    frame(&mut rt, source, Some(1));
+    assert_eq!(*values.borrow(), vec![1]);
+
+    // within the period: collapsed into one trailing emission
+    clock.advance(ms(10));
+    frame(&mut rt, source, Some(2));
+    clock.advance(ms(10));
+    frame(&mut rt, source, Some(3));
+    assert_eq!(*values.borrow(), vec![1]);
+    assert_eq!(rt.next_deadline(), Some(ms(100)));
+
+    clock.advance(ms(80));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![1, 3]);
+
+    // a change well after the trailing emission is a new leading edge
+    clock.advance(ms(200));
+    frame(&mut rt, source, Some(4));
+    assert_eq!(*values.borrow(), vec![1, 3, 4]);
+}
+
+#[test]
+fn sample_emits_changed_values_at_period() {
+    let (mut rt, clock, source) = runtime();
+    let out = sample(source, ms(100), mk_clone_fn::<i32>(), Some(mk_eq_fn::<i32>()), &mut rt);
+    let values = recorder(&mut rt, out);
+
+    frame(&mut rt, source, Some(1));
+    frame(&mut rt, source, Some(2));
+    assert!(values.borrow().is_empty());
+
+    clock.advance(ms(100));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![2]);
+
+    // no change in the next period: no emission, the sampler keeps ticking
+    clock.advance(ms(100));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![2]);
+    assert_eq!(rt.next_deadline(), Some(ms(300)));
+
+    // late timer run: the next deadline stays on the period grid
+    frame(&mut rt, source, Some(3));
+    clock.advance(ms(150));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![2, 3]);
+    assert_eq!(rt.next_deadline(), Some(ms(400)));
+}
+
+#[test]
+fn delay_emits_every_value_later() {
+    let (mut rt, clock, source) = runtime();
+    let out = delay(source, ms(100), mk_clone_fn::<i32>(), Some(mk_eq_fn::<i32>()), &mut rt);
+    let values = recorder(&mut rt, out);
+
+    frame(&mut rt, source, Some(1));
+    clock.advance(ms(30));
+    frame(&mut rt, source, Some(2));
+    assert_eq!(*rt.get_value(out).downcast_ref::<i32>().unwrap(), 0);
+
+    clock.advance(ms(70));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![1]);
+
+    clock.advance(ms(30));
+    frame(&mut rt, source, None);
+    assert_eq!(*values.borrow(), vec![1, 2]);
+}
+
+#[test]
+fn freeing_time_based_store_cancels_timers() {
+    let (mut rt, clock, source) = runtime();
+    let out = debounce(source, ms(100), mk_clone_fn::<i32>(), Some(mk_eq_fn::<i32>()), &mut rt);
+
+    frame(&mut rt, source, Some(1));
+    assert!(rt.next_deadline().is_some());
+
+    rt.free_store(out);
+    assert_eq!(rt.next_deadline(), None);
+
+    // the source change must not reach the freed store
+    clock.advance(ms(100));
+    frame(&mut rt, source, Some(2));
+    assert_eq!(rt.next_deadline(), None);
+}
+
+#[test]
+fn freeing_a_store_removes_only_its_own_timers() {
+    let (mut rt, clock, source) = runtime();
+    let other = rt.alloc_store(Box::new(EmittingStore::new(Box::new(0i32), Some(mk_eq_fn::<i32>()))));
+
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let timer = |name: &'static str| -> fluxum::store::TimerCallback {
+        let fired = fired.clone();
+        Rc::new(move |_, _: &mut StoreEffects| fired.borrow_mut().push(name))
+    };
+
+    let cancelled = rt.schedule(source, ms(10), timer("cancelled"));
+    rt.schedule(source, ms(10), timer("source"));
+    rt.schedule(source, ms(50), timer("freed"));
+    rt.schedule(other, ms(20), timer("other"));
+    assert!(rt.cancel(cancelled));
+    assert_eq!(rt.timer_count(), 3);
+
+    clock.advance(ms(10));
+    rt.run_timers();
+    assert_eq!(*fired.borrow(), vec!["source"]);
+
+    rt.free_store(source);
+    assert_eq!(rt.timer_count(), 1);
+    assert!(!rt.cancel(cancelled));
+
+    clock.advance(ms(50));
+    rt.run_timers();
+    assert_eq!(*fired.borrow(), vec!["source", "other"]);
+    assert_eq!(rt.timer_count(), 0);
+}